@@ -5,7 +5,7 @@ mod life;
 
 use {
     launchpad_pro_rs::{
-        hal::{self, LaunchpadApp},
+        hal::{self, gfx::FrameBuffer, LaunchpadApp},
         launchpad_app,
     },
     life::Life,
@@ -16,6 +16,9 @@ pub struct App {
     is_running: bool,
     /// Our Game of Life state.
     life: Life,
+    /// The frame the universe and setup button are drawn into, so only the cells that change are
+    /// sent to the LEDs.
+    frame: FrameBuffer,
 }
 
 impl Default for App {
@@ -23,15 +26,16 @@ impl Default for App {
         Self {
             is_running: false,
             life: Life::new(),
+            frame: FrameBuffer::new(),
         }
     }
 }
 
 impl App {
     /// Draw the Game of Life universe on the Launchpad Pro grid.
-    fn draw_universe(&self) {
+    fn draw_universe(&mut self) {
         for point in hal::Grid::points() {
-            hal::surface::set_led(
+            self.frame.set(
                 point,
                 match self.life.get(point) {
                     life::Cell::Alive => hal::Rgb::GREEN,
@@ -39,6 +43,20 @@ impl App {
                 },
             );
         }
+        self.frame.flush();
+    }
+
+    /// Light the setup button while the simulation is running.
+    fn draw_setup_button(&mut self) {
+        self.frame.set(
+            hal::surface::Led::Setup,
+            if self.is_running() {
+                hal::Rgb::GREEN
+            } else {
+                hal::Rgb::BLACK
+            },
+        );
+        self.frame.flush();
     }
 
     /// Move the simulation forward by one tick.
@@ -83,6 +101,7 @@ impl LaunchpadApp for App {
                 }
                 hal::surface::Button::Setup => {
                     self.toggle_is_running();
+                    self.draw_setup_button();
                 }
             }
         }
@@ -102,7 +121,7 @@ mod tests {
         let mut app = App::default();
 
         // we expect that our newly created app will start paused
-        assert_eq!(app.is_running, false);
+        assert!(!app.is_running);
 
         // create a single cell that will immediately die once the simulation starts
         app.button_event(hal::surface::ButtonEvent {
@@ -126,7 +145,7 @@ mod tests {
         });
 
         // check that our button press was registered
-        assert_eq!(app.is_running, true);
+        assert!(app.is_running);
 
         // progress the simulation
         app.timer_event();
@@ -134,4 +153,36 @@ mod tests {
         // now that the simulation as started we expect that our solitary cell has died
         assert_eq!(app.life.get(hal::Point::new(5, 5)), life::Cell::Dead);
     }
+
+    /// Tap a button on the simulated hardware.
+    fn tap(simulator: &mut hal::sim::Simulator<App>, button: hal::surface::Button) {
+        simulator.press(button, 127);
+        simulator.release(button);
+    }
+
+    #[test]
+    fn glider_moves_across_the_grid() {
+        let snapshots = concat!(env!("CARGO_MANIFEST_DIR"), "/examples/life/snapshots");
+        let mut simulator = hal::sim::Simulator::new(App::default(), 100);
+
+        // draw a glider heading towards the bottom right of the grid
+        for &(x, y) in &[(1, 8), (2, 7), (0, 6), (1, 6), (2, 6)] {
+            tap(
+                &mut simulator,
+                hal::surface::Button::Pad(hal::Point::new(x, y)),
+            );
+        }
+        hal::snapshot::assert_matches(
+            &hal::sim::frame(),
+            format!("{}/glider-start.txt", snapshots),
+        );
+
+        // after four generations the glider has moved one cell diagonally
+        tap(&mut simulator, hal::surface::Button::Setup);
+        simulator.advance(400);
+        hal::snapshot::assert_matches(
+            &hal::sim::frame(),
+            format!("{}/glider-moved.txt", snapshots),
+        );
+    }
 }
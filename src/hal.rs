@@ -1,4 +1,4 @@
-use core::ops::Add;
+use core::{convert::TryFrom, ops::Add};
 
 #[doc(hidden)]
 pub use spin::Mutex;
@@ -9,32 +9,30 @@ extern "C" {
     fn hal_read_led(t: u8, index: u8, red: *mut u8, green: *mut u8, blue: *mut u8);
     fn hal_send_midi(port: u8, status: u8, data1: u8, data2: u8);
     fn hal_send_sysex(port: u8, data: *const u8, length: u16);
+    fn hal_read_flash(offset: u32, data: *mut u8, length: u32);
+    fn hal_write_flash(offset: u32, data: *const u8, length: u32);
+    fn hal_read_device_id() -> u8;
+    fn hal_read_layout_text() -> u8;
 }
 
 #[cfg(not(target_arch = "arm"))]
-unsafe fn hal_plot_led(t: u8, index: u8, red: u8, green: u8, blue: u8) {
-    println!(
-        "plot_led, type: {}, index: {}, color: ({}, {}, {})",
-        t, index, red, green, blue
-    );
-}
+use sim::{
+    advance_clock, hal_plot_led, hal_read_device_id, hal_read_flash, hal_read_layout_text,
+    hal_read_led, hal_send_midi, hal_send_sysex, hal_write_flash, read_clock,
+};
 
-#[cfg(not(target_arch = "arm"))]
-unsafe fn hal_read_led(t: u8, index: u8, _red: *mut u8, _green: *mut u8, _blue: *mut u8) {
-    println!("read_led, type: {}, index: {}", t, index);
-}
+/// The number of milliseconds that have elapsed since the app was initialised.
+#[cfg(target_arch = "arm")]
+static CLOCK: core::sync::atomic::AtomicU32 = core::sync::atomic::AtomicU32::new(0);
 
-#[cfg(not(target_arch = "arm"))]
-unsafe fn hal_send_midi(port: u8, status: u8, data1: u8, data2: u8) {
-    println!(
-        "send_midi, port: {}, status: {}, data: ({}, {})",
-        port, status, data1, data2
-    );
+#[cfg(target_arch = "arm")]
+fn read_clock() -> u32 {
+    CLOCK.load(core::sync::atomic::Ordering::Relaxed)
 }
 
-#[cfg(not(target_arch = "arm"))]
-unsafe fn hal_send_sysex(port: u8, _data: *const u8, length: u16) {
-    println!("send_sysex, port: {}, length: {}", port, length);
+#[cfg(target_arch = "arm")]
+fn advance_clock() {
+    CLOCK.fetch_add(1, core::sync::atomic::Ordering::Relaxed);
 }
 
 /// The Launchpad Pro grid.
@@ -87,6 +85,11 @@ impl Red {
     pub const fn new(red: u8) -> Self {
         Self(convert_to_6_bit(red))
     }
+
+    /// Returns the 6-bit level sent to the LED, in the range `[0, 63]`.
+    pub const fn value(&self) -> u8 {
+        self.0
+    }
 }
 
 /// The green component of an RGB color.
@@ -97,6 +100,11 @@ impl Green {
     pub const fn new(green: u8) -> Self {
         Self(convert_to_6_bit(green))
     }
+
+    /// Returns the 6-bit level sent to the LED, in the range `[0, 63]`.
+    pub const fn value(&self) -> u8 {
+        self.0
+    }
 }
 
 /// The blue component of an RGB color.
@@ -107,6 +115,11 @@ impl Blue {
     pub const fn new(blue: u8) -> Self {
         Self(convert_to_6_bit(blue))
     }
+
+    /// Returns the 6-bit level sent to the LED, in the range `[0, 63]`.
+    pub const fn value(&self) -> u8 {
+        self.0
+    }
 }
 
 /// Map an 8-bit value to a 6-bit range.
@@ -131,6 +144,44 @@ impl Rgb {
         Rgb(red, green, blue)
     }
 
+    /// Construct a new RGB color from the 6-bit levels sent to the LED, in the range `[0, 63]`.
+    /// Larger levels are clamped to 63.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use launchpad_pro_rs::hal::Rgb;
+    ///
+    /// let orange = Rgb::from_6_bit(63, 20, 0);
+    /// assert_eq!(orange.green().value(), 20);
+    /// assert_eq!(Rgb::from_6_bit(255, 255, 255), Rgb::WHITE);
+    /// ```
+    pub const fn from_6_bit(red: u8, green: u8, blue: u8) -> Self {
+        const fn clamp(level: u8) -> u8 {
+            if level > 63 {
+                63
+            } else {
+                level
+            }
+        }
+        Rgb(Red(clamp(red)), Green(clamp(green)), Blue(clamp(blue)))
+    }
+
+    /// Returns the red component.
+    pub const fn red(&self) -> Red {
+        self.0
+    }
+
+    /// Returns the green component.
+    pub const fn green(&self) -> Green {
+        self.1
+    }
+
+    /// Returns the blue component.
+    pub const fn blue(&self) -> Blue {
+        self.2
+    }
+
     pub const RED: Self = Self(Red::new(255), Green::new(0), Blue::new(0));
     pub const GREEN: Self = Self(Red::new(0), Green::new(255), Blue::new(0));
     pub const BLUE: Self = Self(Red::new(0), Green::new(0), Blue::new(255));
@@ -141,6 +192,14 @@ impl Rgb {
     pub const BLACK: Self = Self(Red::new(0), Green::new(0), Blue::new(0));
 }
 
+mod colour;
+mod palette;
+
+pub use {
+    colour::{Hsl, Hsv},
+    palette::Palette,
+};
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 /// A point on the Launchpad Pro grid.
 pub struct Point {
@@ -215,42 +274,73 @@ impl Point {
 pub mod surface {
     use crate::hal::{Blue, Green, Point, Red, Rgb};
 
-    /// Set the colour of an LED on the grid.
+    /// The LEDs on the surface of the Launchpad Pro.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum Led {
+        /// The LED of a pad or button on the grid.
+        Pad(Point),
+        /// The LED of the setup button.
+        Setup,
+    }
+
+    impl Led {
+        /// The LED type and index used to address this LED in the low level firmware. If the LED
+        /// does not exist then this function will return None.
+        const fn address(&self) -> Option<(u8, u8)> {
+            match self {
+                Led::Pad(point) if point.to_index() < super::Grid::size() => {
+                    Some((0, point.to_index()))
+                }
+                Led::Pad(_) => None,
+                Led::Setup => Some((1, 0)),
+            }
+        }
+    }
+
+    impl From<Point> for Led {
+        fn from(point: Point) -> Self {
+            Led::Pad(point)
+        }
+    }
+
+    /// Set the colour of an LED on the grid, or of the setup button.
     ///
     /// # Example
     ///
     /// ```
     ///
-    /// use launchpad_pro_rs::hal::surface::set_led;
+    /// use launchpad_pro_rs::hal::surface::{set_led, Led};
     /// use launchpad_pro_rs::hal::{Point, Rgb, Red, Green, Blue};
     ///
     /// set_led(Point::new(5, 5), Rgb::new(Red::new(255), Green::new(127), Blue::new(0)));
+    /// set_led(Led::Setup, Rgb::BLUE);
     /// ```
-    pub fn set_led(point: Point, Rgb(Red(red), Green(green), Blue(blue)): Rgb) {
-        if point.to_index() < super::Grid::size() {
+    pub fn set_led(led: impl Into<Led>, Rgb(Red(red), Green(green), Blue(blue)): Rgb) {
+        if let Some((t, index)) = led.into().address() {
             unsafe {
-                super::hal_plot_led(0, point.to_index(), red, green, blue);
+                super::hal_plot_led(t, index, red, green, blue);
             };
         }
     }
 
-    /// Read the color of an LED on the grid.
+    /// Read the color of an LED on the grid, or of the setup button.
     ///
     /// # Example
     ///
     /// ```
-    /// use launchpad_pro_rs::hal::surface::read_led;
+    /// use launchpad_pro_rs::hal::surface::{read_led, Led};
     /// use launchpad_pro_rs::hal::Point;
     ///
     /// let color = read_led(Point::new(0, 0));
+    /// let setup_color = read_led(Led::Setup);
     /// ```
-    pub fn read_led(point: Point) -> Option<Rgb> {
-        if point.to_index() < super::Grid::size() {
+    pub fn read_led(led: impl Into<Led>) -> Option<Rgb> {
+        if let Some((t, index)) = led.into().address() {
             let mut red = 0;
             let mut green = 0;
             let mut blue = 0;
             unsafe {
-                super::hal_read_led(0, point.to_index(), &mut red, &mut green, &mut blue);
+                super::hal_read_led(t, index, &mut red, &mut green, &mut blue);
             };
             return Some(Rgb(Red(red), Green(green), Blue(blue)));
         }
@@ -258,6 +348,7 @@ pub mod surface {
     }
 
     /// The types of button on the surface of the Launchpad Pro.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
     pub enum Button {
         /// A pad button.
         Pad(Point),
@@ -265,7 +356,17 @@ pub mod surface {
         Setup,
     }
 
+    impl From<Button> for Led {
+        fn from(button: Button) -> Self {
+            match button {
+                Button::Pad(point) => Led::Pad(point),
+                Button::Setup => Led::Setup,
+            }
+        }
+    }
+
     /// The types of event that can occur on a button.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
     pub enum Event {
         /// A button has been pressed. Contains the value of the button press.
         Press(u8),
@@ -274,6 +375,7 @@ pub mod surface {
     }
 
     /// Button events occur when a button is pressed or released on the Launchpad Pro.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
     pub struct ButtonEvent {
         /// The button that was pressed or released.
         pub button: Button,
@@ -282,6 +384,7 @@ pub mod surface {
     }
 
     /// Aftertouch events occur when an aftertouch (pad pressure) event is reported.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
     pub struct AftertouchEvent {
         pub point: Point,
         pub value: u8,
@@ -310,7 +413,7 @@ pub mod surface {
         /// This function converts points in the grid to offsets into this ADC pointer corresponding
         /// to that point. If there isn't a pad at the point provided then this function will return
         /// None.
-        const fn point_to_offset(pos: Point) -> Option<usize> {
+        pub(crate) const fn point_to_offset(pos: Point) -> Option<usize> {
             if pos.y >= 1 && pos.y <= 4 && pos.x >= 1 && pos.x <= 8 {
                 let y_offset = (pos.y - 1) * 16;
                 let x_offset = (pos.x - 1) * 2;
@@ -354,50 +457,76 @@ pub mod surface {
         assert_eq!(Pads::point_to_offset(Point::from_index(0)), None);
     }
 
+    #[test]
+    fn led_addressing() {
+        assert_eq!(Led::from(Point::new(0, 0)).address(), Some((0, 0)));
+        assert_eq!(Led::from(Point::new(5, 3)).address(), Some((0, 35)));
+        assert_eq!(Led::Pad(Point::new(9, 9)).address(), Some((0, 99)));
+        assert_eq!(Led::Setup.address(), Some((1, 0)));
+    }
+
     #[test]
     fn read_adc_value() {
         let mut values = [0u16; 64];
-        let pads = Pads::new(values.as_ptr());
+        let adc = values.as_mut_ptr();
+        let pads = Pads::new(adc);
 
         assert_eq!(pads.read(Point::new(0, 0)), None);
 
-        values[16] = 7;
+        unsafe { *adc.add(16) = 7 };
         assert_eq!(pads.read(Point::new(1, 2)), Some(7));
 
-        values[16] = 34;
+        unsafe { *adc.add(16) = 34 };
         assert_eq!(pads.read(Point::new(1, 2)), Some(34));
     }
 }
 
 /// Send and receive MIDI messages.
 pub mod midi {
+    mod message;
+    mod parser;
+
+    /// Follow the MIDI clock sent by another device, such as a DAW.
+    pub mod clock;
+
+    pub use {
+        message::{Channel, Error, Message, MidiMessage, U14, U7},
+        parser::{Parsed, Parser, DEFAULT_SYSEX_CAPACITY},
+    };
+
+    /// The SysEx manufacturer ID reserved for non-commercial use. It starts the SysEx messages
+    /// that carry log records, panic reports and recordings.
+    pub(crate) const NON_COMMERCIAL_ID: u8 = 0x7D;
+
     /// The MIDI ports available on the Launchpad Pro.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
     pub enum Port {
         Standalone = 0,
         USB = 1,
         DIN = 2,
     }
 
-    /// A MIDI message.
-    pub struct Message {
-        pub status: u8,
-        pub data: (u8, u8),
-    }
-
-    impl Message {
-        /// Construct a new MIDI message.
-        pub const fn new(status: u8, data: (u8, u8)) -> Self {
-            Self { status, data }
+    impl Port {
+        /// Convert a port number used by the low level firmware into a port.
+        pub(crate) const fn from_u8(port: u8) -> Option<Self> {
+            match port {
+                0 => Some(Port::Standalone),
+                1 => Some(Port::USB),
+                2 => Some(Port::DIN),
+                _ => None,
+            }
         }
     }
 
     /// The MIDI DIN socket types available.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
     pub enum Cable {
         MidiIn,
         MidiOut,
     }
 
     /// The events that can occur for the MIDI DIN sockets.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
     pub enum CableEvent {
         Connect(Cable),
         Disconnect(Cable),
@@ -410,14 +539,61 @@ pub mod midi {
     /// ```
     /// use launchpad_pro_rs::hal::midi::{send_message, Port, Message};
     ///
-    /// send_message(Port::DIN, Message::new(0x90, (60, 127)));
+    /// send_message(Port::DIN, Message::new(0x90, (60, 127)).unwrap());
     /// ```
     pub fn send_message(port: Port, message: Message) {
+        let (data1, data2) = message.data();
         unsafe {
-            super::hal_send_midi(port as u8, message.status, message.data.0, message.data.1);
+            super::hal_send_midi(port as u8, message.status(), data1.into(), data2.into());
         }
     }
 
+    /// Pack 8-bit data into 7-bit bytes so that it can be carried in a SysEx message. Each group
+    /// of up to seven bytes is preceded by a byte holding their most significant bits. Returns
+    /// the number of bytes written, or None if the output buffer is too small.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use launchpad_pro_rs::hal::midi::{pack_7bit, unpack_7bit};
+    ///
+    /// let mut packed = [0; 4];
+    /// assert_eq!(pack_7bit(&[0x01, 0xFF, 0x80], &mut packed), Some(4));
+    /// assert_eq!(packed, [0x06, 0x01, 0x7F, 0x00]);
+    ///
+    /// let mut unpacked = [0; 3];
+    /// assert_eq!(unpack_7bit(&packed, &mut unpacked), Some(3));
+    /// assert_eq!(unpacked, [0x01, 0xFF, 0x80]);
+    /// ```
+    pub fn pack_7bit(data: &[u8], output: &mut [u8]) -> Option<usize> {
+        let mut length = 0;
+        for group in data.chunks(7) {
+            let output = output.get_mut(length..length + group.len() + 1)?;
+            output[0] = 0;
+            for (i, &byte) in group.iter().enumerate() {
+                output[0] |= (byte >> 7) << i;
+                output[i + 1] = byte & 0x7F;
+            }
+            length += output.len();
+        }
+        Some(length)
+    }
+
+    /// Unpack 7-bit bytes produced by [`pack_7bit`] back into 8-bit data. Returns the number of
+    /// bytes written, or None if the output buffer is too small.
+    pub fn unpack_7bit(data: &[u8], output: &mut [u8]) -> Option<usize> {
+        let mut length = 0;
+        for group in data.chunks(8) {
+            let (msbs, group) = group.split_first()?;
+            let output = output.get_mut(length..length + group.len())?;
+            for (i, &byte) in group.iter().enumerate() {
+                output[i] = (byte & 0x7F) | (((msbs >> i) & 1) << 7);
+            }
+            length += output.len();
+        }
+        Some(length)
+    }
+
     /// Send a SysEx message to one of the ports on the device.
     /// The caller is responsible for ensuring that the message is correctly formatted:
     ///     - Starts with 0xF0 and ends with 0xF7.
@@ -440,14 +616,319 @@ pub mod midi {
     }
 }
 
+/// Persist data across power cycles in the user area of the Launchpad Pro's flash memory.
+///
+/// When running on the host the user area is simulated by an in-memory buffer. Each thread has its
+/// own buffer, so tests running in parallel will not interfere with one another.
+pub mod flash {
+    use core::fmt;
+
+    /// The size of the user area in bytes.
+    pub const SIZE: usize = 1024;
+
+    /// The errors that can occur when accessing the flash memory.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum Error {
+        /// The buffer is larger than the entire user area.
+        TooLarge,
+        /// The access would extend beyond the end of the user area.
+        OutOfRange,
+    }
+
+    impl fmt::Display for Error {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                Error::TooLarge => write!(f, "buffer exceeds the {} byte user area", SIZE),
+                Error::OutOfRange => write!(f, "access extends beyond the end of the user area"),
+            }
+        }
+    }
+
+    /// Check that `length` bytes starting at `offset` fit within the user area.
+    const fn check_bounds(offset: usize, length: usize) -> Result<(), Error> {
+        if length > SIZE {
+            Err(Error::TooLarge)
+        } else if offset > SIZE - length {
+            Err(Error::OutOfRange)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Read from the user area, starting at `offset` bytes from its beginning, filling the buffer.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use launchpad_pro_rs::hal::flash;
+    ///
+    /// let mut buffer = [0; 16];
+    /// flash::read(0, &mut buffer).unwrap();
+    ///
+    /// assert_eq!(flash::read(1020, &mut buffer), Err(flash::Error::OutOfRange));
+    /// ```
+    pub fn read(offset: usize, buffer: &mut [u8]) -> Result<(), Error> {
+        check_bounds(offset, buffer.len())?;
+        unsafe {
+            super::hal_read_flash(offset as u32, buffer.as_mut_ptr(), buffer.len() as u32);
+        }
+        Ok(())
+    }
+
+    /// Write the data to the user area, starting at `offset` bytes from its beginning.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use launchpad_pro_rs::hal::flash;
+    ///
+    /// flash::write(0, &[0xDE, 0xAD, 0xBE, 0xEF]).unwrap();
+    ///
+    /// assert_eq!(flash::write(0, &[0; 2048]), Err(flash::Error::TooLarge));
+    /// ```
+    pub fn write(offset: usize, data: &[u8]) -> Result<(), Error> {
+        check_bounds(offset, data.len())?;
+        unsafe {
+            super::hal_write_flash(offset as u32, data.as_ptr(), data.len() as u32);
+        }
+        Ok(())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn can_read_back_written_data() {
+            write(100, &[1, 2, 3, 4]).unwrap();
+
+            let mut buffer = [0; 4];
+            read(100, &mut buffer).unwrap();
+            assert_eq!(buffer, [1, 2, 3, 4]);
+        }
+
+        #[test]
+        fn can_access_the_entire_user_area() {
+            write(0, &[42; SIZE]).unwrap();
+
+            let mut buffer = [0; SIZE];
+            read(0, &mut buffer).unwrap();
+            assert_eq!(buffer, [42; SIZE]);
+
+            assert_eq!(write(SIZE, &[]), Ok(()));
+            assert_eq!(write(SIZE - 1, &[7]), Ok(()));
+        }
+
+        #[test]
+        fn accesses_beyond_the_user_area_are_rejected() {
+            assert_eq!(write(SIZE, &[0]), Err(Error::OutOfRange));
+            assert_eq!(write(SIZE - 3, &[0; 4]), Err(Error::OutOfRange));
+            assert_eq!(write(usize::MAX, &[0]), Err(Error::OutOfRange));
+            assert_eq!(read(SIZE + 1, &mut []), Err(Error::OutOfRange));
+        }
+
+        #[test]
+        fn oversized_buffers_are_rejected() {
+            assert_eq!(write(0, &[0; SIZE + 1]), Err(Error::TooLarge));
+            assert_eq!(read(0, &mut [0; SIZE + 1]), Err(Error::TooLarge));
+        }
+    }
+}
+
+/// Query the configuration of the Launchpad Pro unit.
+///
+/// When running on the host these values can be configured using [`device::set_id`] and
+/// [`device::set_layout_text`].
+pub mod device {
+    /// The manufacturer ID assigned to Novation (Focusrite).
+    const MANUFACTURER_ID: [u8; 3] = [0x00, 0x20, 0x29];
+
+    /// The device family code of the Launchpad Pro.
+    const FAMILY_CODE: [u8; 2] = [0x51, 0x00];
+
+    /// Returns the device ID of this unit, as chosen by the user in the setup menu. This can be
+    /// used to tell multiple Launchpad Pros apart.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use launchpad_pro_rs::hal::device;
+    ///
+    /// let id = device::id();
+    /// ```
+    pub fn id() -> u8 {
+        unsafe { super::hal_read_device_id() }
+    }
+
+    /// Returns whether the layout text setting is enabled in the setup menu. When enabled the
+    /// stock firmware scrolls the name of a layout across the grid when it is selected, so apps
+    /// can use it to decide whether to display their own text.
+    ///
+    /// The firmware only exposes this setting, it cannot be used to display the text itself.
+    pub fn layout_text() -> bool {
+        unsafe { super::hal_read_layout_text() != 0 }
+    }
+
+    /// Returns whether a SysEx message is a universal identity request (device inquiry) that is
+    /// addressed to this unit, or to all devices.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use launchpad_pro_rs::hal::device;
+    ///
+    /// assert!(device::is_identity_request(&[0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7]));
+    /// ```
+    pub fn is_identity_request(data: &[u8]) -> bool {
+        match *data {
+            [0xF0, 0x7E, target, 0x06, 0x01, 0xF7] => target == 0x7F || target == id(),
+            _ => false,
+        }
+    }
+
+    /// Returns the identity reply for this unit, which should be sent in response to an identity
+    /// request. The version is reported as four digits, each in the range `[0, 127]`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use launchpad_pro_rs::hal::{device, midi::{send_sysex, Port}};
+    ///
+    /// send_sysex(Port::USB, &device::identity_reply([0, 1, 0, 0]));
+    /// ```
+    #[rustfmt::skip]
+    pub fn identity_reply(version: [u8; 4]) -> [u8; 17] {
+        let [m0, m1, m2] = MANUFACTURER_ID;
+        let [f0, f1] = FAMILY_CODE;
+        let [v0, v1, v2, v3] = version.map(|digit| digit & 0x7F);
+        [
+            0xF0, 0x7E, id() & 0x7F, 0x06, 0x02, m0, m1, m2, f0, f1, 0x00, 0x00, v0, v1, v2, v3, 0xF7,
+        ]
+    }
+
+    /// Set the device ID returned by [`id`] when running on the host.
+    #[cfg(not(target_arch = "arm"))]
+    pub fn set_id(id: u8) {
+        super::sim::set_device_id(id);
+    }
+
+    /// Set the layout text setting returned by [`layout_text`] when running on the host.
+    #[cfg(not(target_arch = "arm"))]
+    pub fn set_layout_text(enabled: bool) {
+        super::sim::set_layout_text(enabled);
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn device_id_is_configurable_on_the_host() {
+            set_id(3);
+            assert_eq!(id(), 3);
+
+            set_id(12);
+            assert_eq!(id(), 12);
+        }
+
+        #[test]
+        fn layout_text_is_configurable_on_the_host() {
+            set_layout_text(false);
+            assert!(!layout_text());
+
+            set_layout_text(true);
+            assert!(layout_text());
+        }
+
+        #[test]
+        fn identity_requests_are_answered_for_this_unit_only() {
+            set_id(5);
+            assert!(is_identity_request(&[0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7]));
+            assert!(is_identity_request(&[0xF0, 0x7E, 0x05, 0x06, 0x01, 0xF7]));
+            assert!(!is_identity_request(&[0xF0, 0x7E, 0x06, 0x06, 0x01, 0xF7]));
+            assert!(!is_identity_request(&[0xF0, 0x7E, 0x7F, 0x06, 0x02, 0xF7]));
+
+            let reply = identity_reply([1, 2, 3, 200]);
+            assert_eq!(reply[2], 5);
+            assert_eq!(&reply[12..], &[1, 2, 3, 72, 0xF7]);
+        }
+    }
+}
+
+/// Keep track of time on the Launchpad Pro.
+pub mod time {
+    /// Returns the number of milliseconds that have elapsed since the app was initialised, as
+    /// counted by the firmware's 1 ms timer. The count wraps around after roughly 49 days.
+    pub fn now() -> u32 {
+        super::read_clock()
+    }
+}
+
+/// Draw graphics into a frame buffer, and show them on the LEDs.
+pub mod gfx;
+
+/// Store typed, versioned app settings in the user area of the flash memory.
+pub mod settings;
+
+/// Simulate the Launchpad Pro hardware on the host, so that apps can be run and tested without it.
+#[cfg(not(target_arch = "arm"))]
+pub mod sim;
+
+/// Schedule one-shot and periodic software timers, counted by the 1 ms tick, and control the
+/// interval of the app's `timer_event`.
+pub mod timer;
+
+/// Queue the events from the low level firmware until the app is free to receive them.
+pub mod queue;
+
+/// Report panics on the device, over USB and in flash.
+pub mod panic;
+
+/// Send log messages to the host as SysEx, and decode them there.
+pub mod log;
+
+/// Record the events an app receives so that they can be replayed on the host.
+pub mod record;
+
+/// Replay recorded events into an app on the host and compare the results against a golden
+/// transcript.
+#[cfg(not(target_arch = "arm"))]
+pub mod replay;
+
+/// Compare the LEDs of the simulated hardware against golden snapshots stored as text grids.
+#[cfg(not(target_arch = "arm"))]
+pub mod snapshot;
+
+/// Run apps on the host in a terminal, using the simulated Launchpad Pro hardware.
+#[cfg(all(feature = "emulator", not(target_arch = "arm")))]
+pub mod emulator;
+
 /// The LaunchpadApp trait can be implemented to receive events from the Launchpad Pro hardware.
 pub trait LaunchpadApp {
     /// Called on startup.
     fn init_event(&mut self, _pads: surface::Pads) {}
-    /// A timer. The interval is specified in milliseconds when using the `launchpad_app` macro.
+    /// A timer. The interval is specified in milliseconds when using the `launchpad_app` macro,
+    /// and can be changed or paused at runtime using [`timer::set_interval`] and [`timer::pause`].
     fn timer_event(&mut self) {}
+    /// Called when the timer has fired `count` times since it was last delivered, which is more
+    /// than once if the app was still busy handling other events. By default `timer_event` is
+    /// called once for each time, but apps that only care about the latest state can catch up in
+    /// one step.
+    fn timer_events(&mut self, count: u32) {
+        for _ in 0..count {
+            self.timer_event();
+        }
+    }
+    /// Called when one of the software timers scheduled with `hal::timer` fires.
+    fn software_timer_event(&mut self, _timer: timer::TimerId) {}
     /// Called when a MIDI message is received from USB or DIN.
     fn midi_event(&mut self, _port: midi::Port, _midi_event: midi::Message) {}
+    /// Called when a MIDI message is received from USB or DIN, decoded into its typed form. This
+    /// is called after `midi_event`, for messages that can be decoded.
+    fn midi_message_event(&mut self, _port: midi::Port, _message: midi::MidiMessage) {}
+    /// Called when the MIDI clock received on a port ticks, or its transport changes. The clock on
+    /// each port is followed separately.
+    fn clock_event(&mut self, _port: midi::Port, _clock_event: midi::clock::Event) {}
     /// Called when a SysEx message is received from USB or DIN.
     fn sysex_event(&mut self, _port: midi::Port, _data: &[u8]) {}
     /// Called when a MIDI DIN cable is connected or disconnected.
@@ -486,103 +967,174 @@ pub trait LaunchpadApp {
 ///
 /// // Register our app with the hardware.
 /// // The timer interval is specified in milliseconds, and determines how often the `timer_event`
-/// // method will be called.
+/// // method will be called. It can be changed while the app is running with
+/// // `hal::timer::set_interval`.
 /// launchpad_app!(App => 100 ms);
 /// ```
+///
+/// Apps that implement [`settings::Persistent`] can have their settings restored from flash
+/// before `init_event` is called by registering them using `launchpad_app!(App => 100 ms, with
+/// settings)`.
 #[macro_export]
 macro_rules! launchpad_app {
     ($t:ty => $timer_interval:literal ms) => {
+        $crate::launchpad_app!(@register $t, $timer_interval, $crate::hal::app_init_event);
+    };
+    ($t:ty => $timer_interval:literal ms, with settings) => {
+        $crate::launchpad_app!(@register $t, $timer_interval, $crate::hal::app_init_event_with_settings);
+    };
+    (@register $t:ty, $timer_interval:literal, $init_event:path) => {
         static __LAUNCHPAD_APP: $crate::hal::Mutex<Option<$t>> = $crate::hal::Mutex::new(None);
 
         #[no_mangle]
         pub extern "C" fn app_init(adc: *const u16) {
-            let mut app = __LAUNCHPAD_APP.lock();
-            if app.is_none() {
-                let app = app.insert(<$t>::default());
-                $crate::hal::app_init_event(app, adc);
+            {
+                let mut app = __LAUNCHPAD_APP.lock();
+                if app.is_none() {
+                    $crate::hal::timer::set_interval($timer_interval);
+                    let app = app.insert(<$t>::default());
+                    $init_event(app, adc);
+                }
             }
+            $crate::hal::queue::dispatch(&__LAUNCHPAD_APP);
         }
 
+        // The events are queued rather than waiting for the lock on the app, so that the firmware
+        // is never blocked. Whichever call holds the lock delivers them.
+
         #[no_mangle]
         pub extern "C" fn app_surface_event(event: u8, index: u8, value: u8) {
-            let mut app = __LAUNCHPAD_APP.lock();
-            if let Some(app) = app.as_mut() {
-                $crate::hal::app_surface_event(app, event, index, value);
-            }
+            $crate::hal::queue::push_surface_event(event, index, value);
+            $crate::hal::queue::dispatch(&__LAUNCHPAD_APP);
         }
 
         #[no_mangle]
         pub extern "C" fn app_midi_event(port: u8, status: u8, data1: u8, data2: u8) {
-            let mut app = __LAUNCHPAD_APP.lock();
-            if let Some(app) = app.as_mut() {
-                $crate::hal::app_midi_event(app, port, status, data1, data2);
-            }
+            $crate::hal::queue::push_midi_event(port, status, data1, data2);
+            $crate::hal::queue::dispatch(&__LAUNCHPAD_APP);
         }
 
         #[no_mangle]
+        #[allow(clippy::not_unsafe_ptr_arg_deref)]
         pub extern "C" fn app_sysex_event(port: u8, data: *mut u8, count: u16) {
-            let mut app = __LAUNCHPAD_APP.lock();
-            if let Some(app) = app.as_mut() {
-                $crate::hal::app_sysex_event(app, port, data, count);
-            }
+            let data = unsafe { core::slice::from_raw_parts(data, count as usize) };
+            $crate::hal::queue::push_sysex_event(port, data);
+            $crate::hal::queue::dispatch(&__LAUNCHPAD_APP);
         }
 
         #[no_mangle]
         pub extern "C" fn app_aftertouch_event(index: u8, value: u8) {
-            let mut app = __LAUNCHPAD_APP.lock();
-            if let Some(app) = app.as_mut() {
-                $crate::hal::app_aftertouch_event(&mut *app, index, value);
-            }
+            $crate::hal::queue::push_aftertouch_event(index, value);
+            $crate::hal::queue::dispatch(&__LAUNCHPAD_APP);
         }
 
         #[no_mangle]
         extern "C" fn app_cable_event(cable_type: u8, value: u8) {
-            let mut app = __LAUNCHPAD_APP.lock();
-            if let Some(app) = app.as_mut() {
-                $crate::hal::app_cable_event(app, cable_type, value);
-            }
+            $crate::hal::queue::push_cable_event(cable_type, value);
+            $crate::hal::queue::dispatch(&__LAUNCHPAD_APP);
         }
 
         #[no_mangle]
         pub extern "C" fn app_timer_event() {
-            static mut ONE_MS_TICKS: i32 = 0;
-
-            unsafe {
-                if ONE_MS_TICKS == $timer_interval {
-                    ONE_MS_TICKS = 0;
-                } else {
-                    ONE_MS_TICKS += 1;
-                    return;
-                }
-            }
-
-            // If the timer is running too fast we can get too much contention on the lock, which
-            // grinds everything to a halt, so we only run the timer event if the lock is free.
-            if let Some(mut app) = __LAUNCHPAD_APP.try_lock() {
-                if let Some(app) = app.as_mut() {
-                    $crate::hal::app_timer_event(app);
-                }
+            $crate::hal::app_tick();
+            if $crate::hal::timer::interval_elapsed() {
+                $crate::hal::queue::push_timer_event();
             }
+            $crate::hal::queue::dispatch(&__LAUNCHPAD_APP);
         }
 
         #[cfg(target_arch = "arm")]
         #[panic_handler]
-        fn panic(_info: &core::panic::PanicInfo) -> ! {
-            loop {}
+        fn panic(info: &core::panic::PanicInfo) -> ! {
+            $crate::hal::panic::handle(info)
         }
 
         #[cfg(not(target_arch = "arm"))]
-        fn main() {}
+        fn main() {
+            $crate::hal::host_main($crate::hal::EntryPoints {
+                init: app_init,
+                surface: app_surface_event,
+                midi: app_midi_event,
+                sysex: app_sysex_event,
+                aftertouch: app_aftertouch_event,
+                cable: app_cable_event,
+                timer: app_timer_event,
+            });
+        }
     };
 }
 
+/// The entry points generated by the `launchpad_app!` macro, which the low level firmware calls.
+#[doc(hidden)]
+#[cfg(not(target_arch = "arm"))]
+#[derive(Clone, Copy)]
+pub struct EntryPoints {
+    pub init: extern "C" fn(*const u16),
+    pub surface: extern "C" fn(u8, u8, u8),
+    pub midi: extern "C" fn(u8, u8, u8, u8),
+    pub sysex: extern "C" fn(u8, *mut u8, u16),
+    pub aftertouch: extern "C" fn(u8, u8),
+    pub cable: extern "C" fn(u8, u8),
+    pub timer: extern "C" fn(),
+}
+
+/// Called by the `main` function generated by the `launchpad_app!` macro when building for the
+/// host. When the `emulator` feature is enabled the app is run in the terminal, otherwise this
+/// does nothing.
+#[doc(hidden)]
+#[cfg(not(target_arch = "arm"))]
+pub fn host_main(entry_points: EntryPoints) {
+    #[cfg(feature = "emulator")]
+    if let Err(error) = emulator::run(entry_points) {
+        eprintln!("emulator error: {}", error);
+        std::process::exit(1);
+    }
+
+    #[cfg(not(feature = "emulator"))]
+    let _ = entry_points;
+}
+
 #[doc(hidden)]
 pub fn app_init_event(app: &mut dyn LaunchpadApp, adc: *const u16) {
     app.init_event(surface::Pads::new(adc));
 }
 
+#[doc(hidden)]
+pub fn app_init_event_with_settings<A: LaunchpadApp + settings::Persistent>(
+    app: &mut A,
+    adc: *const u16,
+) {
+    app.restore(settings::load());
+    app_init_event(app, adc);
+}
+
+#[doc(hidden)]
+pub fn app_tick() {
+    advance_clock();
+    midi::clock::master::tick();
+    timer::tick();
+}
+
+#[doc(hidden)]
+pub fn app_software_timer_events(app: &mut dyn LaunchpadApp) {
+    while let Some(id) = timer::take_expired() {
+        app_software_timer_event(app, id);
+    }
+}
+
+#[doc(hidden)]
+pub fn app_software_timer_event(app: &mut dyn LaunchpadApp, id: timer::TimerId) {
+    record::capture(record::Event::SoftwareTimer { id: id.0 });
+    app.software_timer_event(id);
+}
+
 #[doc(hidden)]
 pub fn app_surface_event(app: &mut dyn LaunchpadApp, event: u8, index: u8, value: u8) {
+    record::capture(record::Event::Surface {
+        event,
+        index,
+        value,
+    });
     app.button_event(surface::ButtonEvent {
         button: if event == 1 {
             surface::Button::Setup
@@ -598,42 +1150,43 @@ pub fn app_surface_event(app: &mut dyn LaunchpadApp, event: u8, index: u8, value
 }
 
 #[doc(hidden)]
-pub fn app_midi_event(app: &mut dyn LaunchpadApp, port: u8, status: u8, data1: u8, data2: u8) {
-    let port = match port {
-        0 => Some(midi::Port::Standalone),
-        1 => Some(midi::Port::USB),
-        2 => Some(midi::Port::DIN),
-        _ => None,
-    };
-
-    if let Some(port) = port {
-        app.midi_event(
-            port,
-            midi::Message {
-                status,
-                data: (data1, data2),
-            },
-        );
+pub fn app_midi_event(
+    app: &mut dyn LaunchpadApp,
+    port: u8,
+    status: u8,
+    data1: u8,
+    data2: u8,
+    time: u32,
+) {
+    record::capture(record::Event::Midi {
+        port,
+        status,
+        data: (data1, data2),
+    });
+    if let Some(port) = midi::Port::from_u8(port) {
+        if let Some(message) = midi::Message::new(status, (data1, data2)) {
+            app.midi_event(port, message);
+            if let Ok(message) = midi::MidiMessage::try_from(message) {
+                app.midi_message_event(port, message);
+                for clock_event in midi::clock::follow(port, message, time) {
+                    app.clock_event(port, clock_event);
+                }
+            }
+        }
     }
 }
 
 #[doc(hidden)]
-pub fn app_sysex_event(app: &mut dyn LaunchpadApp, port: u8, data: *mut u8, count: u16) {
-    let port = match port {
-        0 => Some(midi::Port::Standalone),
-        1 => Some(midi::Port::USB),
-        2 => Some(midi::Port::DIN),
-        _ => None,
-    };
-
-    if let Some(port) = port {
-        let slice = unsafe { core::slice::from_raw_parts(data, count as usize) };
-        app.sysex_event(port, slice);
+pub fn app_sysex_event(app: &mut dyn LaunchpadApp, port: u8, data: &[u8]) {
+    record::capture(record::Event::Sysex { port, data });
+    if let Some(port) = midi::Port::from_u8(port) {
+        app.sysex_event(port, data);
     }
 }
 
 #[doc(hidden)]
 pub fn app_aftertouch_event(app: &mut dyn LaunchpadApp, index: u8, value: u8) {
+    record::capture(record::Event::Aftertouch { index, value });
     app.aftertouch_event(surface::AftertouchEvent {
         point: Point::from_index(index),
         value,
@@ -642,6 +1195,7 @@ pub fn app_aftertouch_event(app: &mut dyn LaunchpadApp, index: u8, value: u8) {
 
 #[doc(hidden)]
 pub fn app_cable_event(app: &mut dyn LaunchpadApp, cable_type: u8, value: u8) {
+    record::capture(record::Event::Cable { cable_type, value });
     let cable_type = match cable_type {
         0 => Some(midi::Cable::MidiIn),
         1 => Some(midi::Cable::MidiOut),
@@ -658,7 +1212,16 @@ pub fn app_cable_event(app: &mut dyn LaunchpadApp, cable_type: u8, value: u8) {
 
 #[doc(hidden)]
 pub fn app_timer_event(app: &mut dyn LaunchpadApp) {
-    app.timer_event();
+    app_timer_events(app, 1);
+}
+
+#[doc(hidden)]
+pub fn app_timer_events(app: &mut dyn LaunchpadApp, count: u32) {
+    record::capture(match count {
+        1 => record::Event::Timer,
+        count => record::Event::Timers { count },
+    });
+    app.timer_events(count);
 }
 
 #[cfg(test)]
@@ -724,4 +1287,221 @@ mod tests {
         assert_eq!(green, 31);
         assert_eq!(blue, 15);
     }
+
+    #[test]
+    fn midi_messages_are_delivered_decoded() {
+        #[derive(Default)]
+        struct App {
+            messages: Vec<(midi::Port, midi::MidiMessage)>,
+        }
+
+        impl LaunchpadApp for App {
+            fn midi_message_event(&mut self, port: midi::Port, message: midi::MidiMessage) {
+                self.messages.push((port, message));
+            }
+        }
+
+        let mut simulator = sim::Simulator::new(App::default(), 0);
+        let message = |status, data| midi::Message::new(status, data).unwrap();
+        simulator.midi(midi::Port::DIN, message(0xC1, (42, 0)));
+        simulator.midi(midi::Port::USB, message(0xF4, (0, 0)));
+        simulator.midi(midi::Port::USB, message(0xFC, (0, 0)));
+
+        assert_eq!(
+            simulator.app().messages,
+            vec![
+                (
+                    midi::Port::DIN,
+                    midi::MidiMessage::ProgramChange {
+                        channel: midi::Channel::new(1).unwrap(),
+                        program: midi::U7::new(42).unwrap(),
+                    }
+                ),
+                (midi::Port::USB, midi::MidiMessage::Stop),
+            ]
+        );
+    }
+
+    #[test]
+    fn software_timers_are_delivered() {
+        const BLINK: timer::TimerId = timer::TimerId(1);
+        const STOP: timer::TimerId = timer::TimerId(2);
+
+        #[derive(Default)]
+        struct App {
+            fired: Vec<(u32, timer::TimerId)>,
+        }
+
+        impl LaunchpadApp for App {
+            fn init_event(&mut self, _pads: surface::Pads) {
+                timer::every(BLINK, 10).unwrap();
+                timer::once(STOP, 25).unwrap();
+            }
+
+            fn software_timer_event(&mut self, id: timer::TimerId) {
+                self.fired.push((time::now(), id));
+                if id == STOP {
+                    timer::cancel(BLINK);
+                }
+            }
+        }
+
+        let mut simulator = sim::Simulator::new(App::default(), 0);
+        simulator.advance(100);
+        assert_eq!(
+            simulator.app().fired,
+            vec![(10, BLINK), (20, BLINK), (25, STOP)]
+        );
+        assert_eq!(timer::remaining(BLINK), None);
+    }
+
+    #[test]
+    fn events_are_queued_while_the_app_is_busy() {
+        #[derive(Default)]
+        struct App {
+            events: Vec<String>,
+        }
+
+        impl LaunchpadApp for App {
+            fn button_event(&mut self, event: surface::ButtonEvent) {
+                self.events.push(format!("{:?}", event.button));
+            }
+
+            fn sysex_event(&mut self, _port: midi::Port, data: &[u8]) {
+                self.events.push(format!("sysex {:?}", data));
+            }
+
+            fn software_timer_event(&mut self, id: timer::TimerId) {
+                self.events.push(format!("software timer {}", id.0));
+            }
+
+            fn timer_events(&mut self, count: u32) {
+                self.events.push(format!("timer x{}", count));
+            }
+        }
+
+        sim::reset();
+        let app = Mutex::new(Some(App::default()));
+
+        let busy = app.lock();
+        queue::push_timer_event();
+        queue::push_surface_event(0, 11, 127);
+        let mut sysex = [0xF0, 1, 2, 0xF7];
+        queue::push_sysex_event(0, &sysex);
+        sysex[1] = 3;
+        timer::once(timer::TimerId(4), 1).unwrap();
+        timer::tick();
+        queue::push_timer_event();
+        queue::push_surface_event(1, 0, 127);
+        queue::dispatch(&app);
+        drop(busy);
+        assert!(app.lock().as_ref().unwrap().events.is_empty());
+
+        queue::dispatch(&app);
+        assert_eq!(
+            app.lock().as_ref().unwrap().events,
+            [
+                "Pad(Point { x: 1, y: 1 })",
+                "sysex [240, 1, 2, 247]",
+                "Setup",
+                "software timer 4",
+                "timer x2"
+            ]
+        );
+        assert_eq!(queue::overflows(), queue::Overflows::default());
+
+        // events are dropped when there is no room to queue them
+        let busy = app.lock();
+        for _ in 0..queue::SYSEX_CAPACITY + 1 {
+            queue::push_sysex_event(0, &sysex);
+        }
+        for _ in 0..queue::CAPACITY {
+            queue::push_surface_event(1, 0, 0);
+        }
+        drop(busy);
+        queue::dispatch(&app);
+        assert_eq!(
+            app.lock().as_ref().unwrap().events.len(),
+            5 + queue::CAPACITY
+        );
+        assert_eq!(
+            queue::overflows(),
+            queue::Overflows {
+                events: queue::SYSEX_CAPACITY as u32,
+                sysex: 1
+            }
+        );
+    }
+
+    #[test]
+    fn midi_clock_is_followed() {
+        #[derive(Default)]
+        struct App {
+            clock_events: Vec<(midi::Port, midi::clock::Event)>,
+        }
+
+        impl LaunchpadApp for App {
+            fn clock_event(&mut self, port: midi::Port, clock_event: midi::clock::Event) {
+                self.clock_events.push((port, clock_event));
+            }
+        }
+
+        let mut simulator = sim::Simulator::new(App::default(), 0);
+        let message = |status| midi::Message::new(status, (0, 0)).unwrap();
+        simulator.midi(midi::Port::USB, message(0xFA));
+        simulator.midi(midi::Port::DIN, message(0xFA));
+        for _ in 0..25 {
+            // the same clock arriving on two ports is followed separately for each
+            simulator.midi(midi::Port::USB, message(0xF8));
+            simulator.midi(midi::Port::DIN, message(0xF8));
+            simulator.advance(20);
+        }
+
+        for &port in &[midi::Port::USB, midi::Port::DIN] {
+            assert_eq!(
+                midi::clock::transport(port),
+                midi::clock::Transport::Playing
+            );
+            assert_eq!(midi::clock::position(port), 25);
+            assert_eq!(midi::clock::bpm(port), Some(125.0));
+            assert_eq!(
+                simulator
+                    .app()
+                    .clock_events
+                    .iter()
+                    .filter(|(event_port, event)| *event_port == port
+                        && matches!(event, midi::clock::Event::Beat(_)))
+                    .count(),
+                2
+            );
+        }
+        assert_eq!(
+            midi::clock::transport(midi::Port::Standalone),
+            midi::clock::Transport::Stopped
+        );
+
+        sim::reset();
+        assert_eq!(
+            midi::clock::transport(midi::Port::USB),
+            midi::clock::Transport::Stopped
+        );
+    }
+
+    #[test]
+    fn midi_clock_is_timed_when_it_is_queued() {
+        sim::reset();
+        let app = Mutex::new(Some(sim::IdleApp));
+        queue::push_midi_event(midi::Port::USB as u8, 0xF8, 0, 0);
+        for _ in 0..20 {
+            app_tick();
+        }
+        queue::push_midi_event(midi::Port::USB as u8, 0xF8, 0, 0);
+
+        // both ticks are delivered together later on, but are timed from when they arrived
+        for _ in 0..100 {
+            app_tick();
+        }
+        queue::dispatch(&app);
+        assert_eq!(midi::clock::bpm(midi::Port::USB), Some(125.0));
+    }
 }
@@ -0,0 +1,521 @@
+use crate::hal::{Blue, Green, Red, Rgb};
+
+/// A colour given as hue, saturation and value, which is convenient for rainbows and for mapping
+/// values such as velocity onto colours.
+///
+/// # Example
+///
+/// ```
+/// use launchpad_pro_rs::hal::{Hsv, Rgb};
+///
+/// assert_eq!(Rgb::from_hsv(Hsv::new(120, 255, 255)), Rgb::GREEN);
+///
+/// // map a velocity from 0 to 127 around the colour wheel
+/// let velocity = 64;
+/// let rgb = Rgb::from_hsv(Hsv::new(velocity * 360 / 128, 255, 255));
+/// assert_eq!(rgb, Rgb::CYAN);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Hsv {
+    hue: u16,
+    saturation: u8,
+    value: u8,
+}
+
+impl Hsv {
+    /// Construct a colour from a hue in degrees, which wraps around at 360, and a saturation and
+    /// value in the range `[0, 255]`.
+    pub const fn new(hue: u16, saturation: u8, value: u8) -> Self {
+        Self {
+            hue: hue % 360,
+            saturation,
+            value,
+        }
+    }
+
+    /// Returns the hue in degrees, in the range `[0, 359]`.
+    pub const fn hue(&self) -> u16 {
+        self.hue
+    }
+
+    /// Returns the saturation, from 0 for grey to 255 for the pure hue.
+    pub const fn saturation(&self) -> u8 {
+        self.saturation
+    }
+
+    /// Returns the value, from 0 for black to 255 for the brightest colour.
+    pub const fn value(&self) -> u8 {
+        self.value
+    }
+}
+
+/// A colour given as hue, saturation and lightness.
+///
+/// # Example
+///
+/// ```
+/// use launchpad_pro_rs::hal::{Hsl, Rgb};
+///
+/// assert_eq!(Rgb::from_hsl(Hsl::new(0, 255, 128)), Rgb::RED);
+/// assert_eq!(Rgb::from_hsl(Hsl::new(0, 255, 255)), Rgb::WHITE);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Hsl {
+    hue: u16,
+    saturation: u8,
+    lightness: u8,
+}
+
+impl Hsl {
+    /// Construct a colour from a hue in degrees, which wraps around at 360, and a saturation and
+    /// lightness in the range `[0, 255]`.
+    pub const fn new(hue: u16, saturation: u8, lightness: u8) -> Self {
+        Self {
+            hue: hue % 360,
+            saturation,
+            lightness,
+        }
+    }
+
+    /// Returns the hue in degrees, in the range `[0, 359]`.
+    pub const fn hue(&self) -> u16 {
+        self.hue
+    }
+
+    /// Returns the saturation, from 0 for grey to 255 for the pure hue.
+    pub const fn saturation(&self) -> u8 {
+        self.saturation
+    }
+
+    /// Returns the lightness, from 0 for black through the pure hue at 128 to 255 for white.
+    pub const fn lightness(&self) -> u8 {
+        self.lightness
+    }
+}
+
+impl Rgb {
+    /// Construct a colour from hue, saturation and value.
+    pub const fn from_hsv(hsv: Hsv) -> Self {
+        let value = hsv.value as i32;
+        let chroma = value * hsv.saturation as i32 / 255;
+        from_hue(hsv.hue, chroma, value - chroma)
+    }
+
+    /// Construct a colour from hue, saturation and lightness.
+    pub const fn from_hsl(hsl: Hsl) -> Self {
+        let lightness = hsl.lightness as i32;
+        let chroma = (255 - (2 * lightness - 255).abs()) * hsl.saturation as i32 / 255;
+        from_hue(hsl.hue, chroma, lightness - chroma / 2)
+    }
+
+    /// Returns the hue, saturation and value of the colour.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use launchpad_pro_rs::hal::{Hsv, Rgb};
+    ///
+    /// assert_eq!(Rgb::YELLOW.to_hsv(), Hsv::new(60, 255, 255));
+    /// ```
+    pub const fn to_hsv(self) -> Hsv {
+        let (max, min) = (self.max(), self.min());
+        let saturation = match max {
+            0 => 0,
+            _ => (max - min) * 255 / max,
+        };
+        Hsv::new(self.hue(), saturation as u8, max as u8)
+    }
+
+    /// Returns the hue, saturation and lightness of the colour.
+    pub const fn to_hsl(self) -> Hsl {
+        let (max, min) = (self.max(), self.min());
+        let saturation = match max - min {
+            0 => 0,
+            chroma => chroma * 255 / (255 - (max + min - 255).abs()),
+        };
+        Hsl::new(self.hue(), saturation as u8, ((max + min + 1) / 2) as u8)
+    }
+
+    /// Rotate the hue of the colour around the colour wheel by a number of degrees, keeping its
+    /// saturation and value.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use launchpad_pro_rs::hal::Rgb;
+    ///
+    /// assert_eq!(Rgb::RED.rotate_hue(120), Rgb::GREEN);
+    /// assert_eq!(Rgb::RED.rotate_hue(-120), Rgb::BLUE);
+    /// ```
+    pub const fn rotate_hue(self, degrees: i32) -> Self {
+        let hsv = self.to_hsv();
+        let hue = (hsv.hue as i32 + degrees).rem_euclid(360);
+        Self::from_hsv(Hsv::new(hue as u16, hsv.saturation, hsv.value))
+    }
+
+    /// Scale the brightness of the colour so that equal steps in brightness look equally far
+    /// apart, from 0 for black to 255 for the colour unchanged. The LEDs are much brighter than
+    /// they look at low levels, so scaling each component linearly makes a fade look uneven.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use launchpad_pro_rs::hal::{Blue, Green, Red, Rgb};
+    ///
+    /// assert_eq!(Rgb::WHITE.scale_brightness(255), Rgb::WHITE);
+    /// assert_eq!(Rgb::WHITE.scale_brightness(0), Rgb::BLACK);
+    ///
+    /// // half as bright looks like much less than half of the light
+    /// let half = Rgb::new(Red::new(49), Green::new(49), Blue::new(49));
+    /// assert_eq!(Rgb::WHITE.scale_brightness(128), half);
+    /// ```
+    pub const fn scale_brightness(self, brightness: u8) -> Self {
+        let luminance = luminance(brightness);
+        let Rgb(Red(red), Green(green), Blue(blue)) = self;
+        Rgb(
+            Red(scale(red, luminance)),
+            Green(scale(green, luminance)),
+            Blue(scale(blue, luminance)),
+        )
+    }
+
+    /// Returns a colour part way between this colour and another, from 0 for this colour to 255
+    /// for the other.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use launchpad_pro_rs::hal::Rgb;
+    ///
+    /// assert_eq!(Rgb::BLACK.lerp(Rgb::WHITE, 128), Rgb::from_6_bit(32, 32, 32));
+    /// assert_eq!(Rgb::RED.lerp(Rgb::BLUE, 255), Rgb::BLUE);
+    /// ```
+    pub const fn lerp(self, other: Rgb, amount: u8) -> Self {
+        let (Rgb(Red(r0), Green(g0), Blue(b0)), Rgb(Red(r1), Green(g1), Blue(b1))) = (self, other);
+        Rgb(
+            Red(lerp(r0, r1, amount)),
+            Green(lerp(g0, g1, amount)),
+            Blue(lerp(b0, b1, amount)),
+        )
+    }
+
+    /// Add the components of two colours, so that each brightens the other. Components that would
+    /// be brighter than the LED can show are clamped.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use launchpad_pro_rs::hal::Rgb;
+    ///
+    /// assert_eq!(Rgb::RED.saturating_add(Rgb::GREEN), Rgb::YELLOW);
+    /// assert_eq!(Rgb::YELLOW.saturating_add(Rgb::RED), Rgb::YELLOW);
+    /// ```
+    pub const fn saturating_add(self, other: Rgb) -> Self {
+        let (Rgb(Red(r0), Green(g0), Blue(b0)), Rgb(Red(r1), Green(g1), Blue(b1))) = (self, other);
+        Rgb::from_6_bit(r0 + r1, g0 + g1, b0 + b1)
+    }
+
+    /// Multiply the components of two colours as fractions of the brightest level, so that each
+    /// darkens or tints the other.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use launchpad_pro_rs::hal::Rgb;
+    ///
+    /// assert_eq!(Rgb::YELLOW.multiply(Rgb::RED), Rgb::RED);
+    /// assert_eq!(Rgb::WHITE.multiply(Rgb::CYAN), Rgb::CYAN);
+    /// ```
+    pub const fn multiply(self, other: Rgb) -> Self {
+        let (Rgb(Red(r0), Green(g0), Blue(b0)), Rgb(Red(r1), Green(g1), Blue(b1))) = (self, other);
+        Rgb(
+            Red(multiply(r0, r1)),
+            Green(multiply(g0, g1)),
+            Blue(multiply(b0, b1)),
+        )
+    }
+
+    /// Composite the colour over another with an opacity, from 0 for fully transparent, which
+    /// shows only the colour beneath, to 255 for fully opaque.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use launchpad_pro_rs::hal::Rgb;
+    ///
+    /// assert_eq!(Rgb::RED.over(Rgb::BLUE, 255), Rgb::RED);
+    /// assert_eq!(Rgb::RED.over(Rgb::BLUE, 0), Rgb::BLUE);
+    /// assert_eq!(Rgb::WHITE.over(Rgb::BLACK, 64), Rgb::from_6_bit(16, 16, 16));
+    /// ```
+    pub const fn over(self, below: Rgb, alpha: u8) -> Self {
+        below.lerp(self, alpha)
+    }
+
+    /// Correct the colour for the response of the LEDs, treating each component as a perceived
+    /// brightness. The LED drivers are linear in light output, but the eye is not, so without
+    /// correction a component at half its level looks much brighter than half as bright. Levels
+    /// above zero are kept above zero, so that dim colours do not disappear.
+    ///
+    /// To dim a colour that has already been chosen by eye, use
+    /// [`scale_brightness`](Rgb::scale_brightness) instead.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use launchpad_pro_rs::hal::Rgb;
+    ///
+    /// assert_eq!(Rgb::from_6_bit(32, 0, 1).gamma_correct(), Rgb::from_6_bit(14, 0, 1));
+    /// assert_eq!(Rgb::WHITE.gamma_correct(), Rgb::WHITE);
+    /// ```
+    pub const fn gamma_correct(self) -> Self {
+        let Rgb(Red(red), Green(green), Blue(blue)) = self;
+        Rgb(
+            Red(GAMMA[red as usize]),
+            Green(GAMMA[green as usize]),
+            Blue(GAMMA[blue as usize]),
+        )
+    }
+
+    /// Returns the components of the colour in the range `[0, 255]`.
+    const fn components(self) -> [i32; 3] {
+        let Rgb(Red(red), Green(green), Blue(blue)) = self;
+        [
+            convert_to_8_bit(red),
+            convert_to_8_bit(green),
+            convert_to_8_bit(blue),
+        ]
+    }
+
+    const fn max(self) -> i32 {
+        let [red, green, blue] = self.components();
+        let max = if red > green { red } else { green };
+        if max > blue {
+            max
+        } else {
+            blue
+        }
+    }
+
+    const fn min(self) -> i32 {
+        let [red, green, blue] = self.components();
+        let min = if red < green { red } else { green };
+        if min < blue {
+            min
+        } else {
+            blue
+        }
+    }
+
+    /// Returns the hue of the colour in degrees, or 0 for greys.
+    const fn hue(self) -> u16 {
+        let [red, green, blue] = self.components();
+        let (max, min) = (self.max(), self.min());
+        let chroma = max - min;
+        if chroma == 0 {
+            return 0;
+        }
+        let hue = if max == red {
+            divide((green - blue) * 60, chroma)
+        } else if max == green {
+            divide((blue - red) * 60, chroma) + 120
+        } else {
+            divide((red - green) * 60, chroma) + 240
+        };
+        hue.rem_euclid(360) as u16
+    }
+}
+
+impl From<Hsv> for Rgb {
+    fn from(hsv: Hsv) -> Self {
+        Self::from_hsv(hsv)
+    }
+}
+
+impl From<Rgb> for Hsv {
+    fn from(rgb: Rgb) -> Self {
+        rgb.to_hsv()
+    }
+}
+
+impl From<Hsl> for Rgb {
+    fn from(hsl: Hsl) -> Self {
+        Self::from_hsl(hsl)
+    }
+}
+
+impl From<Rgb> for Hsl {
+    fn from(rgb: Rgb) -> Self {
+        rgb.to_hsl()
+    }
+}
+
+/// The level to send to an LED driver for each perceived brightness, using a gamma of 2.2 and
+/// keeping every level above zero lit.
+#[rustfmt::skip]
+const GAMMA: [u8; 64] = [
+    0, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 3,
+    3, 4, 4, 5, 5, 6, 6, 7, 8, 8, 9, 10, 11, 11, 12, 13,
+    14, 15, 16, 17, 18, 20, 21, 22, 23, 24, 26, 27, 29, 30, 32, 33,
+    35, 36, 38, 40, 41, 43, 45, 47, 49, 51, 53, 55, 57, 59, 61, 63,
+];
+
+/// Returns a 6-bit level part way between two others, from 0 for the first to 255 for the
+/// second.
+const fn lerp(from: u8, to: u8, amount: u8) -> u8 {
+    let (from, to, amount) = (from as i32, to as i32, amount as i32);
+    (from + divide((to - from) * amount, 255)) as u8
+}
+
+/// Multiply two 6-bit levels as fractions of the brightest level.
+const fn multiply(a: u8, b: u8) -> u8 {
+    divide(a as i32 * b as i32, 63) as u8
+}
+
+/// Construct a colour from its hue, chroma and the amount added to each component, all but the
+/// hue in the range `[0, 255]`.
+const fn from_hue(hue: u16, chroma: i32, offset: i32) -> Rgb {
+    let hue = (hue % 360) as i32;
+    let second = divide(chroma * (60 - (hue % 120 - 60).abs()), 60);
+    let (red, green, blue) = match hue / 60 {
+        0 => (chroma, second, 0),
+        1 => (second, chroma, 0),
+        2 => (0, chroma, second),
+        3 => (0, second, chroma),
+        4 => (second, 0, chroma),
+        _ => (chroma, 0, second),
+    };
+    Rgb::new(
+        Red::new((red + offset) as u8),
+        Green::new((green + offset) as u8),
+        Blue::new((blue + offset) as u8),
+    )
+}
+
+/// Divide by a positive number, rounding to the nearest integer.
+const fn divide(dividend: i32, divisor: i32) -> i32 {
+    (2 * dividend + divisor).div_euclid(2 * divisor)
+}
+
+/// Map a 6-bit component to the smallest value in the range `[0, 255]` that maps back to it.
+const fn convert_to_8_bit(component: u8) -> i32 {
+    (component as i32 * 255 + 62) / 63
+}
+
+/// Scale a component by a luminance in the range `[0, 65535]`.
+const fn scale(component: u8, luminance: u64) -> u8 {
+    ((component as u64 * luminance + 32_767) / 65_535) as u8
+}
+
+/// Returns the luminance in the range `[0, 65535]` that looks as bright as a perceived brightness
+/// in the range `[0, 255]`, using the CIE 1976 lightness formula.
+const fn luminance(brightness: u8) -> u64 {
+    let brightness = brightness as u64;
+    // the lightness is brightness * 100 / 255, and is linear below 8
+    if brightness * 100 <= 8 * 255 {
+        (brightness * 100 * 65_535 * 10 + 255 * 9_033 / 2) / (255 * 9_033)
+    } else {
+        let numerator = brightness * 100 + 16 * 255;
+        let denominator = 116 * 255;
+        (numerator * numerator * numerator * 65_535 + denominator * denominator * denominator / 2)
+            / (denominator * denominator * denominator)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_colour_wheel_is_converted_from_hsv() {
+        let wheel = [
+            (0, Rgb::RED),
+            (60, Rgb::YELLOW),
+            (120, Rgb::GREEN),
+            (180, Rgb::CYAN),
+            (240, Rgb::BLUE),
+            (300, Rgb::MAGENTA),
+            (360, Rgb::RED),
+        ];
+        for (hue, rgb) in wheel {
+            assert_eq!(Rgb::from_hsv(Hsv::new(hue, 255, 255)), rgb, "{}", hue);
+            assert_eq!(Rgb::from_hsl(Hsl::new(hue, 255, 128)), rgb, "{}", hue);
+            assert_eq!(rgb.to_hsv(), Hsv::new(hue, 255, 255), "{}", hue);
+            assert_eq!(rgb.to_hsl(), Hsl::new(hue, 255, 128), "{}", hue);
+        }
+        assert_eq!(Rgb::from_hsv(Hsv::new(200, 0, 255)), Rgb::WHITE);
+        assert_eq!(Rgb::from_hsv(Hsv::new(200, 255, 0)), Rgb::BLACK);
+        assert_eq!(Rgb::BLACK.to_hsl(), Hsl::new(0, 0, 0));
+        assert_eq!(Rgb::WHITE.to_hsl(), Hsl::new(0, 0, 255));
+    }
+
+    /// Returns whether two colours differ by at most one step in each component.
+    fn is_close(a: Rgb, b: Rgb) -> bool {
+        let (Rgb(Red(r0), Green(g0), Blue(b0)), Rgb(Red(r1), Green(g1), Blue(b1))) = (a, b);
+        r0.abs_diff(r1) <= 1 && g0.abs_diff(g1) <= 1 && b0.abs_diff(b1) <= 1
+    }
+
+    #[test]
+    fn conversions_round_trip_within_the_precision_of_the_leds() {
+        for hue in (0..360).step_by(7) {
+            for level in (0..=255).step_by(17) {
+                for rgb in [
+                    Rgb::from_hsv(Hsv::new(hue, 255 - level, 255)),
+                    Rgb::from_hsv(Hsv::new(hue, 255, level)),
+                ] {
+                    let hsv = Hsv::from(rgb);
+                    assert!(is_close(Rgb::from(hsv), rgb), "{:?} {:?}", rgb, hsv);
+                    let hsl = Hsl::from(rgb);
+                    assert!(is_close(Rgb::from(hsl), rgb), "{:?} {:?}", rgb, hsl);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn hues_rotate_around_the_wheel() {
+        assert_eq!(Rgb::YELLOW.rotate_hue(120), Rgb::CYAN);
+        assert_eq!(Rgb::CYAN.rotate_hue(-540), Rgb::RED);
+        assert_eq!(Rgb::WHITE.rotate_hue(90), Rgb::WHITE);
+        const ROTATED: Rgb = Rgb::MAGENTA.rotate_hue(60);
+        assert_eq!(ROTATED, Rgb::RED);
+    }
+
+    #[test]
+    fn colours_are_mixed_and_blended() {
+        let grey = Rgb::from_6_bit(32, 32, 32);
+        assert_eq!(Rgb::RED.lerp(Rgb::GREEN, 0), Rgb::RED);
+        assert_eq!(Rgb::RED.lerp(Rgb::GREEN, 128), Rgb::from_6_bit(31, 32, 0));
+        assert_eq!(Rgb::GREEN.lerp(Rgb::RED, 128), Rgb::from_6_bit(32, 31, 0));
+        assert_eq!(grey.saturating_add(grey), Rgb::WHITE);
+        assert_eq!(grey.saturating_add(Rgb::BLACK), grey);
+        assert_eq!(grey.multiply(grey), Rgb::from_6_bit(16, 16, 16));
+        assert_eq!(grey.multiply(Rgb::BLACK), Rgb::BLACK);
+        assert_eq!(Rgb::BLUE.over(grey, 128), Rgb::from_6_bit(16, 16, 48));
+
+        let Rgb(red, green, blue) = Rgb::from_6_bit(1, 2, 3);
+        assert_eq!((red.value(), green.value(), blue.value()), (1, 2, 3));
+        assert_eq!(Rgb::CYAN.red(), Red::new(0));
+        assert_eq!(Rgb::CYAN.blue(), Blue::new(255));
+    }
+
+    #[test]
+    fn gamma_correction_keeps_dim_colours_lit() {
+        assert!(GAMMA.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(GAMMA[63], 63);
+        for level in 1..64 {
+            let Rgb(red, _, _) = Rgb::from_6_bit(level, 0, 0).gamma_correct();
+            assert!(red.value() > 0 && red.value() <= level, "{}", level);
+        }
+        assert_eq!(Rgb::BLACK.gamma_correct(), Rgb::BLACK);
+    }
+
+    #[test]
+    fn brightness_is_scaled_perceptually() {
+        let levels: [u8; 9] = core::array::from_fn(|step| {
+            let Rgb(Red(red), _, _) = Rgb::RED.scale_brightness((step * 32).min(255) as u8);
+            red
+        });
+        assert_eq!(levels, [0, 1, 3, 6, 12, 20, 31, 45, 63]);
+        assert_eq!(Rgb::BLACK.scale_brightness(255), Rgb::BLACK);
+    }
+}
@@ -0,0 +1,481 @@
+use {
+    crate::hal::{
+        log,
+        midi::Port,
+        record,
+        sim::{self, Frame},
+        surface::{Button, Led, Pads},
+        Blue, EntryPoints, Green, Grid, Point, Red, Rgb,
+    },
+    crossterm::{
+        cursor,
+        event::{
+            self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyEventKind,
+            KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+        },
+        execute, queue,
+        style::{self, Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor},
+        terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen},
+    },
+    std::{
+        collections::VecDeque,
+        env, fs,
+        io::{self, Write},
+        path::PathBuf,
+        time::{Duration, Instant},
+    },
+};
+
+/// The space each pad occupies on the terminal, in characters. Pads are drawn one character
+/// narrower and one line shorter than this to leave a gap between them.
+const PAD_WIDTH: u16 = 4;
+const PAD_HEIGHT: u16 = 2;
+
+/// The position of the top left corner of the grid on the terminal.
+const GRID_ORIGIN: (u16, u16) = (2, 1);
+
+/// The column at which the MIDI log is drawn.
+const LOG_COLUMN: u16 = GRID_ORIGIN.0 + Grid::width() as u16 * PAD_WIDTH + 4;
+
+/// The number of outgoing messages kept in the MIDI log.
+const LOG_LENGTH: usize = (Grid::height() as u16 * PAD_HEIGHT) as usize - 2;
+
+/// How often the terminal is redrawn.
+const FRAME_INTERVAL: Duration = Duration::from_millis(16);
+
+/// The amount the aftertouch value changes for each key press or scroll.
+const AFTERTOUCH_STEP: u8 = 8;
+
+/// Run an app registered with the `launchpad_app!` macro in the terminal, until the user quits.
+///
+/// The grid is drawn as coloured blocks, with the setup button in the top left corner. Pads are
+/// pressed by clicking them, or by moving the cursor with the arrow keys and pressing space to
+/// hold or release a pad, or enter to tap it. The number keys choose the velocity, and scrolling
+/// or pressing `+`/`-` while holding a pad changes its aftertouch. `s` taps the setup button and
+/// `q` quits.
+///
+/// If the `LAUNCHPAD_RECORD` environment variable is set, the events the app receives are recorded
+/// to the file it names, so that the session can be replayed later.
+pub fn run(entry_points: EntryPoints) -> io::Result<()> {
+    let recording_path = env::var_os("LAUNCHPAD_RECORD").map(PathBuf::from);
+
+    let _terminal = Terminal::enter()?;
+    let mut emulator = Emulator::new(entry_points, recording_path.is_some());
+    let mut stdout = io::stdout();
+    let result = emulator.run(&mut stdout);
+
+    if let (Some(path), Some(recording)) = (recording_path, emulator.finish_recording()) {
+        fs::write(path, recording)?;
+    }
+    result
+}
+
+/// Puts the terminal into the state needed by the emulator, restoring it when dropped.
+struct Terminal;
+
+impl Terminal {
+    fn enter() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        execute!(
+            io::stdout(),
+            EnterAlternateScreen,
+            EnableMouseCapture,
+            cursor::Hide,
+            Clear(ClearType::All)
+        )?;
+        Ok(Self)
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = execute!(
+            io::stdout(),
+            ResetColor,
+            cursor::Show,
+            DisableMouseCapture,
+            LeaveAlternateScreen
+        );
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// The state of the emulator.
+struct Emulator {
+    entry_points: EntryPoints,
+    adc: sim::Adc,
+    elapsed: u64,
+    cursor: Point,
+    velocity: u8,
+    held: Option<Button>,
+    aftertouch: u8,
+    log: VecDeque<String>,
+    recording: Option<Vec<u8>>,
+    log_decoder: log::Decoder,
+}
+
+impl Emulator {
+    fn new(entry_points: EntryPoints, record: bool) -> Self {
+        sim::reset();
+
+        let adc = sim::Adc::new();
+        (entry_points.init)(adc.as_ptr());
+        if record {
+            record::start(Port::USB);
+        }
+
+        Self {
+            entry_points,
+            adc,
+            elapsed: 0,
+            cursor: Point::new(4, 4),
+            velocity: 127,
+            held: None,
+            aftertouch: 0,
+            log: VecDeque::with_capacity(LOG_LENGTH),
+            recording: record.then(Vec::new),
+            log_decoder: log::Decoder::new(),
+        }
+    }
+
+    /// Run the emulator until the user quits.
+    fn run(&mut self, out: &mut impl Write) -> io::Result<()> {
+        let start = Instant::now();
+        let mut last_draw: Option<Instant> = None;
+        loop {
+            self.advance_to(start.elapsed());
+            self.capture_midi();
+
+            if last_draw.is_none_or(|last_draw| last_draw.elapsed() >= FRAME_INTERVAL) {
+                self.draw(out)?;
+                last_draw = Some(Instant::now());
+            }
+
+            while event::poll(Duration::from_millis(1))? {
+                let carry_on = match event::read()? {
+                    Event::Key(key) => self.key(key),
+                    Event::Mouse(mouse) => {
+                        self.mouse(mouse);
+                        true
+                    }
+                    Event::Resize(..) => {
+                        queue!(out, Clear(ClearType::All))?;
+                        true
+                    }
+                    _ => true,
+                };
+                if !carry_on {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Stop recording, returning the recording if one was being made.
+    fn finish_recording(&mut self) -> Option<Vec<u8>> {
+        record::stop();
+        self.capture_midi();
+        self.recording.take()
+    }
+
+    /// Deliver a timer event for every millisecond that has elapsed.
+    fn advance_to(&mut self, elapsed: Duration) {
+        let elapsed = elapsed.as_millis() as u64;
+        while self.elapsed < elapsed {
+            (self.entry_points.timer)();
+            self.elapsed += 1;
+        }
+    }
+
+    /// Move the outgoing MIDI and SysEx messages into the log.
+    fn capture_midi(&mut self) {
+        let midi = sim::take_midi().into_iter().map(|(port, message)| {
+            format!(
+                "{:<10} {:02X} {:02X} {:02X}",
+                format!("{:?}", port),
+                message.status(),
+                message.data().0,
+                message.data().1
+            )
+        });
+        let recording = &mut self.recording;
+        let log_decoder = &mut self.log_decoder;
+        let sysex = sim::take_sysex()
+            .into_iter()
+            .filter(|(_, data)| match recording {
+                Some(recording) => !record::read_frame(data, recording),
+                None => true,
+            })
+            .filter_map(|(port, data)| {
+                if log::is_frame(&data) {
+                    // log records are shown once all of their frames have arrived
+                    return log_decoder.push(&data).map(|record| record.to_string());
+                }
+                Some(format!(
+                    "{:<10} SysEx, {} bytes",
+                    format!("{:?}", port),
+                    data.len()
+                ))
+            });
+
+        for entry in midi.chain(sysex) {
+            if self.log.len() == LOG_LENGTH {
+                self.log.pop_front();
+            }
+            self.log.push_back(entry);
+        }
+    }
+
+    fn press(&mut self, button: Button) {
+        self.release();
+        self.held = Some(button);
+        self.aftertouch = 0;
+        self.set_pressure(button, self.velocity);
+        self.surface_event(button, self.velocity);
+    }
+
+    fn release(&mut self) {
+        if let Some(button) = self.held.take() {
+            self.set_pressure(button, 0);
+            self.surface_event(button, 0);
+        }
+    }
+
+    fn tap(&mut self, button: Button) {
+        self.press(button);
+        self.release();
+    }
+
+    fn change_aftertouch(&mut self, increase: bool) {
+        if let Some(Button::Pad(point)) = self.held {
+            self.aftertouch = if increase {
+                self.aftertouch.saturating_add(AFTERTOUCH_STEP).min(127)
+            } else {
+                self.aftertouch.saturating_sub(AFTERTOUCH_STEP)
+            };
+            self.set_pressure(Button::Pad(point), self.velocity.max(self.aftertouch));
+            (self.entry_points.aftertouch)(point.to_index(), self.aftertouch);
+        }
+    }
+
+    fn surface_event(&mut self, button: Button, value: u8) {
+        match button {
+            Button::Pad(point) => (self.entry_points.surface)(0, point.to_index(), value),
+            Button::Setup => (self.entry_points.surface)(1, 0, value),
+        }
+    }
+
+    /// Reflect the pressure on a pad in the raw ADC values, which have a 12-bit range.
+    fn set_pressure(&mut self, button: Button, value: u8) {
+        if let Button::Pad(point) = button {
+            if let Some(offset) = Pads::point_to_offset(point) {
+                self.adc.set(offset, (value as u16) << 5);
+            }
+        }
+    }
+
+    /// Handle a key press, returning false if the emulator should quit.
+    fn key(&mut self, key: KeyEvent) -> bool {
+        if key.kind != KeyEventKind::Press {
+            return true;
+        }
+
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return false,
+            KeyCode::Left => self.move_cursor(-1, 0),
+            KeyCode::Right => self.move_cursor(1, 0),
+            KeyCode::Up => self.move_cursor(0, 1),
+            KeyCode::Down => self.move_cursor(0, -1),
+            KeyCode::Char(' ') => {
+                if self.held.is_some() {
+                    self.release();
+                } else if let Some(button) = button_at(self.cursor) {
+                    self.press(button);
+                }
+            }
+            KeyCode::Enter => {
+                if let Some(button) = button_at(self.cursor) {
+                    self.tap(button);
+                }
+            }
+            KeyCode::Char('s') => self.tap(Button::Setup),
+            KeyCode::Char('+') | KeyCode::Char('=') => self.change_aftertouch(true),
+            KeyCode::Char('-') => self.change_aftertouch(false),
+            KeyCode::Char(digit @ '1'..='9') => self.velocity = velocity_for_digit(digit),
+            _ => {}
+        }
+        true
+    }
+
+    fn move_cursor(&mut self, dx: i8, dy: i8) {
+        let x = (self.cursor.x() + dx).clamp(0, Grid::width() as i8 - 1);
+        let y = (self.cursor.y() + dy).clamp(0, Grid::height() as i8 - 1);
+        self.cursor = Point::new(x, y);
+    }
+
+    fn mouse(&mut self, mouse: MouseEvent) {
+        match mouse.kind {
+            MouseEventKind::Down(MouseButton::Left) => {
+                if let Some(point) = point_at(mouse.column, mouse.row) {
+                    self.cursor = point;
+                    if let Some(button) = button_at(point) {
+                        self.press(button);
+                    }
+                }
+            }
+            MouseEventKind::Up(MouseButton::Left) => self.release(),
+            MouseEventKind::ScrollUp => self.change_aftertouch(true),
+            MouseEventKind::ScrollDown => self.change_aftertouch(false),
+            _ => {}
+        }
+    }
+
+    fn draw(&self, out: &mut impl Write) -> io::Result<()> {
+        let frame = sim::frame();
+        for point in Grid::points() {
+            self.draw_pad(out, &frame, point)?;
+        }
+
+        let status_row = GRID_ORIGIN.1 + Grid::height() as u16 * PAD_HEIGHT + 1;
+        queue!(
+            out,
+            ResetColor,
+            cursor::MoveTo(GRID_ORIGIN.0, status_row),
+            Clear(ClearType::UntilNewLine),
+            Print(format!(
+                "velocity {:>3}   aftertouch {:>3}   {:>8.1} s",
+                self.velocity,
+                self.aftertouch,
+                self.elapsed as f64 / 1000.0
+            )),
+            cursor::MoveTo(GRID_ORIGIN.0, status_row + 1),
+            Print("arrows move, space holds, enter taps, s setup, 1-9 velocity, +/- aftertouch, q quits"),
+            cursor::MoveTo(LOG_COLUMN, GRID_ORIGIN.1),
+            style::SetAttribute(style::Attribute::Bold),
+            Print("MIDI out"),
+            style::SetAttribute(style::Attribute::Reset),
+        )?;
+
+        for row in 0..LOG_LENGTH {
+            queue!(
+                out,
+                cursor::MoveTo(LOG_COLUMN, GRID_ORIGIN.1 + 2 + row as u16),
+                Clear(ClearType::UntilNewLine),
+                Print(self.log.get(row).map_or("", String::as_str)),
+            )?;
+        }
+
+        out.flush()
+    }
+
+    fn draw_pad(&self, out: &mut impl Write, frame: &Frame, point: Point) -> io::Result<()> {
+        let column = GRID_ORIGIN.0 + point.x() as u16 * PAD_WIDTH;
+        let row = GRID_ORIGIN.1 + (Grid::height() - 1 - point.y() as u8) as u16 * PAD_HEIGHT;
+
+        let color = match button_at(point) {
+            Some(Button::Pad(point)) => terminal_color(frame.led(point)),
+            Some(Button::Setup) => terminal_color(frame.led(Led::Setup)),
+            None => Color::Reset,
+        };
+        let label = if point == self.cursor { "[ ]" } else { "   " };
+
+        queue!(
+            out,
+            cursor::MoveTo(column, row),
+            SetBackgroundColor(color),
+            SetForegroundColor(Color::White),
+            Print(label),
+            ResetColor,
+        )
+    }
+}
+
+/// Returns the button drawn at a point on the grid. The setup button occupies the top left
+/// corner, the other corners are empty.
+fn button_at(point: Point) -> Option<Button> {
+    let (right, top) = (Grid::width() as i8 - 1, Grid::height() as i8 - 1);
+    match (point.x(), point.y()) {
+        (0, y) if y == top => Some(Button::Setup),
+        (0, 0) => None,
+        (x, 0) if x == right => None,
+        (x, y) if x == right && y == top => None,
+        _ => Some(Button::Pad(point)),
+    }
+}
+
+/// Returns the point on the grid drawn at a position on the terminal.
+fn point_at(column: u16, row: u16) -> Option<Point> {
+    let x = column.checked_sub(GRID_ORIGIN.0)? / PAD_WIDTH;
+    let y = row.checked_sub(GRID_ORIGIN.1)? / PAD_HEIGHT;
+    if x < Grid::width() as u16 && y < Grid::height() as u16 {
+        Some(Point::new(x as i8, (Grid::height() as u16 - 1 - y) as i8))
+    } else {
+        None
+    }
+}
+
+/// Map the digits 1 to 9 across the velocity range.
+fn velocity_for_digit(digit: char) -> u8 {
+    let digit = digit.to_digit(10).unwrap_or(9) as u16;
+    (digit * 127 / 9) as u8
+}
+
+/// Convert an LED colour to a terminal colour. LEDs that are off are drawn in dark grey so that
+/// the pads remain visible.
+fn terminal_color(Rgb(Red(red), Green(green), Blue(blue)): Rgb) -> Color {
+    let convert_to_8_bit = |led: u8| ((255 * led as u16) / 63) as u8;
+    match (red, green, blue) {
+        (0, 0, 0) => Color::Rgb {
+            r: 40,
+            g: 40,
+            b: 40,
+        },
+        (red, green, blue) => Color::Rgb {
+            r: convert_to_8_bit(red),
+            g: convert_to_8_bit(green),
+            b: convert_to_8_bit(blue),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn setup_button_is_in_the_top_left_corner() {
+        assert_eq!(button_at(Point::new(0, 9)), Some(Button::Setup));
+        assert_eq!(button_at(Point::new(0, 0)), None);
+        assert_eq!(button_at(Point::new(9, 0)), None);
+        assert_eq!(button_at(Point::new(9, 9)), None);
+        assert_eq!(
+            button_at(Point::new(1, 9)),
+            Some(Button::Pad(Point::new(1, 9)))
+        );
+    }
+
+    #[test]
+    fn terminal_positions_map_to_points() {
+        assert_eq!(point_at(0, 0), None);
+        assert_eq!(
+            point_at(GRID_ORIGIN.0, GRID_ORIGIN.1),
+            Some(Point::new(0, 9))
+        );
+        assert_eq!(
+            point_at(
+                GRID_ORIGIN.0 + PAD_WIDTH * 3 + 1,
+                GRID_ORIGIN.1 + PAD_HEIGHT * 9 + 1
+            ),
+            Some(Point::new(3, 0))
+        );
+        assert_eq!(point_at(LOG_COLUMN, GRID_ORIGIN.1), None);
+    }
+
+    #[test]
+    fn digits_span_the_velocity_range() {
+        assert_eq!(velocity_for_digit('1'), 14);
+        assert_eq!(velocity_for_digit('5'), 70);
+        assert_eq!(velocity_for_digit('9'), 127);
+    }
+}
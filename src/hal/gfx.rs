@@ -0,0 +1,216 @@
+pub mod animation;
+mod draw;
+mod text;
+
+pub use {
+    draw::{Bitmap, Canvas},
+    text::{Font, Glyph, Marquee, FONT_3X5},
+};
+
+use crate::hal::{
+    surface::{self, Led},
+    Grid, Point, Rgb,
+};
+
+/// The number of LEDs held by a frame buffer: every pad on the grid, followed by the setup button.
+const LEDS: usize = Grid::size() as usize + 1;
+
+/// The index of the setup button's LED in a frame buffer.
+const SETUP: usize = Grid::size() as usize;
+
+/// A double buffered image of the LEDs on the surface. Apps draw into the back buffer, and
+/// [`FrameBuffer::flush`] then sends only the LEDs that differ from the last frame shown, so a
+/// partly drawn frame is never visible.
+///
+/// # Example
+///
+/// ```
+/// use launchpad_pro_rs::hal::{gfx::FrameBuffer, surface::read_led, Point, Rgb};
+///
+/// let mut frame = FrameBuffer::new();
+/// frame.set(Point::new(4, 4), Rgb::RED);
+/// assert_eq!(read_led(Point::new(4, 4)), Some(Rgb::BLACK));
+///
+/// frame.flush();
+/// assert_eq!(read_led(Point::new(4, 4)), Some(Rgb::RED));
+/// ```
+#[derive(Clone, Debug)]
+pub struct FrameBuffer {
+    /// The frame being drawn.
+    back: [Rgb; LEDS],
+    /// The frame last sent to the LEDs.
+    front: [Rgb; LEDS],
+    /// The LEDs that have been drawn since the last flush, one bit each.
+    dirty: u128,
+    /// Whether the LEDs may no longer match the front buffer, so that every LED must be sent.
+    stale: bool,
+}
+
+impl FrameBuffer {
+    /// Construct a black frame buffer. The first flush sends every LED, as the LEDs may have
+    /// been set without it.
+    pub const fn new() -> Self {
+        Self {
+            back: [Rgb::BLACK; LEDS],
+            front: [Rgb::BLACK; LEDS],
+            dirty: 0,
+            stale: true,
+        }
+    }
+
+    /// Returns the colour of an LED in the frame being drawn.
+    pub fn get(&self, led: impl Into<Led>) -> Rgb {
+        self.back[Self::index(led.into())]
+    }
+
+    /// Set the colour of an LED in the frame being drawn.
+    pub fn set(&mut self, led: impl Into<Led>, rgb: Rgb) {
+        let index = Self::index(led.into());
+        self.back[index] = rgb;
+        self.dirty |= 1 << index;
+    }
+
+    /// Set every LED in the frame being drawn to a colour, including the setup button.
+    pub fn fill(&mut self, rgb: Rgb) {
+        self.back = [rgb; LEDS];
+        self.dirty = !0;
+    }
+
+    /// Set every LED in the frame being drawn to black.
+    pub fn clear(&mut self) {
+        self.fill(Rgb::BLACK);
+    }
+
+    /// Returns the colour of an LED in the frame last shown.
+    pub fn shown(&self, led: impl Into<Led>) -> Rgb {
+        self.front[Self::index(led.into())]
+    }
+
+    /// Returns whether the frame being drawn differs from the frame last shown.
+    pub fn is_dirty(&self) -> bool {
+        self.stale || self.changes().next().is_some()
+    }
+
+    /// Send every LED on the next flush, for example after something else has set the LEDs.
+    pub fn invalidate(&mut self) {
+        self.stale = true;
+    }
+
+    /// Show the frame that has been drawn, sending only the LEDs that have changed since the last
+    /// frame was shown. Returns the number of LEDs that were sent. Drawing continues from the
+    /// frame that was shown.
+    pub fn flush(&mut self) -> usize {
+        let mut sent = 0;
+        for index in 0..LEDS {
+            let changed = self.back[index] != self.front[index];
+            if self.stale || (self.dirty & (1 << index) != 0 && changed) {
+                surface::set_led(Self::led(index), self.back[index]);
+                self.front[index] = self.back[index];
+                sent += 1;
+            }
+        }
+        self.dirty = 0;
+        self.stale = false;
+        sent
+    }
+
+    /// Returns the LEDs that have been drawn in a different colour to the frame last shown.
+    fn changes(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..LEDS).filter(move |&index| {
+            self.dirty & (1 << index) != 0 && self.back[index] != self.front[index]
+        })
+    }
+
+    fn index(led: Led) -> usize {
+        match led {
+            Led::Pad(point) => point.to_index() as usize,
+            Led::Setup => SETUP,
+        }
+    }
+
+    fn led(index: usize) -> Led {
+        if index == SETUP {
+            Led::Setup
+        } else {
+            Led::Pad(Point::from_index(index as u8))
+        }
+    }
+}
+
+impl Default for FrameBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Canvas for FrameBuffer {
+    fn pixel(&self, point: Point) -> Rgb {
+        self.get(point)
+    }
+
+    fn set_pixel(&mut self, point: Point, rgb: Rgb) {
+        self.set(point, rgb);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::hal::sim};
+
+    #[test]
+    fn nothing_is_shown_until_the_frame_is_flushed() {
+        sim::reset();
+        let mut frame = FrameBuffer::new();
+        frame.fill(Rgb::BLUE);
+        frame.set(Point::new(2, 3), Rgb::RED);
+        frame.set(Led::Setup, Rgb::GREEN);
+        assert_eq!(sim::frame(), sim::Frame::default());
+
+        assert_eq!(frame.flush(), LEDS);
+        let shown = sim::frame();
+        assert_eq!(shown.led(Point::new(2, 3)), Rgb::RED);
+        assert_eq!(shown.led(Point::new(9, 9)), Rgb::BLUE);
+        assert_eq!(shown.led(Led::Setup), Rgb::GREEN);
+        assert_eq!(frame.shown(Point::new(2, 3)), Rgb::RED);
+    }
+
+    #[test]
+    fn only_changed_leds_are_sent() {
+        sim::reset();
+        let mut frame = FrameBuffer::new();
+        frame.flush();
+        assert!(!frame.is_dirty());
+
+        // redrawing the same frame sends nothing
+        for point in Grid::points() {
+            frame.set(point, Rgb::BLACK);
+        }
+        assert!(!frame.is_dirty());
+        assert_eq!(frame.flush(), 0);
+
+        frame.set(Point::new(1, 1), Rgb::WHITE);
+        frame.set(Point::new(8, 8), Rgb::WHITE);
+        frame.set(Point::new(5, 5), Rgb::WHITE);
+        frame.set(Point::new(5, 5), Rgb::BLACK);
+        assert!(frame.is_dirty());
+        assert_eq!(frame.flush(), 2);
+        assert_eq!(frame.get(Point::new(1, 1)), Rgb::WHITE);
+
+        frame.clear();
+        assert_eq!(frame.flush(), 2);
+        assert_eq!(sim::frame(), sim::Frame::default());
+    }
+
+    #[test]
+    fn an_invalidated_frame_is_sent_in_full() {
+        sim::reset();
+        let mut frame = FrameBuffer::new();
+        frame.flush();
+
+        surface::set_led(Point::new(0, 0), Rgb::RED);
+        frame.invalidate();
+        assert!(frame.is_dirty());
+        assert_eq!(frame.flush(), LEDS);
+        assert_eq!(sim::frame().led(Point::new(0, 0)), Rgb::BLACK);
+    }
+}
@@ -0,0 +1,753 @@
+//! Animate the LEDs with keyframes, without keeping track of time in the app.
+//!
+//! An [`Animator`] runs many animations at once, each on a single LED, a rectangle of pads or a
+//! ripple spreading out from a pad. Every time it is flushed it draws the app's frame, blends the
+//! current colour of each animation over it, and sends the LEDs that have changed.
+//!
+//! # Example
+//!
+//! ```
+//! use launchpad_pro_rs::hal::{
+//!     gfx::{
+//!         animation::{Animation, Animator, Blend, Easing, Keyframe, Repeat, Target},
+//!         FrameBuffer,
+//!     },
+//!     Point, Rgb,
+//! };
+//!
+//! const PULSE: [Keyframe; 3] = [
+//!     Keyframe::new(0, Rgb::BLACK, Easing::Linear),
+//!     Keyframe::new(500, Rgb::RED, Easing::EaseOut),
+//!     Keyframe::new(1000, Rgb::BLACK, Easing::EaseIn),
+//! ];
+//!
+//! let mut frame = FrameBuffer::new();
+//! let mut animator = Animator::new();
+//! animator
+//!     .start(
+//!         Target::Led(Point::new(0, 0).into()),
+//!         Animation::new(&PULSE).repeat(Repeat::Forever),
+//!     )
+//!     .unwrap();
+//! animator
+//!     .start(
+//!         Target::Ripple {
+//!             centre: Point::new(4, 4),
+//!             interval: 50,
+//!         },
+//!         Animation::tween(Rgb::WHITE, Rgb::BLACK, 300, Easing::Linear).blend(Blend::Add),
+//!     )
+//!     .unwrap();
+//!
+//! // in timer_event
+//! animator.flush(&frame);
+//! ```
+
+use {
+    crate::hal::{
+        gfx::{FrameBuffer, LEDS},
+        surface::Led,
+        time, Blue, Green, Grid, Point, Red, Rgb,
+    },
+    core::fmt,
+};
+
+/// The number of animations that can run at once.
+pub const CAPACITY: usize = 32;
+
+/// The largest value of a colour component, which are stored with 6 bits.
+const MAX: u8 = 63;
+
+/// Identifies an animation that has been started, so that it can be stopped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AnimationId(u16);
+
+/// The reasons an animation cannot be started.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The maximum number of animations are already running.
+    Full,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Full => write!(f, "all {} animations are running", CAPACITY),
+        }
+    }
+}
+
+/// How an animation changes speed between two keyframes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Easing {
+    /// Change at a constant speed.
+    Linear,
+    /// Start slowly and speed up.
+    EaseIn,
+    /// Start quickly and slow down.
+    EaseOut,
+    /// Start slowly, speed up, then slow down again.
+    EaseInOut,
+    /// Hold the previous colour, then change all at once.
+    Step,
+}
+
+impl Easing {
+    /// Returns how far the colour has changed, from 0 to 1, after a fraction of the time between
+    /// two keyframes.
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t * t,
+            Easing::EaseOut => {
+                let u = 1.0 - t;
+                1.0 - u * u * u
+            }
+            Easing::EaseInOut if t < 0.5 => 4.0 * t * t * t,
+            Easing::EaseInOut => {
+                let u = 2.0 - 2.0 * t;
+                1.0 - u * u * u / 2.0
+            }
+            Easing::Step if t < 1.0 => 0.0,
+            Easing::Step => 1.0,
+        }
+    }
+}
+
+/// How the colour of an animation is combined with the colour beneath it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Blend {
+    /// Show the colour of the animation.
+    Replace,
+    /// Add the colours, so that the animation brightens the colour beneath it.
+    Add,
+    /// Multiply the colours, so that the animation darkens the colour beneath it.
+    Multiply,
+    /// Invert, multiply and invert the colours, which brightens more gently than adding.
+    Screen,
+    /// Take the brighter of each component.
+    Lighten,
+    /// Take the darker of each component.
+    Darken,
+    /// Mix the colour of the animation with the colour beneath it, from 0 showing only the colour
+    /// beneath to 255 showing only the animation.
+    Mix(u8),
+}
+
+impl Blend {
+    /// Combine the colour of an animation with the colour beneath it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use launchpad_pro_rs::hal::{gfx::animation::Blend, Rgb};
+    ///
+    /// assert_eq!(Blend::Add.apply(Rgb::RED, Rgb::GREEN), Rgb::YELLOW);
+    /// assert_eq!(Blend::Multiply.apply(Rgb::YELLOW, Rgb::RED), Rgb::RED);
+    /// ```
+    pub fn apply(self, below: Rgb, above: Rgb) -> Rgb {
+        let component = |below: u8, above: u8| -> u8 {
+            let (below, above) = (below as u16, above as u16);
+            let max = MAX as u16;
+            let value = match self {
+                Blend::Screen => max - (max - below) * (max - above) / max,
+                Blend::Lighten => below.max(above),
+                _ => below.min(above),
+            };
+            value as u8
+        };
+        match self {
+            Blend::Replace => above,
+            Blend::Add => below.saturating_add(above),
+            Blend::Multiply => below.multiply(above),
+            Blend::Mix(alpha) => above.over(below, alpha),
+            Blend::Screen | Blend::Lighten | Blend::Darken => {
+                let (Rgb(Red(r0), Green(g0), Blue(b0)), Rgb(Red(r1), Green(g1), Blue(b1))) =
+                    (below, above);
+                Rgb(
+                    Red(component(r0, r1)),
+                    Green(component(g0, g1)),
+                    Blue(component(b0, b1)),
+                )
+            }
+        }
+    }
+}
+
+/// A colour at a point in an animation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Keyframe {
+    time: u32,
+    rgb: Rgb,
+    easing: Easing,
+}
+
+impl Keyframe {
+    /// Construct a keyframe at a number of milliseconds from the start of the animation. The
+    /// easing is used on the way to this keyframe from the one before it.
+    pub const fn new(time: u32, rgb: Rgb, easing: Easing) -> Self {
+        Self { time, rgb, easing }
+    }
+}
+
+/// How many times an animation is played.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Repeat {
+    /// Play the animation once.
+    Once,
+    /// Play the animation a number of times.
+    Times(u32),
+    /// Play the animation until it is stopped.
+    Forever,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Keyframes {
+    Static(&'static [Keyframe]),
+    Tween([Keyframe; 2]),
+}
+
+/// A sequence of keyframes, and how to play them.
+#[derive(Clone, Copy, Debug)]
+pub struct Animation {
+    keyframes: Keyframes,
+    blend: Blend,
+    repeat: Repeat,
+    delay: u32,
+}
+
+impl Animation {
+    /// Construct an animation that plays keyframes once, replacing the colour beneath it. The
+    /// keyframes must be in order of time. Before the time of the first keyframe its colour is
+    /// shown.
+    pub const fn new(keyframes: &'static [Keyframe]) -> Self {
+        Self::with_keyframes(Keyframes::Static(keyframes))
+    }
+
+    /// Construct an animation that changes from one colour to another over a number of
+    /// milliseconds.
+    pub const fn tween(from: Rgb, to: Rgb, duration: u32, easing: Easing) -> Self {
+        Self::with_keyframes(Keyframes::Tween([
+            Keyframe::new(0, from, Easing::Linear),
+            Keyframe::new(duration, to, easing),
+        ]))
+    }
+
+    const fn with_keyframes(keyframes: Keyframes) -> Self {
+        Self {
+            keyframes,
+            blend: Blend::Replace,
+            repeat: Repeat::Once,
+            delay: 0,
+        }
+    }
+
+    /// Set how the animation is combined with the colour beneath it.
+    pub const fn blend(mut self, blend: Blend) -> Self {
+        self.blend = blend;
+        self
+    }
+
+    /// Set how many times the animation is played.
+    pub const fn repeat(mut self, repeat: Repeat) -> Self {
+        self.repeat = repeat;
+        self
+    }
+
+    /// Wait a number of milliseconds after the animation is started before playing it.
+    pub const fn delay(mut self, delay: u32) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    fn keyframes(&self) -> &[Keyframe] {
+        match &self.keyframes {
+            Keyframes::Static(keyframes) => keyframes,
+            Keyframes::Tween(keyframes) => keyframes,
+        }
+    }
+
+    /// Returns the number of milliseconds it takes to play the keyframes once.
+    pub fn duration(&self) -> u32 {
+        self.keyframes().last().map_or(0, |keyframe| keyframe.time)
+    }
+
+    /// Returns the number of milliseconds from the start of the animation until it finishes, or
+    /// None if it repeats forever.
+    fn length(&self) -> Option<u32> {
+        let plays = match self.repeat {
+            Repeat::Once => 1,
+            Repeat::Times(count) => count,
+            Repeat::Forever => return None,
+        };
+        Some(
+            self.delay
+                .saturating_add(self.duration().max(1).saturating_mul(plays)),
+        )
+    }
+
+    /// Returns the colour of the animation a number of milliseconds after it started, or None if
+    /// it is not playing at that time.
+    pub fn sample(&self, elapsed: u32) -> Option<Rgb> {
+        if self.length().is_some_and(|length| elapsed >= length) {
+            return None;
+        }
+        let elapsed = elapsed.checked_sub(self.delay)?;
+
+        let keyframes = self.keyframes();
+        let time = match self.duration() {
+            0 => 0,
+            duration => elapsed % duration,
+        };
+        let next = keyframes.iter().position(|keyframe| keyframe.time > time);
+        match next {
+            None => keyframes.last().map(|keyframe| keyframe.rgb),
+            Some(0) => Some(keyframes[0].rgb),
+            Some(index) => {
+                let (from, to) = (&keyframes[index - 1], &keyframes[index]);
+                let t = (time - from.time) as f32 / (to.time - from.time) as f32;
+                let amount = to.easing.apply(t) * 255.0 + 0.5;
+                Some(from.rgb.lerp(to.rgb, amount as u8))
+            }
+        }
+    }
+}
+
+/// The LEDs that an animation is shown on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Target {
+    /// A single LED.
+    Led(Led),
+    /// A rectangle of pads with its bottom left corner at a position. The parts of the rectangle
+    /// that are not on the grid are ignored.
+    Rect {
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+    },
+    /// Every pad on the grid, starting at a pad and spreading out in square rings. Each ring
+    /// starts playing the animation a number of milliseconds after the ring inside it.
+    Ripple { centre: Point, interval: u32 },
+}
+
+impl Target {
+    /// Returns the number of milliseconds after the target starts that an LED starts, or None if
+    /// the LED is not part of the target.
+    fn offset(&self, led: Led) -> Option<u32> {
+        match (*self, led) {
+            (Target::Led(target), led) => (target == led).then_some(0),
+            (Target::Rect { .. }, Led::Setup) | (Target::Ripple { .. }, Led::Setup) => None,
+            (
+                Target::Rect {
+                    x,
+                    y,
+                    width,
+                    height,
+                },
+                Led::Pad(point),
+            ) => {
+                let (px, py) = (point.x() as i32, point.y() as i32);
+                let (columns, rows) = (x..x.saturating_add(width), y..y.saturating_add(height));
+                (columns.contains(&px) && rows.contains(&py)).then_some(0)
+            }
+            (Target::Ripple { centre, interval }, Led::Pad(point)) => {
+                let distance = (point.x() as i32 - centre.x() as i32)
+                    .abs()
+                    .max((point.y() as i32 - centre.y() as i32).abs());
+                Some((distance as u32).saturating_mul(interval))
+            }
+        }
+    }
+
+    /// Returns the number of milliseconds after the target starts that the last LED starts.
+    fn spread(&self) -> u32 {
+        match *self {
+            Target::Ripple { centre, interval } => {
+                let furthest = (centre.x() as i32)
+                    .max(Grid::width() as i32 - 1 - centre.x() as i32)
+                    .max(centre.y() as i32)
+                    .max(Grid::height() as i32 - 1 - centre.y() as i32);
+                (furthest.max(0) as u32).saturating_mul(interval)
+            }
+            _ => 0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Running {
+    id: AnimationId,
+    target: Target,
+    animation: Animation,
+    started: u32,
+}
+
+impl Running {
+    fn is_finished(&self, now: u32) -> bool {
+        self.animation.length().is_some_and(|length| {
+            now.wrapping_sub(self.started) >= length.saturating_add(self.target.spread())
+        })
+    }
+}
+
+/// Runs animations on the LEDs, and shows them over a frame drawn by the app. Animations started
+/// later are shown above those started earlier. Once an animation has finished it is removed, and
+/// the LEDs show the app's frame again.
+#[derive(Clone, Debug)]
+pub struct Animator {
+    /// The running animations in the order they were started.
+    running: [Option<Running>; CAPACITY],
+    next_id: u16,
+    /// The app's frame with the animations shown over it.
+    frame: FrameBuffer,
+}
+
+impl Animator {
+    /// Construct an animator with no animations running.
+    pub const fn new() -> Self {
+        Self {
+            running: [None; CAPACITY],
+            next_id: 0,
+            frame: FrameBuffer::new(),
+        }
+    }
+
+    /// Start playing an animation on a target, returning an ID that can be used to stop it.
+    pub fn start(&mut self, target: Target, animation: Animation) -> Result<AnimationId, Error> {
+        let slot = self
+            .running
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(Error::Full)?;
+        let id = AnimationId(self.next_id);
+        self.next_id = self.next_id.wrapping_add(1);
+        *slot = Some(Running {
+            id,
+            target,
+            animation,
+            started: time::now(),
+        });
+        Ok(id)
+    }
+
+    /// Stop an animation. Returns false if the animation is not running.
+    pub fn stop(&mut self, id: AnimationId) -> bool {
+        match self
+            .running
+            .iter()
+            .position(|slot| slot.is_some_and(|running| running.id == id))
+        {
+            Some(index) => {
+                self.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Stop every animation.
+    pub fn stop_all(&mut self) {
+        self.running = [None; CAPACITY];
+    }
+
+    /// Returns whether an animation is still running.
+    pub fn is_running(&self, id: AnimationId) -> bool {
+        let now = time::now();
+        self.running
+            .iter()
+            .flatten()
+            .any(|running| running.id == id && !running.is_finished(now))
+    }
+
+    /// Returns the number of animations that are running.
+    pub fn len(&self) -> usize {
+        let now = time::now();
+        self.running
+            .iter()
+            .flatten()
+            .filter(|running| !running.is_finished(now))
+            .count()
+    }
+
+    /// Returns whether no animations are running.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Show the animations over a frame drawn by the app, sending only the LEDs that have changed.
+    /// Returns the number of LEDs that were sent. Call this from the app's `timer_event` instead
+    /// of flushing the app's frame.
+    pub fn flush(&mut self, base: &FrameBuffer) -> usize {
+        let now = time::now();
+        let mut index = 0;
+        while let Some(running) = self.running.get(index).copied().flatten() {
+            if running.is_finished(now) {
+                self.remove(index);
+            } else {
+                index += 1;
+            }
+        }
+
+        for index in 0..LEDS {
+            let led = FrameBuffer::led(index);
+            let rgb = self
+                .running
+                .iter()
+                .flatten()
+                .fold(base.back[index], |below, running| {
+                    let elapsed = now.wrapping_sub(running.started);
+                    running
+                        .target
+                        .offset(led)
+                        .and_then(|offset| running.animation.sample(elapsed.checked_sub(offset)?))
+                        .map_or(below, |above| running.animation.blend.apply(below, above))
+                });
+            self.frame.set(led, rgb);
+        }
+        self.frame.flush()
+    }
+
+    /// Send every LED on the next flush, for example after something else has set the LEDs.
+    pub fn invalidate(&mut self) {
+        self.frame.invalidate();
+    }
+
+    /// Remove a running animation, keeping the rest in the order they were started.
+    fn remove(&mut self, index: usize) {
+        self.running[index..].rotate_left(1);
+        self.running[CAPACITY - 1] = None;
+    }
+}
+
+impl Default for Animator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::hal::sim};
+
+    const GREY: Rgb = Rgb(Red(32), Green(32), Blue(32));
+
+    const PULSE: [Keyframe; 3] = [
+        Keyframe::new(0, Rgb::BLACK, Easing::Linear),
+        Keyframe::new(100, Rgb::RED, Easing::Linear),
+        Keyframe::new(200, Rgb::BLACK, Easing::Linear),
+    ];
+
+    #[test]
+    fn easing_curves_start_at_zero_and_end_at_one() {
+        for easing in [
+            Easing::Linear,
+            Easing::EaseIn,
+            Easing::EaseOut,
+            Easing::EaseInOut,
+            Easing::Step,
+        ] {
+            assert_eq!(easing.apply(0.0), 0.0, "{:?}", easing);
+            assert_eq!(easing.apply(1.0), 1.0, "{:?}", easing);
+        }
+        assert!(Easing::EaseIn.apply(0.5) < 0.5);
+        assert!(Easing::EaseOut.apply(0.5) > 0.5);
+        assert_eq!(Easing::EaseInOut.apply(0.5), 0.5);
+        assert_eq!(Easing::Step.apply(0.99), 0.0);
+    }
+
+    #[test]
+    fn keyframes_are_interpolated() {
+        let animation = Animation::new(&PULSE).delay(10);
+        assert_eq!(animation.sample(0), None);
+        assert_eq!(animation.sample(10), Some(Rgb::BLACK));
+        assert_eq!(animation.sample(60), Some(Rgb(Red(32), Green(0), Blue(0))));
+        assert_eq!(animation.sample(110), Some(Rgb::RED));
+        assert_eq!(animation.sample(160), Some(Rgb(Red(31), Green(0), Blue(0))));
+        assert_eq!(animation.sample(210), None);
+
+        let animation = animation.repeat(Repeat::Times(2));
+        assert_eq!(animation.sample(310), Some(Rgb::RED));
+        assert_eq!(animation.sample(410), None);
+        assert_eq!(
+            animation.repeat(Repeat::Forever).sample(100_110),
+            Some(Rgb::RED)
+        );
+    }
+
+    #[test]
+    fn colours_are_blended() {
+        assert_eq!(Blend::Replace.apply(GREY, Rgb::RED), Rgb::RED);
+        assert_eq!(
+            Blend::Add.apply(GREY, Rgb::RED),
+            Rgb(Red(63), Green(32), Blue(32))
+        );
+        assert_eq!(
+            Blend::Multiply.apply(GREY, Rgb::RED),
+            Rgb(Red(32), Green(0), Blue(0))
+        );
+        assert_eq!(Blend::Screen.apply(GREY, Rgb::BLACK), GREY);
+        assert_eq!(
+            Blend::Lighten.apply(GREY, Rgb::RED),
+            Rgb(Red(63), Green(32), Blue(32))
+        );
+        assert_eq!(
+            Blend::Darken.apply(GREY, Rgb::RED),
+            Rgb(Red(32), Green(0), Blue(0))
+        );
+        assert_eq!(Blend::Mix(0).apply(GREY, Rgb::RED), GREY);
+        assert_eq!(Blend::Mix(255).apply(GREY, Rgb::RED), Rgb::RED);
+    }
+
+    #[test]
+    fn animations_are_shown_over_the_frame_until_they_finish() {
+        let mut simulator = sim::Simulator::idle();
+        let mut base = FrameBuffer::new();
+        base.fill(GREY);
+        let mut animator = Animator::new();
+
+        let pad = animator
+            .start(
+                Target::Led(Point::new(1, 1).into()),
+                Animation::new(&PULSE).repeat(Repeat::Times(2)),
+            )
+            .unwrap();
+        animator
+            .start(
+                Target::Rect {
+                    x: 0,
+                    y: 0,
+                    width: 3,
+                    height: 3,
+                },
+                Animation::tween(Rgb::BLACK, Rgb::BLUE, 100, Easing::Linear)
+                    .blend(Blend::Add)
+                    .delay(100),
+            )
+            .unwrap();
+        assert_eq!(animator.len(), 2);
+        animator.flush(&base);
+        assert_eq!(sim::frame().led(Point::new(1, 1)), Rgb::BLACK);
+        assert_eq!(sim::frame().led(Point::new(2, 2)), GREY);
+
+        simulator.advance(150);
+        animator.flush(&base);
+        let frame = sim::frame();
+        assert_eq!(
+            frame.led(Point::new(1, 1)),
+            Rgb(Red(31), Green(0), Blue(32))
+        );
+        assert_eq!(
+            frame.led(Point::new(2, 2)),
+            Rgb(Red(32), Green(32), Blue(63))
+        );
+        assert_eq!(frame.led(Point::new(3, 3)), GREY);
+        assert_eq!(frame.led(Led::Setup), GREY);
+
+        // the tween has finished, and the pulse is playing for the second time
+        simulator.advance(150);
+        animator.flush(&base);
+        assert_eq!(animator.len(), 1);
+        assert!(animator.is_running(pad));
+        assert_eq!(sim::frame().led(Point::new(1, 1)), Rgb::RED);
+        assert_eq!(sim::frame().led(Point::new(2, 2)), GREY);
+
+        simulator.advance(100);
+        animator.flush(&base);
+        assert!(animator.is_empty());
+        assert!(!animator.is_running(pad));
+        assert_eq!(sim::frame().led(Point::new(1, 1)), GREY);
+    }
+
+    #[test]
+    fn ripples_spread_out_from_a_pad() {
+        let mut simulator = sim::Simulator::idle();
+        let base = FrameBuffer::new();
+        let mut animator = Animator::new();
+        let ripple = animator
+            .start(
+                Target::Ripple {
+                    centre: Point::new(1, 1),
+                    interval: 10,
+                },
+                Animation::tween(Rgb::WHITE, Rgb::WHITE, 10, Easing::Linear),
+            )
+            .unwrap();
+
+        for ring in 0..=8 {
+            animator.flush(&base);
+            let frame = sim::frame();
+            for point in Grid::points() {
+                let distance = (point.x() - 1).abs().max((point.y() - 1).abs());
+                let lit = if distance == ring {
+                    Rgb::WHITE
+                } else {
+                    Rgb::BLACK
+                };
+                assert_eq!(frame.led(point), lit, "{:?} in ring {}", point, ring);
+            }
+            simulator.advance(10);
+        }
+        assert!(!animator.is_running(ripple));
+
+        // the newest animation is shown on top, and stopping it shows the one beneath
+        let below = animator
+            .start(
+                Target::Led(Led::Setup),
+                Animation::tween(Rgb::RED, Rgb::RED, 10, Easing::Step).repeat(Repeat::Forever),
+            )
+            .unwrap();
+        let above = animator
+            .start(
+                Target::Led(Led::Setup),
+                Animation::tween(Rgb::GREEN, Rgb::GREEN, 10, Easing::Step).repeat(Repeat::Forever),
+            )
+            .unwrap();
+        animator.flush(&base);
+        assert_eq!(sim::frame().led(Led::Setup), Rgb::GREEN);
+        assert!(animator.stop(above));
+        assert!(!animator.stop(above));
+        animator.flush(&base);
+        assert_eq!(sim::frame().led(Led::Setup), Rgb::RED);
+        assert!(animator.is_running(below));
+        animator.stop_all();
+        assert!(animator.is_empty());
+    }
+
+    #[test]
+    fn too_many_animations_cannot_be_started() {
+        let mut animator = Animator::new();
+        let target = Target::Led(Led::Setup);
+        let animation = Animation::new(&PULSE);
+        for _ in 0..CAPACITY {
+            animator.start(target, animation).unwrap();
+        }
+        assert_eq!(animator.start(target, animation), Err(Error::Full));
+    }
+
+    #[test]
+    fn huge_targets_do_not_overflow() {
+        let pad = Led::Pad(Point::new(9, 9));
+        let rect = |x, y| Target::Rect {
+            x,
+            y,
+            width: i32::MAX,
+            height: i32::MAX,
+        };
+        assert_eq!(rect(i32::MAX, 0).offset(pad), None);
+        assert_eq!(rect(0, i32::MAX).offset(pad), None);
+        assert_eq!(rect(0, 0).offset(pad), Some(0));
+
+        let ripple = Target::Ripple {
+            centre: Point::new(0, 0),
+            interval: u32::MAX / 4,
+        };
+        assert_eq!(ripple.offset(pad), Some(u32::MAX));
+        assert_eq!(
+            ripple.offset(Led::Pad(Point::new(0, 1))),
+            Some(u32::MAX / 4)
+        );
+        assert_eq!(ripple.spread(), u32::MAX);
+    }
+}
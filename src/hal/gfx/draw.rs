@@ -0,0 +1,451 @@
+use crate::hal::{gfx::Font, Grid, Point, Rgb};
+
+/// A small image that can be drawn onto a [`Canvas`]. Pixels that are None are transparent.
+///
+/// # Example
+///
+/// ```
+/// use launchpad_pro_rs::hal::{gfx::Bitmap, Rgb};
+///
+/// const R: Option<Rgb> = Some(Rgb::RED);
+/// const HEART: Bitmap = Bitmap::new(
+///     5,
+///     &[
+///         None, R, None, R, None, //
+///         R, R, R, R, R, //
+///         None, R, R, R, None, //
+///         None, None, R, None, None,
+///     ],
+/// );
+/// assert_eq!(HEART.height(), 4);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Bitmap<'a> {
+    width: usize,
+    pixels: &'a [Option<Rgb>],
+}
+
+impl<'a> Bitmap<'a> {
+    /// Construct a bitmap from its pixels, given row by row from the top. Any pixels that do not
+    /// make up a full row are ignored.
+    pub const fn new(width: usize, pixels: &'a [Option<Rgb>]) -> Self {
+        Self { width, pixels }
+    }
+
+    /// Returns the width of the bitmap.
+    pub const fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Returns the height of the bitmap.
+    pub const fn height(&self) -> usize {
+        match self.width {
+            0 => 0,
+            width => self.pixels.len() / width,
+        }
+    }
+
+    /// Returns the pixel at a position, where `(0, 0)` is the top left corner.
+    pub fn pixel(&self, x: usize, y: usize) -> Option<Rgb> {
+        if x < self.width && y < self.height() {
+            self.pixels[y * self.width + x]
+        } else {
+            None
+        }
+    }
+}
+
+/// Returns the point at a position, or None if it is off the grid.
+fn clip(x: i32, y: i32) -> Option<Point> {
+    let on_grid = (0..Grid::width() as i32).contains(&x) && (0..Grid::height() as i32).contains(&y);
+    on_grid.then(|| Point::new(x as i8, y as i8))
+}
+
+/// The largest radius a circle is drawn with. A circle this large around any point on the grid
+/// covers the whole grid, so larger circles are drawn with this radius instead.
+const MAX_RADIUS: i32 = 2 * Grid::width() as i32;
+
+/// Calls `f` with the offset of each point in the first octant of a circle, using the midpoint
+/// algorithm. The other octants are found by symmetry. Nothing is drawn for a negative radius.
+fn midpoint_circle(radius: i32, mut f: impl FnMut(i32, i32)) {
+    if radius < 0 {
+        return;
+    }
+    let (mut x, mut y) = (radius.min(MAX_RADIUS), 0);
+    let mut error = 1 - x;
+    while x >= y {
+        f(x, y);
+        y += 1;
+        if error < 0 {
+            error += 2 * y + 1;
+        } else {
+            x -= 1;
+            error += 2 * (y - x) + 1;
+        }
+    }
+}
+
+/// A surface that can be drawn on, such as a [`FrameBuffer`](super::FrameBuffer). Positions are
+/// given as `x` and `y` coordinates from the bottom left corner of the grid, and anything drawn
+/// beyond the edges of the grid is clipped rather than wrapping around.
+///
+/// # Example
+///
+/// ```
+/// use launchpad_pro_rs::hal::{
+///     gfx::{Canvas, FrameBuffer},
+///     Point, Rgb,
+/// };
+///
+/// let mut frame = FrameBuffer::new();
+/// frame.fill_rect(1, 1, 8, 8, Rgb::BLUE);
+/// frame.line(1, 1, 8, 8, Rgb::WHITE);
+/// frame.circle(5, 5, 7, Rgb::RED);
+///
+/// assert_eq!(frame.pixel(Point::new(4, 4)), Rgb::WHITE);
+/// frame.flush();
+/// ```
+pub trait Canvas {
+    /// Returns the colour of a pixel.
+    fn pixel(&self, point: Point) -> Rgb;
+
+    /// Set the colour of a pixel.
+    fn set_pixel(&mut self, point: Point, rgb: Rgb);
+
+    /// Set the colour of the pixel at a position, if it is on the grid.
+    fn plot(&mut self, x: i32, y: i32, rgb: Rgb) {
+        if let Some(point) = clip(x, y) {
+            self.set_pixel(point, rgb);
+        }
+    }
+
+    /// Draw a line between two positions, including both ends.
+    fn line(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, rgb: Rgb) {
+        // the distance between two positions can be too large for an i32
+        let (x0, y0, x1, y1) = (i64::from(x0), i64::from(y0), i64::from(x1), i64::from(y1));
+        let (dx, dy) = ((x1 - x0).abs(), -(y1 - y0).abs());
+        let (step_x, step_y) = ((x1 - x0).signum(), (y1 - y0).signum());
+        let (mut x, mut y) = (x0, y0);
+        let mut error = dx + dy;
+        loop {
+            // the position is always between the two ends, so it fits in an i32
+            self.plot(x as i32, y as i32, rgb);
+            if x == x1 && y == y1 {
+                break;
+            }
+            let doubled = 2 * error;
+            if doubled >= dy {
+                error += dy;
+                x += step_x;
+            }
+            if doubled <= dx {
+                error += dx;
+                y += step_y;
+            }
+        }
+    }
+
+    /// Draw the outline of a rectangle, with its bottom left corner at a position.
+    fn rect(&mut self, x: i32, y: i32, width: i32, height: i32, rgb: Rgb) {
+        if width <= 0 || height <= 0 {
+            return;
+        }
+        let (right, top) = (x.saturating_add(width - 1), y.saturating_add(height - 1));
+        self.line(x, y, right, y, rgb);
+        self.line(x, top, right, top, rgb);
+        self.line(x, y, x, top, rgb);
+        self.line(right, y, right, top, rgb);
+    }
+
+    /// Fill a rectangle, with its bottom left corner at a position.
+    fn fill_rect(&mut self, x: i32, y: i32, width: i32, height: i32, rgb: Rgb) {
+        let columns = x.max(0)..x.saturating_add(width).min(Grid::width() as i32);
+        let rows = y.max(0)..y.saturating_add(height).min(Grid::height() as i32);
+        for y in rows {
+            for x in columns.clone() {
+                self.plot(x, y, rgb);
+            }
+        }
+    }
+
+    /// Draw the outline of a circle around a centre position. Nothing is drawn for a negative
+    /// radius.
+    fn circle(&mut self, x: i32, y: i32, radius: i32, rgb: Rgb) {
+        midpoint_circle(radius, |dx, dy| {
+            for &(dx, dy) in &[(dx, dy), (dy, dx)] {
+                let (left, right) = (x.saturating_sub(dx), x.saturating_add(dx));
+                let (bottom, top) = (y.saturating_sub(dy), y.saturating_add(dy));
+                self.plot(right, top, rgb);
+                self.plot(left, top, rgb);
+                self.plot(right, bottom, rgb);
+                self.plot(left, bottom, rgb);
+            }
+        });
+    }
+
+    /// Fill a circle around a centre position. Nothing is drawn for a negative radius.
+    fn fill_circle(&mut self, x: i32, y: i32, radius: i32, rgb: Rgb) {
+        midpoint_circle(radius, |dx, dy| {
+            for &(dx, dy) in &[(dx, dy), (dy, dx)] {
+                let left = x.saturating_sub(dx);
+                self.fill_rect(left, y.saturating_add(dy), 2 * dx + 1, 1, rgb);
+                self.fill_rect(left, y.saturating_sub(dy), 2 * dx + 1, 1, rgb);
+            }
+        });
+    }
+
+    /// Fill the area of the same colour around a position, spreading up, down, left and right.
+    fn flood_fill(&mut self, x: i32, y: i32, rgb: Rgb) {
+        let start = match clip(x, y) {
+            Some(point) => point,
+            None => return,
+        };
+        let target = self.pixel(start);
+        if target == rgb {
+            return;
+        }
+
+        // each pixel is filled as it is pushed, so it is only pushed once
+        let mut stack = [start; Grid::size() as usize];
+        let mut length = 1;
+        self.set_pixel(start, rgb);
+        while length > 0 {
+            length -= 1;
+            let (x, y) = (stack[length].x() as i32, stack[length].y() as i32);
+            for &(x, y) in &[(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)] {
+                if let Some(point) = clip(x, y) {
+                    if self.pixel(point) == target {
+                        self.set_pixel(point, rgb);
+                        stack[length] = point;
+                        length += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Draw a bitmap with its top left corner at a position, skipping its transparent pixels.
+    fn blit(&mut self, bitmap: &Bitmap, x: i32, y: i32) {
+        for row in 0..bitmap.height() {
+            for column in 0..bitmap.width() {
+                if let Some(rgb) = bitmap.pixel(column, row) {
+                    self.plot(
+                        x.saturating_add(column as i32),
+                        y.saturating_sub(row as i32),
+                        rgb,
+                    );
+                }
+            }
+        }
+    }
+
+    /// Draw a character with the top left corner of its glyph at a position. Only the lit pixels
+    /// of the glyph are drawn.
+    fn character(&mut self, font: &Font, c: char, x: i32, y: i32, rgb: Rgb) {
+        let glyph = font.glyph(c);
+        for row in 0..font.height() {
+            for column in 0..font.width() {
+                if glyph.pixel(column, row) {
+                    self.plot(
+                        x.saturating_add(column as i32),
+                        y.saturating_sub(row as i32),
+                        rgb,
+                    );
+                }
+            }
+        }
+    }
+
+    /// Draw a line of text with its top left corner at a position, leaving a blank column between
+    /// characters.
+    fn text(&mut self, font: &Font, text: &str, x: i32, y: i32, rgb: Rgb) {
+        let advance = font.width() as i32 + 1;
+        for (index, c) in text.chars().enumerate() {
+            let x = x.saturating_add((index as i32).saturating_mul(advance));
+            self.character(font, c, x, y, rgb);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::hal::{
+            gfx::{FrameBuffer, FONT_3X5},
+            sim::Frame,
+        },
+    };
+
+    #[test]
+    fn lines_are_drawn_in_every_direction() {
+        let mut frame = FrameBuffer::new();
+        frame.line(0, 0, 9, 3, Rgb::WHITE);
+        frame.line(2, 9, 2, 5, Rgb::WHITE);
+        frame.line(9, 9, 6, 6, Rgb::WHITE);
+        assert_eq!(
+            Frame::from(&frame).render(&[]),
+            [
+                "..#......#",
+                "..#.....#.",
+                "..#....#..",
+                "..#...#...",
+                "..#.......",
+                "..........",
+                "........##",
+                ".....###..",
+                "..###.....",
+                "##........",
+            ]
+        );
+    }
+
+    #[test]
+    fn rectangles_are_drawn_and_filled() {
+        let mut frame = FrameBuffer::new();
+        frame.rect(0, 5, 4, 5, Rgb::WHITE);
+        frame.fill_rect(6, 0, 3, 2, Rgb::WHITE);
+        frame.rect(5, 5, 0, 3, Rgb::WHITE);
+        assert_eq!(
+            Frame::from(&frame).render(&[]),
+            [
+                "####......",
+                "#..#......",
+                "#..#......",
+                "#..#......",
+                "####......",
+                "..........",
+                "..........",
+                "..........",
+                "......###.",
+                "......###.",
+            ]
+        );
+    }
+
+    #[test]
+    fn circles_are_drawn_and_filled() {
+        let mut frame = FrameBuffer::new();
+        frame.circle(4, 4, 3, Rgb::WHITE);
+        frame.fill_circle(9, 9, 1, Rgb::WHITE);
+        assert_eq!(
+            Frame::from(&frame).render(&[]),
+            [
+                "........##",
+                ".........#",
+                "...###....",
+                "..#...#...",
+                ".#.....#..",
+                ".#.....#..",
+                ".#.....#..",
+                "..#...#...",
+                "...###....",
+                "..........",
+            ]
+        );
+    }
+
+    #[test]
+    fn drawing_is_clipped_to_the_grid() {
+        let mut frame = FrameBuffer::new();
+        frame.line(-5, 0, 20, 0, Rgb::WHITE);
+        frame.fill_rect(8, 8, 100, 100, Rgb::WHITE);
+        frame.circle(0, 9, 2, Rgb::WHITE);
+        frame.plot(10, 5, Rgb::RED);
+        frame.plot(-1, 5, Rgb::RED);
+        assert_eq!(
+            Frame::from(&frame).render(&[]),
+            [
+                "..#.....##",
+                "..#.....##",
+                "##........",
+                "..........",
+                "..........",
+                "..........",
+                "..........",
+                "..........",
+                "..........",
+                "##########",
+            ]
+        );
+    }
+
+    #[test]
+    fn huge_shapes_do_not_overflow() {
+        let mut frame = FrameBuffer::new();
+        frame.fill_rect(i32::MIN, 8, i32::MAX, i32::MAX, Rgb::WHITE);
+        frame.fill_rect(i32::MAX, 0, i32::MAX, 1, Rgb::WHITE);
+        frame.rect(i32::MAX - 1, i32::MAX - 1, i32::MAX, i32::MAX, Rgb::WHITE);
+        frame.line(9, 0, 9, 2, Rgb::WHITE);
+        frame.circle(i32::MAX, 0, 1, Rgb::WHITE);
+        frame.circle(i32::MIN, i32::MAX, i32::MAX, Rgb::WHITE);
+        frame.circle(4, 4, -1, Rgb::WHITE);
+        frame.fill_circle(4, 4, -1, Rgb::WHITE);
+        frame.blit(&Bitmap::new(1, &[Some(Rgb::WHITE); 4]), i32::MAX, i32::MIN);
+        frame.text(&FONT_3X5, "ab", i32::MAX - 1, 0, Rgb::WHITE);
+        frame.character(&FONT_3X5, 'a', 0, i32::MIN, Rgb::WHITE);
+        assert_eq!(
+            Frame::from(&frame).render(&[]),
+            [
+                "..........",
+                "..........",
+                "..........",
+                "..........",
+                "..........",
+                "..........",
+                "..........",
+                ".........#",
+                ".........#",
+                ".........#",
+            ]
+        );
+
+        // a huge circle around a point on the grid covers all of it, without drawing every point
+        let mut frame = FrameBuffer::new();
+        frame.circle(4, 4, i32::MAX, Rgb::WHITE);
+        assert_eq!(Frame::from(&frame).render(&[]), [".........."; 10]);
+        frame.fill_circle(0, 0, i32::MAX, Rgb::WHITE);
+        assert_eq!(Frame::from(&frame).render(&[]), ["##########"; 10]);
+    }
+
+    #[test]
+    fn flood_fill_stops_at_edges() {
+        let mut frame = FrameBuffer::new();
+        frame.rect(2, 2, 5, 5, Rgb::WHITE);
+        frame.flood_fill(4, 4, Rgb::RED);
+        assert_eq!(frame.pixel(Point::new(3, 3)), Rgb::RED);
+        assert_eq!(frame.pixel(Point::new(5, 5)), Rgb::RED);
+        assert_eq!(frame.pixel(Point::new(2, 2)), Rgb::WHITE);
+        assert_eq!(frame.pixel(Point::new(1, 1)), Rgb::BLACK);
+
+        // filling the outside covers the rest of the grid
+        frame.flood_fill(0, 0, Rgb::BLUE);
+        let count = |frame: &FrameBuffer, rgb| {
+            Grid::points()
+                .filter(|&point| frame.pixel(point) == rgb)
+                .count()
+        };
+        assert_eq!(count(&frame, Rgb::BLUE), 100 - 25);
+        assert_eq!(count(&frame, Rgb::RED), 9);
+        frame.flood_fill(-1, 0, Rgb::GREEN);
+        assert_eq!(count(&frame, Rgb::GREEN), 0);
+    }
+
+    #[test]
+    fn bitmaps_are_blitted_with_transparency() {
+        const W: Option<Rgb> = Some(Rgb::WHITE);
+        const ARROW: Bitmap = Bitmap::new(3, &[None, W, None, W, W, W, None, W, None, None]);
+        assert_eq!(ARROW.height(), 3);
+
+        let mut frame = FrameBuffer::new();
+        frame.fill(Rgb::RED);
+        frame.blit(&ARROW, 8, 9);
+        frame.blit(&ARROW, -1, 1);
+        assert_eq!(frame.pixel(Point::new(9, 9)), Rgb::WHITE);
+        assert_eq!(frame.pixel(Point::new(8, 9)), Rgb::RED);
+        assert_eq!(frame.pixel(Point::new(8, 8)), Rgb::WHITE);
+        assert_eq!(frame.pixel(Point::new(0, 0)), Rgb::WHITE);
+        assert_eq!(frame.pixel(Point::new(1, 0)), Rgb::WHITE);
+        assert_eq!(frame.pixel(Point::new(1, 1)), Rgb::RED);
+    }
+}
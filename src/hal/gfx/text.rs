@@ -0,0 +1,477 @@
+use crate::hal::{gfx::Canvas, time, Grid, Point, Rgb};
+
+/// A bitmap font for the printable ASCII characters, from space to `~`.
+///
+/// # Example
+///
+/// ```
+/// use launchpad_pro_rs::hal::gfx::FONT_3X5;
+///
+/// let glyph = FONT_3X5.glyph('T');
+/// assert!(glyph.pixel(1, 4));
+/// assert!(!glyph.pixel(0, 4));
+/// assert_eq!(FONT_3X5.text_width("TEXT"), 15);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Font {
+    width: u8,
+    height: u8,
+    rows: &'static [u8],
+}
+
+/// The glyph of a character in a [`Font`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Glyph {
+    width: u8,
+    rows: &'static [u8],
+}
+
+impl Glyph {
+    /// Returns whether the pixel at a position in the glyph is lit, where `(0, 0)` is the top
+    /// left corner.
+    pub fn pixel(&self, x: u8, y: u8) -> bool {
+        x < self.width
+            && self
+                .rows
+                .get(y as usize)
+                .is_some_and(|row| (row >> (self.width - 1 - x)) & 1 != 0)
+    }
+}
+
+impl Font {
+    /// Construct a font of glyphs up to 8 pixels wide. Each glyph is given as `height` rows from
+    /// the top, with the leftmost pixel of each row in bit `width - 1`. The glyphs are for the
+    /// characters from space onwards.
+    pub const fn new(width: u8, height: u8, rows: &'static [u8]) -> Self {
+        Self {
+            width,
+            height,
+            rows,
+        }
+    }
+
+    /// Returns the width of each glyph.
+    pub const fn width(&self) -> u8 {
+        self.width
+    }
+
+    /// Returns the height of each glyph.
+    pub const fn height(&self) -> u8 {
+        self.height
+    }
+
+    /// Returns the glyph of a character. Characters that the font does not cover are shown as
+    /// `?`.
+    pub fn glyph(&self, c: char) -> Glyph {
+        let height = self.height as usize;
+        let index = |c: char| (c as usize).checked_sub(' ' as usize);
+        let rows = index(c)
+            .and_then(|index| self.rows.get(index * height..(index + 1) * height))
+            .or_else(|| {
+                let index = index('?')?;
+                self.rows.get(index * height..(index + 1) * height)
+            })
+            .unwrap_or(&[]);
+        Glyph {
+            width: self.width,
+            rows,
+        }
+    }
+
+    /// Returns the width of a line of text in pixels, with a blank column between characters.
+    pub fn text_width(&self, text: &str) -> i32 {
+        match text.chars().count() as i32 {
+            0 => 0,
+            count => count * (self.width as i32 + 1) - 1,
+        }
+    }
+}
+
+/// A font with glyphs 3 pixels wide and 5 high, so that five rows of text fit across the grid.
+/// Lowercase letters are shown as uppercase.
+pub const FONT_3X5: Font = Font::new(3, 5, &FONT_3X5_ROWS);
+
+#[rustfmt::skip]
+const FONT_3X5_ROWS: [u8; 95 * 5] = [
+    0b000, 0b000, 0b000, 0b000, 0b000, // space
+    0b010, 0b010, 0b010, 0b000, 0b010, // !
+    0b101, 0b101, 0b000, 0b000, 0b000, // "
+    0b101, 0b111, 0b101, 0b111, 0b101, // #
+    0b011, 0b110, 0b010, 0b011, 0b110, // $
+    0b101, 0b001, 0b010, 0b100, 0b101, // %
+    0b010, 0b101, 0b010, 0b101, 0b011, // &
+    0b010, 0b010, 0b000, 0b000, 0b000, // '
+    0b001, 0b010, 0b010, 0b010, 0b001, // (
+    0b100, 0b010, 0b010, 0b010, 0b100, // )
+    0b000, 0b101, 0b010, 0b101, 0b000, // *
+    0b000, 0b010, 0b111, 0b010, 0b000, // +
+    0b000, 0b000, 0b000, 0b010, 0b100, // ,
+    0b000, 0b000, 0b111, 0b000, 0b000, // -
+    0b000, 0b000, 0b000, 0b000, 0b010, // .
+    0b001, 0b001, 0b010, 0b100, 0b100, // /
+    0b111, 0b101, 0b101, 0b101, 0b111, // 0
+    0b010, 0b110, 0b010, 0b010, 0b111, // 1
+    0b111, 0b001, 0b111, 0b100, 0b111, // 2
+    0b111, 0b001, 0b111, 0b001, 0b111, // 3
+    0b101, 0b101, 0b111, 0b001, 0b001, // 4
+    0b111, 0b100, 0b111, 0b001, 0b111, // 5
+    0b111, 0b100, 0b111, 0b101, 0b111, // 6
+    0b111, 0b001, 0b001, 0b010, 0b010, // 7
+    0b111, 0b101, 0b111, 0b101, 0b111, // 8
+    0b111, 0b101, 0b111, 0b001, 0b111, // 9
+    0b000, 0b010, 0b000, 0b010, 0b000, // :
+    0b000, 0b010, 0b000, 0b010, 0b100, // ;
+    0b001, 0b010, 0b100, 0b010, 0b001, // <
+    0b000, 0b111, 0b000, 0b111, 0b000, // =
+    0b100, 0b010, 0b001, 0b010, 0b100, // >
+    0b111, 0b001, 0b011, 0b000, 0b010, // ?
+    0b010, 0b101, 0b111, 0b100, 0b011, // @
+    0b010, 0b101, 0b111, 0b101, 0b101, // A
+    0b110, 0b101, 0b110, 0b101, 0b110, // B
+    0b011, 0b100, 0b100, 0b100, 0b011, // C
+    0b110, 0b101, 0b101, 0b101, 0b110, // D
+    0b111, 0b100, 0b110, 0b100, 0b111, // E
+    0b111, 0b100, 0b110, 0b100, 0b100, // F
+    0b011, 0b100, 0b101, 0b101, 0b011, // G
+    0b101, 0b101, 0b111, 0b101, 0b101, // H
+    0b111, 0b010, 0b010, 0b010, 0b111, // I
+    0b001, 0b001, 0b001, 0b101, 0b010, // J
+    0b101, 0b101, 0b110, 0b101, 0b101, // K
+    0b100, 0b100, 0b100, 0b100, 0b111, // L
+    0b101, 0b111, 0b111, 0b101, 0b101, // M
+    0b110, 0b101, 0b101, 0b101, 0b101, // N
+    0b010, 0b101, 0b101, 0b101, 0b010, // O
+    0b110, 0b101, 0b110, 0b100, 0b100, // P
+    0b010, 0b101, 0b101, 0b110, 0b011, // Q
+    0b110, 0b101, 0b110, 0b101, 0b101, // R
+    0b011, 0b100, 0b010, 0b001, 0b110, // S
+    0b111, 0b010, 0b010, 0b010, 0b010, // T
+    0b101, 0b101, 0b101, 0b101, 0b111, // U
+    0b101, 0b101, 0b101, 0b101, 0b010, // V
+    0b101, 0b101, 0b111, 0b111, 0b101, // W
+    0b101, 0b101, 0b010, 0b101, 0b101, // X
+    0b101, 0b101, 0b010, 0b010, 0b010, // Y
+    0b111, 0b001, 0b010, 0b100, 0b111, // Z
+    0b011, 0b010, 0b010, 0b010, 0b011, // [
+    0b100, 0b100, 0b010, 0b001, 0b001, // \
+    0b110, 0b010, 0b010, 0b010, 0b110, // ]
+    0b010, 0b101, 0b000, 0b000, 0b000, // ^
+    0b000, 0b000, 0b000, 0b000, 0b111, // _
+    0b100, 0b010, 0b000, 0b000, 0b000, // `
+    0b010, 0b101, 0b111, 0b101, 0b101, // a
+    0b110, 0b101, 0b110, 0b101, 0b110, // b
+    0b011, 0b100, 0b100, 0b100, 0b011, // c
+    0b110, 0b101, 0b101, 0b101, 0b110, // d
+    0b111, 0b100, 0b110, 0b100, 0b111, // e
+    0b111, 0b100, 0b110, 0b100, 0b100, // f
+    0b011, 0b100, 0b101, 0b101, 0b011, // g
+    0b101, 0b101, 0b111, 0b101, 0b101, // h
+    0b111, 0b010, 0b010, 0b010, 0b111, // i
+    0b001, 0b001, 0b001, 0b101, 0b010, // j
+    0b101, 0b101, 0b110, 0b101, 0b101, // k
+    0b100, 0b100, 0b100, 0b100, 0b111, // l
+    0b101, 0b111, 0b111, 0b101, 0b101, // m
+    0b110, 0b101, 0b101, 0b101, 0b101, // n
+    0b010, 0b101, 0b101, 0b101, 0b010, // o
+    0b110, 0b101, 0b110, 0b100, 0b100, // p
+    0b010, 0b101, 0b101, 0b110, 0b011, // q
+    0b110, 0b101, 0b110, 0b101, 0b101, // r
+    0b011, 0b100, 0b010, 0b001, 0b110, // s
+    0b111, 0b010, 0b010, 0b010, 0b010, // t
+    0b101, 0b101, 0b101, 0b101, 0b111, // u
+    0b101, 0b101, 0b101, 0b101, 0b010, // v
+    0b101, 0b101, 0b111, 0b111, 0b101, // w
+    0b101, 0b101, 0b010, 0b101, 0b101, // x
+    0b101, 0b101, 0b010, 0b010, 0b010, // y
+    0b111, 0b001, 0b010, 0b100, 0b111, // z
+    0b011, 0b010, 0b110, 0b010, 0b011, // {
+    0b010, 0b010, 0b010, 0b010, 0b010, // |
+    0b110, 0b010, 0b011, 0b010, 0b110, // }
+    0b000, 0b011, 0b110, 0b000, 0b000, // ~
+];
+
+/// Text that scrolls from right to left across a region of the grid, one column at a time.
+/// Call [`Marquee::draw`] from the app's `timer_event` to animate it, followed by flushing the
+/// frame buffer it is drawn into.
+///
+/// # Example
+///
+/// ```
+/// use launchpad_pro_rs::hal::{
+///     gfx::{FrameBuffer, Marquee, FONT_3X5},
+///     Rgb,
+/// };
+///
+/// let mut frame = FrameBuffer::new();
+/// let mut marquee = Marquee::new("HELLO", &FONT_3X5)
+///     .region(0, 1, 10, 8)
+///     .colours(&[Rgb::RED, Rgb::GREEN])
+///     .speed(80)
+///     .on_complete(|| launchpad_pro_rs::info!("said hello"));
+///
+/// // in timer_event
+/// marquee.draw(&mut frame);
+/// frame.flush();
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct Marquee<'a> {
+    text: &'a str,
+    font: &'a Font,
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+    colours: &'a [Rgb],
+    background: Rgb,
+    /// The number of milliseconds it takes the text to move by one column.
+    interval: u32,
+    /// The time the text started scrolling, once it has been drawn.
+    started: Option<u32>,
+    finished: bool,
+    on_complete: Option<fn()>,
+}
+
+impl<'a> Marquee<'a> {
+    /// Construct a marquee that scrolls white text across the middle of the grid on a black
+    /// background, moving a column every 100 ms.
+    pub fn new(text: &'a str, font: &'a Font) -> Self {
+        let height = font.height() as i32;
+        Self {
+            text,
+            font,
+            x: 0,
+            y: (Grid::height() as i32 - height) / 2,
+            width: Grid::width() as i32,
+            height,
+            colours: &[Rgb::WHITE],
+            background: Rgb::BLACK,
+            interval: 100,
+            started: None,
+            finished: false,
+            on_complete: None,
+        }
+    }
+
+    /// Scroll the text across a region of the grid, with its bottom left corner at a position.
+    /// The text is aligned with the top of the region.
+    pub fn region(mut self, x: i32, y: i32, width: i32, height: i32) -> Self {
+        self.x = x;
+        self.y = y;
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    /// Set the colour of each character. If there are more characters than colours then the
+    /// colours are repeated.
+    pub fn colours(mut self, colours: &'a [Rgb]) -> Self {
+        self.colours = colours;
+        self
+    }
+
+    /// Set the colour of the region behind the text.
+    pub fn background(mut self, rgb: Rgb) -> Self {
+        self.background = rgb;
+        self
+    }
+
+    /// Set the number of milliseconds it takes the text to move by one column.
+    pub fn speed(mut self, interval: u32) -> Self {
+        self.interval = interval.max(1);
+        self
+    }
+
+    /// Set a function to call once the text has scrolled off the region.
+    pub fn on_complete(mut self, callback: fn()) -> Self {
+        self.on_complete = Some(callback);
+        self
+    }
+
+    /// Returns whether the text has scrolled off the region.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Scroll the text from the start again the next time it is drawn.
+    pub fn restart(&mut self) {
+        self.started = None;
+        self.finished = false;
+    }
+
+    /// Draw the text at its current position, which depends on the time since it was first
+    /// drawn. Returns false once the text has scrolled off the region, at which point the region
+    /// is left blank and the completion callback is called.
+    pub fn draw(&mut self, canvas: &mut impl Canvas) -> bool {
+        if self.finished {
+            return false;
+        }
+        let now = time::now();
+        let started = *self.started.get_or_insert(now);
+        let offset = (now.wrapping_sub(started) / self.interval).min(i32::MAX as u32) as i32;
+
+        let (right, top) = (
+            self.x.saturating_add(self.width),
+            self.y.saturating_add(self.height),
+        );
+        let mut region = Region {
+            canvas,
+            x: self.x..right,
+            y: self.y..top,
+        };
+        region.fill_rect(self.x, self.y, self.width, self.height, self.background);
+
+        if offset >= self.width.saturating_add(self.font.text_width(self.text)) {
+            self.finished = true;
+            if let Some(callback) = self.on_complete {
+                callback();
+            }
+            return false;
+        }
+
+        let advance = self.font.width() as i32 + 1;
+        let top = top.saturating_sub(1);
+        for (index, c) in self.text.chars().enumerate() {
+            let x = right
+                .saturating_sub(offset)
+                .saturating_add((index as i32).saturating_mul(advance));
+            let colour = match self.colours.len() {
+                0 => Rgb::WHITE,
+                count => self.colours[index % count],
+            };
+            region.character(self.font, c, x, top, colour);
+        }
+        true
+    }
+}
+
+/// Restricts drawing to a region of a canvas.
+struct Region<'c, C: Canvas> {
+    canvas: &'c mut C,
+    x: core::ops::Range<i32>,
+    y: core::ops::Range<i32>,
+}
+
+impl<C: Canvas> Canvas for Region<'_, C> {
+    fn pixel(&self, point: Point) -> Rgb {
+        self.canvas.pixel(point)
+    }
+
+    fn set_pixel(&mut self, point: Point, rgb: Rgb) {
+        if self.x.contains(&(point.x() as i32)) && self.y.contains(&(point.y() as i32)) {
+            self.canvas.set_pixel(point, rgb);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::hal::{
+            gfx::FrameBuffer,
+            sim::{self, Frame},
+        },
+        core::sync::atomic::{AtomicU32, Ordering},
+    };
+
+    /// The characters the marquee colours are rendered with.
+    const LEGEND: &[(Rgb, char)] = &[(Rgb::RED, 'R'), (Rgb::GREEN, 'G')];
+
+    #[test]
+    fn every_printable_character_has_a_glyph() {
+        let question = FONT_3X5.glyph('?');
+        for c in (' '..='~').filter(|&c| c != '?') {
+            assert_ne!(FONT_3X5.glyph(c), question, "{:?}", c);
+        }
+        assert_eq!(FONT_3X5.glyph('a'), FONT_3X5.glyph('A'));
+        assert_eq!(FONT_3X5.glyph('é'), question);
+        assert_eq!(FONT_3X5.glyph('\n'), question);
+        assert_eq!(FONT_3X5.text_width(""), 0);
+    }
+
+    #[test]
+    fn text_is_drawn_top_down() {
+        let mut frame = FrameBuffer::new();
+        frame.text(&FONT_3X5, "HI!", 0, 6, Rgb::WHITE);
+        assert_eq!(
+            &Frame::from(&frame).render(&[])[3..8],
+            [
+                "#.#.###..#",
+                "#.#..#...#",
+                "###..#...#",
+                "#.#..#....",
+                "#.#.###..#",
+            ]
+        );
+    }
+
+    #[test]
+    fn marquees_scroll_across_their_region() {
+        static COMPLETED: AtomicU32 = AtomicU32::new(0);
+
+        let mut simulator = sim::Simulator::idle();
+        let mut frame = FrameBuffer::new();
+        frame.fill(Rgb::BLUE);
+        let mut marquee = Marquee::new("AB", &FONT_3X5)
+            .region(2, 3, 6, 5)
+            .colours(&[Rgb::RED, Rgb::GREEN])
+            .speed(10)
+            .on_complete(|| {
+                COMPLETED.fetch_add(1, Ordering::Relaxed);
+            });
+
+        assert!(marquee.draw(&mut frame));
+        assert_eq!(Frame::from(&frame).render(LEGEND)[2], "##......##");
+
+        simulator.advance(30);
+        assert!(marquee.draw(&mut frame));
+        assert_eq!(
+            &Frame::from(&frame).render(LEGEND)[2..7],
+            [
+                "##....R.##",
+                "##...R.R##",
+                "##...RRR##",
+                "##...R.R##",
+                "##...R.R##",
+            ]
+        );
+
+        simulator.advance(50);
+        assert!(marquee.draw(&mut frame));
+        assert_eq!(
+            &Frame::from(&frame).render(LEGEND)[2..7],
+            [
+                "##..GG..##",
+                "##R.G.G.##",
+                "##R.GG..##",
+                "##R.G.G.##",
+                "##R.GG..##",
+            ]
+        );
+
+        // the text has scrolled off after the width of the region and the text
+        simulator.advance(50);
+        assert!(!marquee.draw(&mut frame));
+        assert!(marquee.is_finished());
+        assert_eq!(Frame::from(&frame).render(LEGEND)[4], "##......##");
+        assert!(!marquee.draw(&mut frame));
+        assert_eq!(COMPLETED.load(Ordering::Relaxed), 1);
+
+        marquee.restart();
+        assert!(marquee.draw(&mut frame));
+    }
+
+    #[test]
+    fn marquees_in_huge_regions_do_not_overflow() {
+        let mut frame = FrameBuffer::new();
+        for &(x, y, width, height) in &[
+            (i32::MAX, 0, 10, 5),
+            (0, i32::MAX, 10, 5),
+            (0, 0, i32::MAX, i32::MAX),
+            (i32::MIN, i32::MIN, i32::MAX, i32::MAX),
+        ] {
+            let mut marquee = Marquee::new("AB", &FONT_3X5).region(x, y, width, height);
+            assert!(marquee.draw(&mut frame));
+        }
+    }
+}
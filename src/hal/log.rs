@@ -0,0 +1,476 @@
+use {
+    crate::hal::midi::{self, Port},
+    core::{
+        fmt,
+        sync::atomic::{AtomicU8, Ordering},
+    },
+};
+
+#[cfg(not(target_arch = "arm"))]
+use std::{string::String, vec::Vec};
+
+/// The maximum length in bytes of a log record, including its target. Longer records are
+/// truncated.
+pub const CAPACITY: usize = 512;
+
+/// The bytes that start each SysEx frame of a log record.
+const FRAME_HEADER: [u8; 4] = [0xF0, midi::NON_COMMERCIAL_ID, b'L', b'G'];
+
+/// The number of bytes of a record carried by each SysEx frame. This is a multiple of seven so
+/// that the packed frames, with their header, level and sequence bytes, stay within the 320 byte
+/// SysEx limit.
+const FRAME_CAPACITY: usize = 273;
+
+/// Set in the sequence byte of a frame when more frames of the record follow it.
+const MORE: u8 = 0x40;
+
+/// The importance of a log record, from most to least severe.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Level {
+    Error = 1,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    #[cfg(not(target_arch = "arm"))]
+    const fn from_u8(level: u8) -> Option<Self> {
+        match level {
+            1 => Some(Level::Error),
+            2 => Some(Level::Warn),
+            3 => Some(Level::Info),
+            4 => Some(Level::Debug),
+            5 => Some(Level::Trace),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of the level in upper case.
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+/// The least severe level of the records that are sent, or `Off` to send none.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LevelFilter {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LevelFilter {
+    const fn from_u8(level: u8) -> Self {
+        match level {
+            0 => LevelFilter::Off,
+            1 => LevelFilter::Error,
+            2 => LevelFilter::Warn,
+            3 => LevelFilter::Info,
+            4 => LevelFilter::Debug,
+            _ => LevelFilter::Trace,
+        }
+    }
+
+    /// Returns whether records of a level pass the filter.
+    pub const fn allows(&self, level: Level) -> bool {
+        level as u8 <= *self as u8
+    }
+}
+
+#[cfg(target_arch = "arm")]
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(LevelFilter::Info as u8);
+
+#[cfg(target_arch = "arm")]
+fn with_max_level<R>(f: impl FnOnce(&AtomicU8) -> R) -> R {
+    f(&MAX_LEVEL)
+}
+
+#[cfg(not(target_arch = "arm"))]
+std::thread_local! {
+    static MAX_LEVEL: AtomicU8 = const { AtomicU8::new(LevelFilter::Info as u8) };
+}
+
+#[cfg(not(target_arch = "arm"))]
+fn with_max_level<R>(f: impl FnOnce(&AtomicU8) -> R) -> R {
+    MAX_LEVEL.with(|level| f(level))
+}
+
+/// Returns the least severe level of the records that are sent. This is `Info` by default.
+pub fn max_level() -> LevelFilter {
+    with_max_level(|level| LevelFilter::from_u8(level.load(Ordering::Relaxed)))
+}
+
+/// Change the least severe level of the records that are sent.
+///
+/// # Example
+///
+/// ```
+/// use launchpad_pro_rs::hal::log::{self, LevelFilter};
+///
+/// log::set_max_level(LevelFilter::Trace);
+/// ```
+pub fn set_max_level(level: LevelFilter) {
+    with_max_level(|max_level| max_level.store(level as u8, Ordering::Relaxed));
+}
+
+/// A log record being formatted into a fixed buffer. The record starts with the length of its
+/// target, followed by the target and the message.
+struct Buffer {
+    data: [u8; CAPACITY],
+    length: usize,
+}
+
+impl fmt::Write for Buffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let count = s.len().min(CAPACITY - self.length);
+        self.data[self.length..self.length + count].copy_from_slice(&s.as_bytes()[..count]);
+        self.length += count;
+        Ok(())
+    }
+}
+
+/// Format a log record and send it over USB. Called by the logging macros once they have checked
+/// the level.
+#[doc(hidden)]
+pub fn write(level: Level, target: &str, args: fmt::Arguments) {
+    let target = &target.as_bytes()[..target.len().min(u8::MAX as usize)];
+    let mut buffer = Buffer {
+        data: [0; CAPACITY],
+        length: 1 + target.len(),
+    };
+    buffer.data[0] = target.len() as u8;
+    buffer.data[1..buffer.length].copy_from_slice(target);
+    let _ = fmt::write(&mut buffer, args);
+
+    send(level, &buffer.data[..buffer.length]);
+}
+
+/// Send an encoded record as one or more SysEx frames.
+fn send(level: Level, record: &[u8]) {
+    const PACKED_CAPACITY: usize = FRAME_CAPACITY + FRAME_CAPACITY / 7;
+    let mut frame = [0; FRAME_HEADER.len() + 2 + PACKED_CAPACITY + 1];
+    frame[..FRAME_HEADER.len()].copy_from_slice(&FRAME_HEADER);
+    frame[FRAME_HEADER.len()] = level as u8;
+
+    let count = record.chunks(FRAME_CAPACITY).count();
+    for (sequence, chunk) in record.chunks(FRAME_CAPACITY).enumerate() {
+        let more = if sequence + 1 < count { MORE } else { 0 };
+        frame[FRAME_HEADER.len() + 1] = sequence as u8 | more;
+        let start = FRAME_HEADER.len() + 2;
+        let packed = midi::pack_7bit(chunk, &mut frame[start..]).unwrap_or(0);
+        let end = start + packed;
+        frame[end] = 0xF7;
+        midi::send_sysex(Port::USB, &frame[..=end]);
+    }
+}
+
+/// Returns whether a SysEx message is a frame of a log record.
+pub fn is_frame(data: &[u8]) -> bool {
+    data.len() > FRAME_HEADER.len() + 2
+        && data.starts_with(&FRAME_HEADER)
+        && data.last() == Some(&0xF7)
+}
+
+/// A log record decoded on the host.
+#[cfg(not(target_arch = "arm"))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Record {
+    pub level: Level,
+    pub target: String,
+    pub message: String,
+}
+
+#[cfg(not(target_arch = "arm"))]
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:<5} {}: {}", self.level, self.target, self.message)
+    }
+}
+
+/// Reassembles log records from the SysEx frames they were sent as.
+///
+/// # Example
+///
+/// ```
+/// use launchpad_pro_rs::{
+///     hal::{log::Decoder, sim},
+///     info,
+/// };
+///
+/// info!(target: "app", "{} pads lit", 3);
+///
+/// let mut decoder = Decoder::new();
+/// let records: Vec<_> = sim::take_sysex()
+///     .into_iter()
+///     .filter_map(|(_, data)| decoder.push(&data))
+///     .map(|record| record.to_string())
+///     .collect();
+/// assert_eq!(records, ["INFO  app: 3 pads lit"]);
+/// ```
+#[cfg(not(target_arch = "arm"))]
+#[derive(Clone, Debug, Default)]
+pub struct Decoder {
+    record: Vec<u8>,
+    /// The sequence number of the next frame of the record being reassembled, or None if the
+    /// next frame must start a new record.
+    next: Option<u8>,
+}
+
+#[cfg(not(target_arch = "arm"))]
+impl Decoder {
+    /// Construct a decoder waiting for the start of a record.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decode a SysEx message, returning a record once its last frame has been received. Messages
+    /// that are not frames of a log record are ignored, and a record with a missing frame is
+    /// dropped.
+    pub fn push(&mut self, data: &[u8]) -> Option<Record> {
+        if !is_frame(data) {
+            return None;
+        }
+        let level = Level::from_u8(data[FRAME_HEADER.len()])?;
+        let sequence = data[FRAME_HEADER.len() + 1];
+        let number = sequence & !MORE;
+
+        if number == 0 {
+            self.record.clear();
+        } else if self.next != Some(number) {
+            self.next = None;
+            return None;
+        }
+
+        let mut chunk = [0; FRAME_CAPACITY];
+        let packed = &data[FRAME_HEADER.len() + 2..data.len() - 1];
+        let length = midi::unpack_7bit(packed, &mut chunk)?;
+        self.record.extend_from_slice(&chunk[..length]);
+
+        if sequence & MORE != 0 {
+            self.next = Some(number + 1);
+            return None;
+        }
+        self.next = None;
+
+        let (&target_length, record) = self.record.split_first()?;
+        let (target, message) = record.split_at((target_length as usize).min(record.len()));
+        Some(Record {
+            level,
+            target: String::from_utf8_lossy(target).into_owned(),
+            message: String::from_utf8_lossy(message).into_owned(),
+        })
+    }
+}
+
+/// Decode every log record in a SysEx dump, such as a `.syx` file saved by a MIDI monitor,
+/// skipping any other messages.
+#[cfg(not(target_arch = "arm"))]
+pub fn decode(dump: &[u8]) -> Vec<Record> {
+    let mut decoder = Decoder::new();
+    dump.split_inclusive(|&byte| byte == 0xF7)
+        .filter_map(|message| {
+            let start = message.iter().position(|&byte| byte == 0xF0)?;
+            decoder.push(&message[start..])
+        })
+        .collect()
+}
+
+/// Log a message at a level, using the same syntax as the `log` crate. The target defaults to
+/// the path of the module that logs the message.
+///
+/// # Example
+///
+/// ```
+/// use launchpad_pro_rs::{hal::log::Level, log};
+///
+/// let pad = 42;
+/// log!(Level::Warn, "pad {} is stuck", pad);
+/// log!(target: "input", Level::Debug, "pad {} pressed", pad);
+/// ```
+#[macro_export]
+macro_rules! log {
+    (target: $target:expr, $level:expr, $($arg:tt)+) => {{
+        let level: $crate::hal::log::Level = $level;
+        if $crate::hal::log::max_level().allows(level) {
+            $crate::hal::log::write(level, $target, format_args!($($arg)+));
+        }
+    }};
+    ($level:expr, $($arg:tt)+) => {
+        $crate::log!(target: ::core::module_path!(), $level, $($arg)+)
+    };
+}
+
+/// Log a message at the error level.
+#[macro_export]
+macro_rules! error {
+    (target: $target:expr, $($arg:tt)+) => {
+        $crate::log!(target: $target, $crate::hal::log::Level::Error, $($arg)+)
+    };
+    ($($arg:tt)+) => {
+        $crate::log!($crate::hal::log::Level::Error, $($arg)+)
+    };
+}
+
+/// Log a message at the warn level.
+#[macro_export]
+macro_rules! warn {
+    (target: $target:expr, $($arg:tt)+) => {
+        $crate::log!(target: $target, $crate::hal::log::Level::Warn, $($arg)+)
+    };
+    ($($arg:tt)+) => {
+        $crate::log!($crate::hal::log::Level::Warn, $($arg)+)
+    };
+}
+
+/// Log a message at the info level.
+#[macro_export]
+macro_rules! info {
+    (target: $target:expr, $($arg:tt)+) => {
+        $crate::log!(target: $target, $crate::hal::log::Level::Info, $($arg)+)
+    };
+    ($($arg:tt)+) => {
+        $crate::log!($crate::hal::log::Level::Info, $($arg)+)
+    };
+}
+
+/// Log a message at the debug level.
+#[macro_export]
+macro_rules! debug {
+    (target: $target:expr, $($arg:tt)+) => {
+        $crate::log!(target: $target, $crate::hal::log::Level::Debug, $($arg)+)
+    };
+    ($($arg:tt)+) => {
+        $crate::log!($crate::hal::log::Level::Debug, $($arg)+)
+    };
+}
+
+/// Log a message at the trace level.
+#[macro_export]
+macro_rules! trace {
+    (target: $target:expr, $($arg:tt)+) => {
+        $crate::log!(target: $target, $crate::hal::log::Level::Trace, $($arg)+)
+    };
+    ($($arg:tt)+) => {
+        $crate::log!($crate::hal::log::Level::Trace, $($arg)+)
+    };
+}
+
+/// Send no log records below the info level.
+#[cfg(not(target_arch = "arm"))]
+pub(crate) fn reset() {
+    set_max_level(LevelFilter::Info);
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::hal::sim,
+        std::{string::ToString, vec},
+    };
+
+    fn sent() -> Vec<Vec<u8>> {
+        sim::take_sysex()
+            .into_iter()
+            .map(|(port, data)| {
+                assert_eq!(port, Port::USB);
+                data
+            })
+            .collect()
+    }
+
+    #[test]
+    fn records_are_sent_as_sysex() {
+        sim::reset();
+        crate::warn!(target: "life", "{} cells alive", 12);
+        crate::info!("started");
+
+        let frames = sent();
+        assert_eq!(frames.len(), 2);
+        assert!(frames
+            .iter()
+            .all(|frame| frame[1..frame.len() - 1].iter().all(|&byte| byte < 0x80)));
+
+        let records = decode(&frames.concat());
+        assert_eq!(
+            records,
+            [
+                Record {
+                    level: Level::Warn,
+                    target: "life".to_string(),
+                    message: "12 cells alive".to_string(),
+                },
+                Record {
+                    level: Level::Info,
+                    target: module_path!().to_string(),
+                    message: "started".to_string(),
+                },
+            ]
+        );
+        assert_eq!(records[0].to_string(), "WARN  life: 12 cells alive");
+    }
+
+    #[test]
+    fn records_below_the_max_level_are_not_sent() {
+        sim::reset();
+        crate::debug!("hidden");
+        crate::trace!("hidden");
+        assert!(sent().is_empty());
+
+        set_max_level(LevelFilter::Trace);
+        crate::trace!("shown");
+        assert_eq!(decode(&sent().concat())[0].message, "shown");
+
+        set_max_level(LevelFilter::Off);
+        crate::error!("hidden");
+        assert!(sent().is_empty());
+    }
+
+    #[test]
+    fn long_records_are_split_into_frames() {
+        sim::reset();
+        let message: String = (0..CAPACITY)
+            .map(|i| (b'a' + (i % 26) as u8) as char)
+            .collect();
+        crate::error!(target: "t", "{}", message);
+
+        let frames = sent();
+        assert_eq!(frames.len(), 2);
+        assert!(frames.iter().all(|frame| frame.len() <= 320));
+
+        // the record is truncated to fit the buffer
+        let records = decode(&frames.concat());
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].message, message[..CAPACITY - 2]);
+
+        // a record with a missing frame is dropped, without affecting the next one
+        let mut decoder = Decoder::new();
+        assert_eq!(decoder.push(&frames[1]), None);
+        crate::error!(target: "t", "short");
+        assert_eq!(decoder.push(&sent()[0]).unwrap().message, "short");
+    }
+
+    #[test]
+    fn other_messages_are_ignored() {
+        let mut dump = vec![0xF0, 0x7D, b'L', b'R', 0x01, 0xF7, 0x90, 0x3C, 0x7F];
+        dump.extend_from_slice(&[0xF0, 0x7D, b'L', b'G', 9, 0, 0, 0xF7]);
+        assert!(decode(&dump).is_empty());
+    }
+}
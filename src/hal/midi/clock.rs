@@ -0,0 +1,401 @@
+use super::{MidiMessage, Port};
+
+/// Generate a MIDI clock to drive other devices.
+pub mod master;
+
+/// The number of MIDI clock ticks in a beat (a quarter note).
+pub const TICKS_PER_BEAT: u32 = 24;
+
+/// The number of clock ticks in a sixteenth note, the unit used by song position pointers.
+const TICKS_PER_SIXTEENTH: u32 = TICKS_PER_BEAT / 4;
+
+/// If no clock tick is received for this many milliseconds the tempo estimate is discarded, as
+/// the clock has stopped or changed source. This corresponds to a tempo of 5 BPM.
+const TIMEOUT: u32 = 500;
+
+/// How strongly the tempo estimate is smoothed, as the weight given to the previous estimate is
+/// `1 - 1 / 2^SMOOTHING`. The millisecond clock only resolves clock ticks to the nearest
+/// millisecond, which is around 5% of a tick at 120 BPM.
+const SMOOTHING: u32 = 4;
+
+/// The number of fractional bits used for the tick interval.
+const FRACTION_BITS: u32 = 8;
+
+/// Whether the clock being followed is playing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Transport {
+    Stopped,
+    Playing,
+}
+
+/// The events raised when following a MIDI clock.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// Playback started from the beginning of the song.
+    Start,
+    /// Playback continued from the current position.
+    Continue,
+    /// Playback stopped.
+    Stop,
+    /// The position was moved by a song position pointer, to the given tick.
+    Position(u32),
+    /// A clock tick was received while playing, at the given tick. Ticks are counted from the
+    /// beginning of the song.
+    Tick(u32),
+    /// A new beat started, numbered from the beginning of the song.
+    Beat(u32),
+    /// A new bar started, numbered from the beginning of the song.
+    Bar(u32),
+}
+
+/// Follow a MIDI clock sent by another device, such as a DAW. The follower tracks whether the
+/// clock is playing and its position in 24 PPQN ticks, and estimates its tempo.
+///
+/// # Example
+///
+/// ```
+/// use launchpad_pro_rs::hal::midi::{
+///     clock::{Event, Follower, Transport},
+///     MidiMessage,
+/// };
+///
+/// let mut follower = Follower::new(4);
+/// assert_eq!(
+///     follower.process(MidiMessage::Start, 0).collect::<Vec<_>>(),
+///     [Event::Start]
+/// );
+/// assert_eq!(
+///     follower.process(MidiMessage::TimingClock, 0).collect::<Vec<_>>(),
+///     [Event::Tick(0), Event::Beat(0), Event::Bar(0)]
+/// );
+/// assert_eq!(follower.transport(), Transport::Playing);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Follower {
+    transport: Transport,
+    position: u32,
+    beats_per_bar: u32,
+    last_tick: Option<u32>,
+    interval: u32,
+}
+
+impl Follower {
+    /// Construct a follower for a clock that is stopped at the beginning of the song. Bars are
+    /// counted with the given number of beats, where zero is treated as one.
+    pub const fn new(beats_per_bar: u8) -> Self {
+        Self {
+            transport: Transport::Stopped,
+            position: 0,
+            beats_per_bar: if beats_per_bar > 0 { beats_per_bar } else { 1 } as u32,
+            last_tick: None,
+            interval: 0,
+        }
+    }
+
+    /// Returns whether the clock is playing.
+    pub const fn transport(&self) -> Transport {
+        self.transport
+    }
+
+    /// Returns the position of the next tick, counted from the beginning of the song.
+    pub const fn position(&self) -> u32 {
+        self.position
+    }
+
+    /// Returns the number of beats in each bar.
+    pub const fn beats_per_bar(&self) -> u8 {
+        self.beats_per_bar as u8
+    }
+
+    /// Set the number of beats in each bar. Zero is treated as one.
+    pub fn set_beats_per_bar(&mut self, beats_per_bar: u8) {
+        self.beats_per_bar = beats_per_bar.max(1) as u32;
+    }
+
+    /// Returns the estimated tempo of the clock in beats per minute, or None if the clock is not
+    /// being received.
+    pub fn bpm(&self) -> Option<f32> {
+        if self.interval == 0 {
+            return None;
+        }
+        let interval = self.interval as f32 / (1 << FRACTION_BITS) as f32;
+        Some(60_000.0 / (interval * TICKS_PER_BEAT as f32))
+    }
+
+    /// Process a MIDI message received at a time in milliseconds, such as that returned by
+    /// [`crate::hal::time::now`]. Returns the events raised, in order. Messages that are not
+    /// clock or transport messages are ignored.
+    pub fn process(&mut self, message: MidiMessage, now: u32) -> impl Iterator<Item = Event> {
+        let mut events = [None; 3];
+        match message {
+            MidiMessage::TimingClock => {
+                self.measure(now);
+                if self.transport == Transport::Playing {
+                    events = self.tick();
+                }
+            }
+            MidiMessage::Start => {
+                self.transport = Transport::Playing;
+                self.position = 0;
+                events[0] = Some(Event::Start);
+            }
+            MidiMessage::Continue => {
+                self.transport = Transport::Playing;
+                events[0] = Some(Event::Continue);
+            }
+            MidiMessage::Stop => {
+                self.transport = Transport::Stopped;
+                events[0] = Some(Event::Stop);
+            }
+            MidiMessage::SongPosition(position) => {
+                self.position = u32::from(position.value()) * TICKS_PER_SIXTEENTH;
+                events[0] = Some(Event::Position(self.position));
+            }
+            _ => {}
+        }
+        IntoIterator::into_iter(events).flatten()
+    }
+
+    /// Advance the position by a tick, returning the events for the tick that was played.
+    fn tick(&mut self) -> [Option<Event>; 3] {
+        let position = self.position;
+        self.position = self.position.wrapping_add(1);
+
+        let mut events = [Some(Event::Tick(position)), None, None];
+        if position.is_multiple_of(TICKS_PER_BEAT) {
+            let beat = position / TICKS_PER_BEAT;
+            events[1] = Some(Event::Beat(beat));
+            if beat.is_multiple_of(self.beats_per_bar) {
+                events[2] = Some(Event::Bar(beat / self.beats_per_bar));
+            }
+        }
+        events
+    }
+
+    /// Update the tempo estimate with the time of a clock tick.
+    fn measure(&mut self, now: u32) {
+        if let Some(last_tick) = self.last_tick {
+            let elapsed = now.wrapping_sub(last_tick);
+            if elapsed > TIMEOUT {
+                self.interval = 0;
+            } else if self.interval == 0 {
+                self.interval = elapsed << FRACTION_BITS;
+            } else {
+                let elapsed = (elapsed << FRACTION_BITS) as i32;
+                let interval = self.interval as i32;
+                self.interval = (interval + ((elapsed - interval) >> SMOOTHING)) as u32;
+            }
+        }
+        self.last_tick = Some(now);
+    }
+}
+
+impl Default for Follower {
+    /// A follower counting four beats in a bar.
+    fn default() -> Self {
+        Self::new(4)
+    }
+}
+
+/// The number of ports that a clock can be received from.
+const PORTS: usize = 3;
+
+#[cfg(target_arch = "arm")]
+static FOLLOWERS: crate::hal::Mutex<[Follower; PORTS]> =
+    crate::hal::Mutex::new([const { Follower::new(4) }; PORTS]);
+
+#[cfg(target_arch = "arm")]
+fn with_followers<R>(f: impl FnOnce(&mut [Follower; PORTS]) -> R) -> R {
+    f(&mut FOLLOWERS.lock())
+}
+
+#[cfg(not(target_arch = "arm"))]
+std::thread_local! {
+    static FOLLOWERS: core::cell::RefCell<[Follower; PORTS]> =
+        const { core::cell::RefCell::new([const { Follower::new(4) }; PORTS]) };
+}
+
+#[cfg(not(target_arch = "arm"))]
+fn with_followers<R>(f: impl FnOnce(&mut [Follower; PORTS]) -> R) -> R {
+    FOLLOWERS.with(|followers| f(&mut followers.borrow_mut()))
+}
+
+fn with_follower<R>(port: Port, f: impl FnOnce(&mut Follower) -> R) -> R {
+    with_followers(|followers| f(&mut followers[port as usize]))
+}
+
+/// Returns whether the MIDI clock received on a port is playing.
+pub fn transport(port: Port) -> Transport {
+    with_follower(port, |follower| follower.transport())
+}
+
+/// Returns the position of the next tick of the MIDI clock received on a port.
+pub fn position(port: Port) -> u32 {
+    with_follower(port, |follower| follower.position())
+}
+
+/// Returns the estimated tempo of the MIDI clock received on a port, or None if no clock is
+/// being received.
+pub fn bpm(port: Port) -> Option<f32> {
+    with_follower(port, |follower| follower.bpm())
+}
+
+/// Set the number of beats in each bar of the MIDI clocks received on every port. Zero is
+/// treated as one.
+pub fn set_beats_per_bar(beats_per_bar: u8) {
+    with_followers(|followers| {
+        for follower in followers {
+            follower.set_beats_per_bar(beats_per_bar);
+        }
+    })
+}
+
+/// Process a MIDI message received on a port at a time in milliseconds, returning the clock
+/// events it raises. Each port is followed separately, so that a clock received on both USB and
+/// DIN is not counted twice.
+pub(crate) fn follow(port: Port, message: MidiMessage, now: u32) -> impl Iterator<Item = Event> {
+    with_follower(port, |follower| follower.process(message, now))
+}
+
+/// Forget the state of the MIDI clocks received on every port.
+#[cfg(not(target_arch = "arm"))]
+pub(crate) fn reset() {
+    with_followers(|followers| *followers = [const { Follower::new(4) }; PORTS]);
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::hal::midi::U14, std::vec::Vec};
+
+    fn process(follower: &mut Follower, message: MidiMessage, now: u32) -> Vec<Event> {
+        follower.process(message, now).collect()
+    }
+
+    #[test]
+    fn transport_messages_are_followed() {
+        let mut follower = Follower::default();
+        assert_eq!(follower.transport(), Transport::Stopped);
+
+        assert_eq!(
+            process(&mut follower, MidiMessage::Start, 0),
+            [Event::Start]
+        );
+        assert_eq!(follower.transport(), Transport::Playing);
+        assert_eq!(process(&mut follower, MidiMessage::Stop, 0), [Event::Stop]);
+        assert_eq!(follower.transport(), Transport::Stopped);
+        assert_eq!(
+            process(&mut follower, MidiMessage::Continue, 0),
+            [Event::Continue]
+        );
+        assert_eq!(follower.transport(), Transport::Playing);
+        assert_eq!(process(&mut follower, MidiMessage::TuneRequest, 0), []);
+    }
+
+    #[test]
+    fn ticks_only_advance_the_position_while_playing() {
+        let mut follower = Follower::default();
+        assert_eq!(process(&mut follower, MidiMessage::TimingClock, 0), []);
+        assert_eq!(follower.position(), 0);
+
+        process(&mut follower, MidiMessage::Start, 0);
+        for tick in 0..30 {
+            process(&mut follower, MidiMessage::TimingClock, tick * 20);
+        }
+        assert_eq!(follower.position(), 30);
+
+        process(&mut follower, MidiMessage::Stop, 600);
+        process(&mut follower, MidiMessage::TimingClock, 620);
+        assert_eq!(follower.position(), 30);
+
+        // continuing resumes from where the clock stopped, while starting begins again
+        process(&mut follower, MidiMessage::Continue, 630);
+        assert_eq!(
+            process(&mut follower, MidiMessage::TimingClock, 640),
+            [Event::Tick(30)]
+        );
+        process(&mut follower, MidiMessage::Start, 650);
+        assert_eq!(follower.position(), 0);
+    }
+
+    #[test]
+    fn beats_and_bars_are_raised() {
+        let mut follower = Follower::new(3);
+        process(&mut follower, MidiMessage::Start, 0);
+
+        let mut beats = Vec::new();
+        let mut bars = Vec::new();
+        for tick in 0..TICKS_PER_BEAT * 7 {
+            for event in follower.process(MidiMessage::TimingClock, tick * 20) {
+                match event {
+                    Event::Beat(beat) => beats.push((tick, beat)),
+                    Event::Bar(bar) => bars.push((tick, bar)),
+                    _ => {}
+                }
+            }
+        }
+        assert_eq!(
+            beats,
+            [
+                (0, 0),
+                (24, 1),
+                (48, 2),
+                (72, 3),
+                (96, 4),
+                (120, 5),
+                (144, 6)
+            ]
+        );
+        assert_eq!(bars, [(0, 0), (72, 1), (144, 2)]);
+    }
+
+    #[test]
+    fn song_position_moves_the_position() {
+        let mut follower = Follower::default();
+        assert_eq!(
+            process(
+                &mut follower,
+                MidiMessage::SongPosition(U14::new(16).unwrap()),
+                0
+            ),
+            [Event::Position(96)]
+        );
+        process(&mut follower, MidiMessage::Continue, 0);
+        assert_eq!(
+            process(&mut follower, MidiMessage::TimingClock, 0),
+            [Event::Tick(96), Event::Beat(4), Event::Bar(1)]
+        );
+    }
+
+    #[test]
+    fn tempo_is_estimated_despite_jitter() {
+        let mut follower = Follower::default();
+        assert_eq!(follower.bpm(), None);
+
+        // at 120 BPM ticks are 20.83 ms apart, which the millisecond clock rounds to 20 or 21
+        for tick in 0..200u32 {
+            process(&mut follower, MidiMessage::TimingClock, tick * 125 / 6);
+        }
+        let bpm = follower.bpm().unwrap();
+        assert!((bpm - 120.0).abs() < 1.0, "estimated {} BPM", bpm);
+
+        // the tempo follows changes
+        for tick in 0..200u32 {
+            process(&mut follower, MidiMessage::TimingClock, 5000 + tick * 25);
+        }
+        let bpm = follower.bpm().unwrap();
+        assert!((bpm - 100.0).abs() < 1.0, "estimated {} BPM", bpm);
+    }
+
+    #[test]
+    fn tempo_estimate_is_discarded_when_the_clock_stops() {
+        let mut follower = Follower::default();
+        process(&mut follower, MidiMessage::TimingClock, 0);
+        process(&mut follower, MidiMessage::TimingClock, 20);
+        assert!(follower.bpm().is_some());
+
+        process(&mut follower, MidiMessage::TimingClock, 2000);
+        assert_eq!(follower.bpm(), None);
+        process(&mut follower, MidiMessage::TimingClock, 2010);
+        assert_eq!(follower.bpm(), Some(250.0));
+    }
+}
@@ -0,0 +1,526 @@
+use {
+    super::{Transport, TICKS_PER_BEAT, TICKS_PER_SIXTEENTH},
+    crate::hal::{
+        midi::{send_message, Message, MidiMessage, Port, U14},
+        time,
+    },
+    core::sync::atomic::{AtomicU32, Ordering},
+};
+
+/// The slowest tempo the clock can run at, in beats per minute.
+pub const MIN_BPM: f32 = 20.0;
+
+/// The fastest tempo the clock can run at, in beats per minute.
+pub const MAX_BPM: f32 = 300.0;
+
+/// The number of milliseconds in a minute, scaled by the hundredths of a beat the tempo is
+/// stored in, divided by the number of ticks in a beat. A tick is due each time the tempo in
+/// hundredths of a beat per minute has been accumulated this many times over.
+const TICK_THRESHOLD: u32 = 60_000 * 100 / TICKS_PER_BEAT;
+
+/// If the gap between taps is longer than this many milliseconds, the tap starts a new tempo.
+const TAP_TIMEOUT: u32 = 2000;
+
+/// The number of taps averaged to find the tempo.
+const TAPS: usize = 4;
+
+/// Generate a MIDI clock from the 1 ms tick, to drive other devices. Tempos are held to a
+/// hundredth of a beat per minute, and the fraction of a millisecond between ticks is carried
+/// over from one tick to the next so that the clock does not drift.
+///
+/// # Example
+///
+/// ```
+/// use launchpad_pro_rs::hal::midi::{clock::master::Master, MidiMessage};
+///
+/// let mut master = Master::new(120.0);
+/// assert_eq!(master.start(), MidiMessage::Start);
+///
+/// // at 120 BPM there are 48 ticks a second
+/// let ticks = (0..1000).filter_map(|_| master.tick()).count();
+/// assert_eq!(ticks, 48);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Master {
+    centibpm: u32,
+    accumulator: u32,
+    transport: Transport,
+    position: u32,
+}
+
+impl Master {
+    /// Construct a stopped clock at the beginning of the song, with a tempo in beats per minute.
+    pub fn new(bpm: f32) -> Self {
+        let mut master = Self {
+            centibpm: 0,
+            accumulator: TICK_THRESHOLD,
+            transport: Transport::Stopped,
+            position: 0,
+        };
+        master.set_bpm(bpm);
+        master
+    }
+
+    /// Returns the tempo in beats per minute.
+    pub fn bpm(&self) -> f32 {
+        self.centibpm as f32 / 100.0
+    }
+
+    /// Set the tempo in beats per minute, which is clamped between [`MIN_BPM`] and [`MAX_BPM`].
+    pub fn set_bpm(&mut self, bpm: f32) {
+        let bpm = if bpm.is_nan() { MIN_BPM } else { bpm };
+        self.centibpm = (bpm.clamp(MIN_BPM, MAX_BPM) * 100.0 + 0.5) as u32;
+    }
+
+    /// Returns whether the clock is playing.
+    pub const fn transport(&self) -> Transport {
+        self.transport
+    }
+
+    /// Returns the position of the next tick, counted from the beginning of the song.
+    pub const fn position(&self) -> u32 {
+        self.position
+    }
+
+    /// Start playing from the beginning of the song. The next call to [`tick`](Self::tick) sends
+    /// the first clock tick.
+    pub fn start(&mut self) -> MidiMessage {
+        self.transport = Transport::Playing;
+        self.position = 0;
+        self.accumulator = TICK_THRESHOLD;
+        MidiMessage::Start
+    }
+
+    /// Continue playing from the current position.
+    pub fn resume(&mut self) -> MidiMessage {
+        self.transport = Transport::Playing;
+        self.accumulator = TICK_THRESHOLD;
+        MidiMessage::Continue
+    }
+
+    /// Stop playing.
+    pub fn stop(&mut self) -> MidiMessage {
+        self.transport = Transport::Stopped;
+        MidiMessage::Stop
+    }
+
+    /// Move to a position in the song, counted in sixteenth notes. Other devices only expect the
+    /// position to move while the clock is stopped.
+    pub fn set_song_position(&mut self, position: U14) -> MidiMessage {
+        self.position = u32::from(position.value()) * TICKS_PER_SIXTEENTH;
+        MidiMessage::SongPosition(position)
+    }
+
+    /// Returns the position of the song in sixteenth notes, as sent in a song position pointer.
+    pub fn song_position(&self) -> U14 {
+        U14::saturating((self.position / TICKS_PER_SIXTEENTH).min(u16::MAX as u32) as u16)
+    }
+
+    /// Advance the clock by a millisecond, returning a clock tick if one is due. Ticks are sent
+    /// whether or not the clock is playing, so that other devices can follow the tempo, but the
+    /// position only advances while playing.
+    pub fn tick(&mut self) -> Option<MidiMessage> {
+        let due = self.accumulator >= TICK_THRESHOLD;
+        if due {
+            self.accumulator -= TICK_THRESHOLD;
+        }
+        self.accumulator += self.centibpm;
+        if !due {
+            return None;
+        }
+        if self.transport == Transport::Playing {
+            self.position = self.position.wrapping_add(1);
+        }
+        Some(MidiMessage::TimingClock)
+    }
+}
+
+/// Find a tempo from the times at which a pad is tapped.
+///
+/// # Example
+///
+/// ```
+/// use launchpad_pro_rs::hal::midi::clock::master::TapTempo;
+///
+/// let mut tap_tempo = TapTempo::new();
+/// assert_eq!(tap_tempo.tap(0), None);
+/// assert_eq!(tap_tempo.tap(500), Some(120.0));
+/// assert_eq!(tap_tempo.tap(1000), Some(120.0));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct TapTempo {
+    taps: [u32; TAPS],
+    count: usize,
+}
+
+impl TapTempo {
+    /// Construct a tap tempo with no taps.
+    pub const fn new() -> Self {
+        Self {
+            taps: [0; TAPS],
+            count: 0,
+        }
+    }
+
+    /// Record a tap at a time in milliseconds, such as that returned by [`time::now`]. Returns
+    /// the tempo in beats per minute found from the last few taps, or None if this is the first
+    /// tap. A tap long after the previous one is treated as the first tap.
+    pub fn tap(&mut self, now: u32) -> Option<f32> {
+        if self.count > 0 && now.wrapping_sub(self.taps[self.count - 1]) > TAP_TIMEOUT {
+            self.count = 0;
+        }
+        if self.count == TAPS {
+            self.taps.copy_within(1.., 0);
+            self.count -= 1;
+        }
+        self.taps[self.count] = now;
+        self.count += 1;
+
+        let elapsed = self.taps[self.count - 1].wrapping_sub(self.taps[0]);
+        if self.count < 2 || elapsed == 0 {
+            return None;
+        }
+        Some(60_000.0 * (self.count - 1) as f32 / elapsed as f32)
+    }
+}
+
+/// The clock generated by the app, along with the ports it is sent to.
+struct State {
+    master: Master,
+    ports: [bool; 3],
+    tap_tempo: TapTempo,
+}
+
+impl State {
+    fn new() -> Self {
+        Self {
+            master: Master::new(120.0),
+            ports: [false; 3],
+            tap_tempo: TapTempo::new(),
+        }
+    }
+}
+
+#[cfg(target_arch = "arm")]
+static STATE: crate::hal::Mutex<Option<State>> = crate::hal::Mutex::new(None);
+
+#[cfg(target_arch = "arm")]
+fn with_state<R>(f: impl FnOnce(&mut State) -> R) -> R {
+    f(STATE.lock().get_or_insert_with(State::new))
+}
+
+#[cfg(target_arch = "arm")]
+fn try_with_state<R>(f: impl FnOnce(&mut State) -> R) -> Option<R> {
+    STATE
+        .try_lock()
+        .map(|mut state| f(state.get_or_insert_with(State::new)))
+}
+
+/// The milliseconds that the tick could not count because the app's clock was in use.
+#[cfg(target_arch = "arm")]
+static MISSED_TICKS: AtomicU32 = AtomicU32::new(0);
+
+#[cfg(target_arch = "arm")]
+fn with_missed_ticks<R>(f: impl FnOnce(&AtomicU32) -> R) -> R {
+    f(&MISSED_TICKS)
+}
+
+#[cfg(not(target_arch = "arm"))]
+std::thread_local! {
+    static STATE: core::cell::RefCell<Option<State>> = const { core::cell::RefCell::new(None) };
+}
+
+#[cfg(not(target_arch = "arm"))]
+fn with_state<R>(f: impl FnOnce(&mut State) -> R) -> R {
+    STATE.with(|state| f(state.borrow_mut().get_or_insert_with(State::new)))
+}
+
+#[cfg(not(target_arch = "arm"))]
+fn try_with_state<R>(f: impl FnOnce(&mut State) -> R) -> Option<R> {
+    STATE.with(|state| {
+        Some(f(state
+            .try_borrow_mut()
+            .ok()?
+            .get_or_insert_with(State::new)))
+    })
+}
+
+#[cfg(not(target_arch = "arm"))]
+std::thread_local! {
+    static MISSED_TICKS: AtomicU32 = const { AtomicU32::new(0) };
+}
+
+#[cfg(not(target_arch = "arm"))]
+fn with_missed_ticks<R>(f: impl FnOnce(&AtomicU32) -> R) -> R {
+    MISSED_TICKS.with(|missed| f(missed))
+}
+
+/// Send a message to the ports the clock is sent to.
+fn send(ports: [bool; 3], message: MidiMessage) {
+    let message = Message::from(message);
+    for port in [Port::Standalone, Port::USB, Port::DIN] {
+        if ports[port as usize] {
+            send_message(port, message);
+        }
+    }
+}
+
+/// Run a function on the app's clock, and send the message it returns.
+fn control(f: impl FnOnce(&mut Master) -> MidiMessage) {
+    let (ports, message) = with_state(|state| (state.ports, f(&mut state.master)));
+    send(ports, message);
+}
+
+/// Set the ports the app's clock is sent to. The clock is not sent anywhere until ports are
+/// chosen.
+///
+/// # Example
+///
+/// ```
+/// use launchpad_pro_rs::hal::midi::{clock::master, Port};
+///
+/// master::set_ports(&[Port::USB, Port::DIN]);
+/// master::set_bpm(128.0);
+/// master::start();
+/// ```
+pub fn set_ports(ports: &[Port]) {
+    with_state(|state| {
+        state.ports = [false; 3];
+        for &port in ports {
+            state.ports[port as usize] = true;
+        }
+    });
+}
+
+/// Returns the tempo of the app's clock in beats per minute.
+pub fn bpm() -> f32 {
+    with_state(|state| state.master.bpm())
+}
+
+/// Set the tempo of the app's clock in beats per minute, which is clamped between [`MIN_BPM`]
+/// and [`MAX_BPM`].
+pub fn set_bpm(bpm: f32) {
+    with_state(|state| state.master.set_bpm(bpm));
+}
+
+/// Returns whether the app's clock is playing.
+pub fn transport() -> Transport {
+    with_state(|state| state.master.transport())
+}
+
+/// Returns the position of the next tick of the app's clock, counted from the beginning of the
+/// song.
+pub fn position() -> u32 {
+    with_state(|state| state.master.position())
+}
+
+/// Start the app's clock from the beginning of the song.
+pub fn start() {
+    control(Master::start);
+}
+
+/// Continue the app's clock from its current position.
+pub fn resume() {
+    control(Master::resume);
+}
+
+/// Stop the app's clock.
+pub fn stop() {
+    control(Master::stop);
+}
+
+/// Move the app's clock to a position in the song, counted in sixteenth notes.
+pub fn set_song_position(position: U14) {
+    control(|master| master.set_song_position(position));
+}
+
+/// Tap the tempo of the app's clock, typically when a pad is pressed. Returns the new tempo once
+/// enough taps have been made to find it.
+pub fn tap() -> Option<f32> {
+    let now = time::now();
+    with_state(|state| {
+        let bpm = state.tap_tempo.tap(now)?;
+        state.master.set_bpm(bpm);
+        Some(state.master.bpm())
+    })
+}
+
+/// Advance the app's clock by a millisecond, sending a clock tick if one is due. This is called
+/// from the 1 ms tick, which can interrupt the app while it is using the clock, so rather than
+/// wait for it the millisecond is counted on the next tick.
+pub(crate) fn tick() {
+    let ticks = with_missed_ticks(|missed| missed.swap(0, Ordering::Relaxed)) + 1;
+    let due = try_with_state(|state| {
+        if state.ports.contains(&true) {
+            let due = (0..ticks).filter_map(|_| state.master.tick()).count();
+            (state.ports, due)
+        } else {
+            (state.ports, 0)
+        }
+    });
+    match due {
+        Some((ports, due)) => {
+            for _ in 0..due {
+                send(ports, MidiMessage::TimingClock);
+            }
+        }
+        None => {
+            with_missed_ticks(|missed| missed.fetch_add(ticks, Ordering::Relaxed));
+        }
+    }
+}
+
+/// Forget the app's clock, including the ports it is sent to.
+#[cfg(not(target_arch = "arm"))]
+pub(crate) fn reset() {
+    STATE.with(|state| *state.borrow_mut() = None);
+    with_missed_ticks(|missed| missed.store(0, Ordering::Relaxed));
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::hal::sim, std::vec::Vec};
+
+    fn count_ticks(master: &mut Master, milliseconds: u32) -> u32 {
+        (0..milliseconds).filter_map(|_| master.tick()).count() as u32
+    }
+
+    #[test]
+    fn tempo_does_not_drift() {
+        for &bpm in &[120.0, 97.5, 133.33, MAX_BPM, MIN_BPM] {
+            let mut master = Master::new(bpm);
+            let ticks = count_ticks(&mut master, 60_000);
+            let expected = (bpm * TICKS_PER_BEAT as f32).round() as u32;
+            assert!(
+                ticks == expected || ticks == expected + 1,
+                "{} ticks in a minute at {} BPM",
+                ticks,
+                bpm
+            );
+        }
+    }
+
+    #[test]
+    fn ticks_are_evenly_spaced() {
+        let mut master = Master::new(120.0);
+        let times: Vec<u32> = (0..1000).filter(|_| master.tick().is_some()).collect();
+        for pair in times.windows(2) {
+            let gap = pair[1] - pair[0];
+            assert!(gap == 20 || gap == 21, "gap of {} ms", gap);
+        }
+    }
+
+    #[test]
+    fn tempo_is_clamped() {
+        assert_eq!(Master::new(1000.0).bpm(), MAX_BPM);
+        assert_eq!(Master::new(1.0).bpm(), MIN_BPM);
+        assert_eq!(Master::new(f32::NAN).bpm(), MIN_BPM);
+        assert_eq!(Master::new(128.5).bpm(), 128.5);
+    }
+
+    #[test]
+    fn position_advances_while_playing() {
+        let mut master = Master::new(120.0);
+        count_ticks(&mut master, 1000);
+        assert_eq!(master.position(), 0);
+
+        assert_eq!(master.start(), MidiMessage::Start);
+        assert_eq!(master.tick(), Some(MidiMessage::TimingClock));
+        count_ticks(&mut master, 999);
+        assert_eq!(master.position(), 48);
+
+        assert_eq!(master.stop(), MidiMessage::Stop);
+        count_ticks(&mut master, 1000);
+        assert_eq!(master.position(), 48);
+        assert_eq!(master.song_position(), U14::new(8).unwrap());
+
+        assert_eq!(master.resume(), MidiMessage::Continue);
+        count_ticks(&mut master, 1);
+        assert_eq!(master.position(), 49);
+
+        let position = U14::new(32).unwrap();
+        assert_eq!(
+            master.set_song_position(position),
+            MidiMessage::SongPosition(position)
+        );
+        assert_eq!(master.position(), 192);
+    }
+
+    #[test]
+    fn tempo_can_be_tapped() {
+        let mut tap_tempo = TapTempo::new();
+        assert_eq!(tap_tempo.tap(1000), None);
+        assert_eq!(tap_tempo.tap(1600), Some(100.0));
+        assert_eq!(tap_tempo.tap(2200), Some(100.0));
+
+        // only the last few taps are averaged
+        for time in &[2700, 3200, 3700] {
+            tap_tempo.tap(*time);
+        }
+        assert_eq!(tap_tempo.tap(4200), Some(120.0));
+
+        // a long pause starts again
+        assert_eq!(tap_tempo.tap(10_000), None);
+        assert_eq!(tap_tempo.tap(10_250), Some(240.0));
+    }
+
+    #[test]
+    fn clock_is_sent_to_the_chosen_ports() {
+        let mut simulator = sim::Simulator::idle();
+
+        // the clock is not sent until ports are chosen
+        simulator.advance(100);
+        assert!(sim::take_midi().is_empty());
+
+        set_ports(&[Port::USB, Port::DIN]);
+        set_bpm(125.0);
+        start();
+        simulator.advance(1000);
+        stop();
+
+        let midi = sim::take_midi();
+        let sent = |port, status| {
+            midi.iter()
+                .filter(|(sent_port, message)| *sent_port == port && message.status() == status)
+                .count()
+        };
+        for &port in &[Port::USB, Port::DIN] {
+            assert_eq!(sent(port, 0xFA), 1);
+            assert_eq!(sent(port, 0xF8), 50);
+            assert_eq!(sent(port, 0xFC), 1);
+        }
+        assert_eq!(sent(Port::Standalone, 0xF8), 0);
+        assert_eq!(position(), 50);
+        assert_eq!(transport(), Transport::Stopped);
+    }
+
+    #[test]
+    fn tapping_sets_the_tempo() {
+        let mut simulator = sim::Simulator::idle();
+
+        assert_eq!(tap(), None);
+        simulator.advance(400);
+        assert_eq!(tap(), Some(150.0));
+        assert_eq!(bpm(), 150.0);
+    }
+
+    #[test]
+    fn ticks_are_not_lost_while_the_clock_is_in_use() {
+        reset();
+        set_ports(&[Port::USB]);
+        set_bpm(150.0);
+        start();
+        sim::take_midi();
+
+        // the first tick is due straight away, and the next 17 ms later
+        with_state(|_| tick());
+        assert!(sim::take_midi().is_empty());
+        for _ in 0..16 {
+            tick();
+        }
+        assert_eq!(sim::take_midi().len(), 1);
+        tick();
+        assert_eq!(sim::take_midi().len(), 1);
+        assert_eq!(position(), 2);
+    }
+}
@@ -0,0 +1,565 @@
+use core::{convert::TryFrom, fmt};
+
+/// A MIDI message, made up of a status byte and two data bytes. Messages that use fewer than
+/// two data bytes have their unused data bytes set to zero.
+///
+/// A message can only be constructed from a valid status byte and 7-bit data bytes. To check a
+/// message at compile time, construct it in a constant.
+///
+/// # Example
+///
+/// ```
+/// use launchpad_pro_rs::hal::midi::Message;
+///
+/// const NOTE_ON: Message = Message::new(0x90, (60, 127)).expect("note on is valid");
+/// assert_eq!(NOTE_ON.status(), 0x90);
+///
+/// assert_eq!(Message::new(0x90, (200, 255)), None);
+/// assert_eq!(Message::new(0x3C, (0, 0)), None);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Message {
+    status: u8,
+    data: (U7, U7),
+}
+
+impl Message {
+    /// Construct a new MIDI message, or return None if the status byte is not a status other
+    /// than the start or end of a SysEx message, or if either data byte is greater than 127.
+    pub const fn new(status: u8, data: (u8, u8)) -> Option<Self> {
+        match (U7::new(data.0), U7::new(data.1)) {
+            (Some(data1), Some(data2)) if status >= 0x80 && status != 0xF0 && status != 0xF7 => {
+                Some(Self {
+                    status,
+                    data: (data1, data2),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the status byte of the message.
+    pub const fn status(&self) -> u8 {
+        self.status
+    }
+
+    /// Returns the data bytes of the message.
+    pub const fn data(&self) -> (U7, U7) {
+        self.data
+    }
+}
+
+/// A MIDI channel, numbered from 0 to 15. Channels are usually displayed to users numbered from 1
+/// to 16.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Channel(u8);
+
+impl Channel {
+    /// Construct a channel, or return None if the channel is not in the range 0 to 15.
+    pub const fn new(channel: u8) -> Option<Self> {
+        if channel < 16 {
+            Some(Self(channel))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the channel, in the range 0 to 15.
+    pub const fn value(self) -> u8 {
+        self.0
+    }
+}
+
+/// A 7-bit value carried in a MIDI data byte, such as a note number or a velocity.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct U7(u8);
+
+impl U7 {
+    /// The largest 7-bit value.
+    pub const MAX: Self = Self(0x7F);
+
+    /// Construct a 7-bit value, or return None if the value is greater than 127.
+    pub const fn new(value: u8) -> Option<Self> {
+        if value <= Self::MAX.0 {
+            Some(Self(value))
+        } else {
+            None
+        }
+    }
+
+    /// Construct a 7-bit value, clamping values greater than 127 to 127.
+    pub const fn saturating(value: u8) -> Self {
+        if value <= Self::MAX.0 {
+            Self(value)
+        } else {
+            Self::MAX
+        }
+    }
+
+    /// Returns the value, in the range 0 to 127.
+    pub const fn value(self) -> u8 {
+        self.0
+    }
+}
+
+impl From<U7> for u8 {
+    fn from(value: U7) -> Self {
+        value.0
+    }
+}
+
+impl TryFrom<u8> for U7 {
+    type Error = u8;
+
+    /// Convert a byte to a 7-bit value, returning the byte as the error if it is out of range.
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Self::new(value).ok_or(value)
+    }
+}
+
+impl fmt::UpperHex for U7 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::UpperHex::fmt(&self.0, f)
+    }
+}
+
+/// A 14-bit value carried in a pair of MIDI data bytes, such as a pitch bend or a pair of
+/// controllers where one holds the most significant bits and another the least.
+///
+/// # Example
+///
+/// ```
+/// use launchpad_pro_rs::hal::midi::{U14, U7};
+///
+/// let value = U14::new(0x2001).unwrap();
+/// assert_eq!(value.split(), (U7::new(0x40).unwrap(), U7::new(0x01).unwrap()));
+/// assert_eq!(U14::join(U7::new(0x40).unwrap(), U7::new(0x01).unwrap()), value);
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct U14(u16);
+
+impl U14 {
+    /// The largest 14-bit value.
+    pub const MAX: Self = Self(0x3FFF);
+    /// The centre of the range, which is the resting position of a pitch bend.
+    pub const CENTRE: Self = Self(0x2000);
+
+    /// Construct a 14-bit value, or return None if the value is greater than 16383.
+    pub const fn new(value: u16) -> Option<Self> {
+        if value <= Self::MAX.0 {
+            Some(Self(value))
+        } else {
+            None
+        }
+    }
+
+    /// Construct a 14-bit value, clamping values greater than 16383 to 16383.
+    pub const fn saturating(value: u16) -> Self {
+        if value <= Self::MAX.0 {
+            Self(value)
+        } else {
+            Self::MAX
+        }
+    }
+
+    /// Returns the value, in the range 0 to 16383.
+    pub const fn value(self) -> u16 {
+        self.0
+    }
+
+    /// Join the most and least significant 7 bits of a value.
+    pub const fn join(msb: U7, lsb: U7) -> Self {
+        Self((msb.0 as u16) << 7 | lsb.0 as u16)
+    }
+
+    /// Split a value into its most and least significant 7 bits.
+    pub const fn split(self) -> (U7, U7) {
+        (self.msb(), self.lsb())
+    }
+
+    /// Returns the most significant 7 bits of the value.
+    pub const fn msb(self) -> U7 {
+        U7((self.0 >> 7) as u8)
+    }
+
+    /// Returns the least significant 7 bits of the value.
+    pub const fn lsb(self) -> U7 {
+        U7((self.0 & 0x7F) as u8)
+    }
+}
+
+impl From<U14> for u16 {
+    fn from(value: U14) -> Self {
+        value.0
+    }
+}
+
+impl TryFrom<u16> for U14 {
+    type Error = u16;
+
+    /// Convert a number to a 14-bit value, returning the number as the error if it is out of
+    /// range.
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        Self::new(value).ok_or(value)
+    }
+}
+
+/// A MIDI message, decoded into its type and values.
+///
+/// # Example
+///
+/// ```
+/// use {
+///     launchpad_pro_rs::hal::midi::{Channel, Message, MidiMessage, U7},
+///     std::convert::TryFrom,
+/// };
+///
+/// let note_on = MidiMessage::NoteOn {
+///     channel: Channel::new(0).unwrap(),
+///     note: U7::new(60).unwrap(),
+///     velocity: U7::MAX,
+/// };
+/// let message = Message::new(0x90, (60, 127)).unwrap();
+/// assert_eq!(Message::from(note_on), message);
+/// assert_eq!(MidiMessage::try_from(message), Ok(note_on));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MidiMessage {
+    NoteOff {
+        channel: Channel,
+        note: U7,
+        velocity: U7,
+    },
+    NoteOn {
+        channel: Channel,
+        note: U7,
+        velocity: U7,
+    },
+    PolyPressure {
+        channel: Channel,
+        note: U7,
+        pressure: U7,
+    },
+    ControlChange {
+        channel: Channel,
+        control: U7,
+        value: U7,
+    },
+    ProgramChange {
+        channel: Channel,
+        program: U7,
+    },
+    ChannelPressure {
+        channel: Channel,
+        pressure: U7,
+    },
+    /// A pitch bend, where the centre of the range is no bend.
+    PitchBend {
+        channel: Channel,
+        value: U14,
+    },
+    /// A MIDI time code quarter frame.
+    TimeCodeQuarterFrame(U7),
+    /// The position in the song, counted in sixteenth notes since the start.
+    SongPosition(U14),
+    SongSelect(U7),
+    TuneRequest,
+    TimingClock,
+    Start,
+    Continue,
+    Stop,
+    ActiveSensing,
+    Reset,
+}
+
+impl MidiMessage {
+    /// Returns the channel of a channel message, or None for a system message.
+    pub const fn channel(&self) -> Option<Channel> {
+        match *self {
+            MidiMessage::NoteOff { channel, .. }
+            | MidiMessage::NoteOn { channel, .. }
+            | MidiMessage::PolyPressure { channel, .. }
+            | MidiMessage::ControlChange { channel, .. }
+            | MidiMessage::ProgramChange { channel, .. }
+            | MidiMessage::ChannelPressure { channel, .. }
+            | MidiMessage::PitchBend { channel, .. } => Some(channel),
+            _ => None,
+        }
+    }
+
+    /// Returns whether the message is a system realtime message, which may be sent at any time.
+    pub const fn is_realtime(&self) -> bool {
+        matches!(
+            self,
+            MidiMessage::TimingClock
+                | MidiMessage::Start
+                | MidiMessage::Continue
+                | MidiMessage::Stop
+                | MidiMessage::ActiveSensing
+                | MidiMessage::Reset
+        )
+    }
+}
+
+/// The reasons a raw MIDI message cannot be decoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The status byte is not one that a message can be decoded from, as it is undefined.
+    InvalidStatus(u8),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidStatus(status) => write!(f, "invalid status byte {:02X}", status),
+        }
+    }
+}
+
+impl TryFrom<Message> for MidiMessage {
+    type Error = Error;
+
+    /// Decode a message. Data bytes that are not used by the message are ignored.
+    fn try_from(message: Message) -> Result<Self, Self::Error> {
+        let Message {
+            status,
+            data: (data1, data2),
+        } = message;
+        let channel = Channel(status & 0x0F);
+
+        Ok(match status {
+            0x80..=0x8F => MidiMessage::NoteOff {
+                channel,
+                note: data1,
+                velocity: data2,
+            },
+            0x90..=0x9F => MidiMessage::NoteOn {
+                channel,
+                note: data1,
+                velocity: data2,
+            },
+            0xA0..=0xAF => MidiMessage::PolyPressure {
+                channel,
+                note: data1,
+                pressure: data2,
+            },
+            0xB0..=0xBF => MidiMessage::ControlChange {
+                channel,
+                control: data1,
+                value: data2,
+            },
+            0xC0..=0xCF => MidiMessage::ProgramChange {
+                channel,
+                program: data1,
+            },
+            0xD0..=0xDF => MidiMessage::ChannelPressure {
+                channel,
+                pressure: data1,
+            },
+            0xE0..=0xEF => MidiMessage::PitchBend {
+                channel,
+                value: U14::join(data2, data1),
+            },
+            0xF1 => MidiMessage::TimeCodeQuarterFrame(data1),
+            0xF2 => MidiMessage::SongPosition(U14::join(data2, data1)),
+            0xF3 => MidiMessage::SongSelect(data1),
+            0xF6 => MidiMessage::TuneRequest,
+            0xF8 => MidiMessage::TimingClock,
+            0xFA => MidiMessage::Start,
+            0xFB => MidiMessage::Continue,
+            0xFC => MidiMessage::Stop,
+            0xFE => MidiMessage::ActiveSensing,
+            0xFF => MidiMessage::Reset,
+            _ => return Err(Error::InvalidStatus(status)),
+        })
+    }
+}
+
+impl From<MidiMessage> for Message {
+    /// Encode a message. Data bytes that are not used by the message are zero.
+    fn from(message: MidiMessage) -> Self {
+        let channel_message = |status: u8, channel: Channel, data: (U7, U7)| Message {
+            status: status | channel.0,
+            data,
+        };
+        let system_message = |status: u8, data: (U7, U7)| Message { status, data };
+        let none = U7(0);
+
+        match message {
+            MidiMessage::NoteOff {
+                channel,
+                note,
+                velocity,
+            } => channel_message(0x80, channel, (note, velocity)),
+            MidiMessage::NoteOn {
+                channel,
+                note,
+                velocity,
+            } => channel_message(0x90, channel, (note, velocity)),
+            MidiMessage::PolyPressure {
+                channel,
+                note,
+                pressure,
+            } => channel_message(0xA0, channel, (note, pressure)),
+            MidiMessage::ControlChange {
+                channel,
+                control,
+                value,
+            } => channel_message(0xB0, channel, (control, value)),
+            MidiMessage::ProgramChange { channel, program } => {
+                channel_message(0xC0, channel, (program, none))
+            }
+            MidiMessage::ChannelPressure { channel, pressure } => {
+                channel_message(0xD0, channel, (pressure, none))
+            }
+            MidiMessage::PitchBend { channel, value } => {
+                channel_message(0xE0, channel, (value.lsb(), value.msb()))
+            }
+            MidiMessage::TimeCodeQuarterFrame(value) => system_message(0xF1, (value, none)),
+            MidiMessage::SongPosition(position) => {
+                system_message(0xF2, (position.lsb(), position.msb()))
+            }
+            MidiMessage::SongSelect(song) => system_message(0xF3, (song, none)),
+            MidiMessage::TuneRequest => system_message(0xF6, (none, none)),
+            MidiMessage::TimingClock => system_message(0xF8, (none, none)),
+            MidiMessage::Start => system_message(0xFA, (none, none)),
+            MidiMessage::Continue => system_message(0xFB, (none, none)),
+            MidiMessage::Stop => system_message(0xFC, (none, none)),
+            MidiMessage::ActiveSensing => system_message(0xFE, (none, none)),
+            MidiMessage::Reset => system_message(0xFF, (none, none)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(status: u8, data: (u8, u8)) -> Result<MidiMessage, Error> {
+        MidiMessage::try_from(Message::new(status, data).unwrap())
+    }
+
+    #[test]
+    fn channel_messages_are_decoded() {
+        let channel = Channel::new(9).unwrap();
+
+        assert_eq!(
+            decode(0x89, (60, 64)),
+            Ok(MidiMessage::NoteOff {
+                channel,
+                note: U7(60),
+                velocity: U7(64)
+            })
+        );
+        assert_eq!(
+            decode(0xB9, (7, 100)),
+            Ok(MidiMessage::ControlChange {
+                channel,
+                control: U7(7),
+                value: U7(100)
+            })
+        );
+        assert_eq!(
+            decode(0xC9, (5, 0)),
+            Ok(MidiMessage::ProgramChange {
+                channel,
+                program: U7(5)
+            })
+        );
+        assert_eq!(
+            decode(0xE9, (0x00, 0x40)),
+            Ok(MidiMessage::PitchBend {
+                channel,
+                value: U14::CENTRE
+            })
+        );
+        assert_eq!(
+            decode(0xF2, (0x7F, 0x7F)),
+            Ok(MidiMessage::SongPosition(U14::MAX))
+        );
+        assert_eq!(decode(0xF8, (0, 0)), Ok(MidiMessage::TimingClock));
+    }
+
+    #[test]
+    fn invalid_messages_are_rejected() {
+        assert_eq!(Message::new(0x40, (0, 0)), None);
+        assert_eq!(Message::new(0xF0, (0, 0)), None);
+        assert_eq!(Message::new(0xF7, (0, 0)), None);
+        assert_eq!(Message::new(0x90, (60, 0x80)), None);
+        assert_eq!(Message::new(0xE0, (0xFF, 0)), None);
+
+        assert_eq!(decode(0xF4, (0, 0)), Err(Error::InvalidStatus(0xF4)));
+        assert_eq!(decode(0xFD, (0, 0)), Err(Error::InvalidStatus(0xFD)));
+
+        // unused data bytes are ignored
+        assert_eq!(decode(0xFA, (0x7F, 0x7F)), Ok(MidiMessage::Start));
+    }
+
+    #[test]
+    fn every_valid_message_survives_a_round_trip() {
+        for status in 0x80..=0xFF {
+            for &data in &[(0, 0), (1, 2), (60, 127), (127, 127)] {
+                let message = match Message::new(status, data) {
+                    Some(message) => message,
+                    None => continue,
+                };
+                if let Ok(decoded) = MidiMessage::try_from(message) {
+                    let encoded = Message::from(decoded);
+                    assert_eq!(encoded.status(), status);
+                    assert_eq!(MidiMessage::try_from(encoded), Ok(decoded));
+                    if (0x80..0xC0).contains(&status) || (0xE0..0xF0).contains(&status) {
+                        assert_eq!(encoded, message);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn values_are_range_checked() {
+        assert_eq!(Channel::new(15).map(Channel::value), Some(15));
+        assert_eq!(Channel::new(16), None);
+        assert_eq!(U7::new(127), Some(U7::MAX));
+        assert_eq!(U7::new(128), None);
+        assert_eq!(U7::try_from(200), Err(200));
+        assert_eq!(U14::new(16383), Some(U14::MAX));
+        assert_eq!(U14::new(16384), None);
+        assert_eq!(U14::try_from(0xFFFF), Err(0xFFFF));
+    }
+
+    #[test]
+    fn values_can_be_saturated() {
+        assert_eq!(U7::saturating(100).value(), 100);
+        assert_eq!(U7::saturating(200), U7::MAX);
+        assert_eq!(U14::saturating(8000).value(), 8000);
+        assert_eq!(U14::saturating(20000), U14::MAX);
+    }
+
+    #[test]
+    fn fourteen_bit_values_can_be_split_and_joined() {
+        for &value in &[0, 1, 0x7F, 0x80, 0x2000, 0x3FFF] {
+            let value = U14(value);
+            let (msb, lsb) = value.split();
+            assert_eq!(msb, value.msb());
+            assert_eq!(lsb, value.lsb());
+            assert_eq!(U14::join(msb, lsb), value);
+        }
+        assert_eq!(U14::CENTRE.split(), (U7(0x40), U7(0)));
+    }
+
+    #[test]
+    fn messages_know_their_channel() {
+        let channel = Channel::new(3).unwrap();
+        assert_eq!(
+            MidiMessage::ChannelPressure {
+                channel,
+                pressure: U7(1)
+            }
+            .channel(),
+            Some(channel)
+        );
+        assert_eq!(MidiMessage::Stop.channel(), None);
+        assert!(MidiMessage::Stop.is_realtime());
+        assert!(!MidiMessage::TuneRequest.is_realtime());
+    }
+}
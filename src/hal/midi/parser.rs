@@ -0,0 +1,488 @@
+use super::Message;
+
+/// The default capacity of the SysEx buffer, matching the largest SysEx message the Launchpad Pro
+/// can send.
+pub const DEFAULT_SYSEX_CAPACITY: usize = 320;
+
+/// Something found by a [`Parser`] in a stream of MIDI bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Parsed<'a> {
+    /// A complete MIDI message.
+    Message(Message),
+    /// A complete SysEx message, starting with 0xF0 and ending with 0xF7.
+    Sysex(&'a [u8]),
+    /// A SysEx message that was too large for the buffer, and was discarded.
+    SysexOverflow,
+}
+
+/// The data bytes the parser is waiting for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    /// Waiting for a status byte. Data bytes are ignored.
+    Idle,
+    /// Waiting for the data bytes of a message. Channel messages are kept as the running status
+    /// once complete, so that further data bytes start another message with the same status.
+    Data {
+        status: u8,
+        expected: usize,
+        received: usize,
+    },
+    /// Collecting the bytes of a SysEx message.
+    Sysex { overflowed: bool },
+}
+
+/// Parse a stream of MIDI bytes into messages, such as the data received from the DIN port or MIDI
+/// carried inside SysEx. The parser supports running status, system realtime messages interleaved
+/// within other messages, and SysEx messages up to the capacity of its buffer. Data bytes with no
+/// status are ignored, as are undefined status bytes.
+///
+/// # Example
+///
+/// ```
+/// use launchpad_pro_rs::hal::midi::{Message, Parsed, Parser};
+///
+/// let mut parser: Parser = Parser::new();
+/// let mut messages = Vec::new();
+///
+/// // two notes using running status, with a timing clock in the middle of the second
+/// parser.parse(&[0x90, 60, 127, 64, 0xF8, 127], |parsed| {
+///     if let Parsed::Message(message) = parsed {
+///         messages.push(message);
+///     }
+/// });
+///
+/// assert_eq!(
+///     messages,
+///     [
+///         Message::new(0x90, (60, 127)).unwrap(),
+///         Message::new(0xF8, (0, 0)).unwrap(),
+///         Message::new(0x90, (64, 127)).unwrap(),
+///     ]
+/// );
+/// ```
+#[derive(Clone, Debug)]
+pub struct Parser<const SYSEX_CAPACITY: usize = DEFAULT_SYSEX_CAPACITY> {
+    state: State,
+    data: [u8; 2],
+    sysex: [u8; SYSEX_CAPACITY],
+    sysex_length: usize,
+}
+
+impl<const SYSEX_CAPACITY: usize> Parser<SYSEX_CAPACITY> {
+    /// Construct a parser that is waiting for a status byte.
+    pub const fn new() -> Self {
+        Self {
+            state: State::Idle,
+            data: [0; 2],
+            sysex: [0; SYSEX_CAPACITY],
+            sysex_length: 0,
+        }
+    }
+
+    /// Forget any partially received message, including the running status.
+    pub fn reset(&mut self) {
+        self.state = State::Idle;
+        self.sysex_length = 0;
+    }
+
+    /// Parse the next byte in the stream, returning anything that it completes.
+    pub fn push(&mut self, byte: u8) -> Option<Parsed<'_>> {
+        match byte {
+            0xF8..=0xFF => Message::new(byte, (0, 0))
+                .filter(|_| byte != 0xF9 && byte != 0xFD)
+                .map(Parsed::Message),
+            0xF0 => {
+                self.state = State::Sysex { overflowed: false };
+                self.sysex_length = 0;
+                self.push_sysex(byte);
+                None
+            }
+            0xF7 => match self.state {
+                State::Sysex { overflowed } => {
+                    self.state = State::Idle;
+                    if overflowed || !self.push_sysex(byte) {
+                        Some(Parsed::SysexOverflow)
+                    } else {
+                        Some(Parsed::Sysex(&self.sysex[..self.sysex_length]))
+                    }
+                }
+                _ => {
+                    self.state = State::Idle;
+                    None
+                }
+            },
+            0x80..=0xF6 => {
+                // any other status ends an unterminated SysEx message, which is discarded
+                self.state = State::Idle;
+                match Self::data_length(byte) {
+                    Some(0) => Message::new(byte, (0, 0)).map(Parsed::Message),
+                    Some(expected) => {
+                        self.state = State::Data {
+                            status: byte,
+                            expected,
+                            received: 0,
+                        };
+                        None
+                    }
+                    None => None,
+                }
+            }
+            _ => self.push_data(byte),
+        }
+    }
+
+    /// Parse a sequence of bytes, calling a function with everything that they complete.
+    pub fn parse(&mut self, bytes: &[u8], mut f: impl FnMut(Parsed<'_>)) {
+        for &byte in bytes {
+            if let Some(parsed) = self.push(byte) {
+                f(parsed);
+            }
+        }
+    }
+
+    fn push_data(&mut self, byte: u8) -> Option<Parsed<'_>> {
+        match self.state {
+            State::Idle => None,
+            State::Sysex { overflowed } => {
+                if !overflowed && !self.push_sysex(byte) {
+                    self.state = State::Sysex { overflowed: true };
+                }
+                None
+            }
+            State::Data {
+                status,
+                expected,
+                received,
+            } => {
+                self.data[received] = byte;
+                if received + 1 < expected {
+                    self.state = State::Data {
+                        status,
+                        expected,
+                        received: received + 1,
+                    };
+                    return None;
+                }
+
+                self.state = if status < 0xF0 {
+                    State::Data {
+                        status,
+                        expected,
+                        received: 0,
+                    }
+                } else {
+                    State::Idle
+                };
+                let data = (self.data[0], if expected == 2 { self.data[1] } else { 0 });
+                Message::new(status, data).map(Parsed::Message)
+            }
+        }
+    }
+
+    /// Append a byte to the SysEx buffer, returning false if it is full.
+    fn push_sysex(&mut self, byte: u8) -> bool {
+        match self.sysex.get_mut(self.sysex_length) {
+            Some(slot) => {
+                *slot = byte;
+                self.sysex_length += 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the number of data bytes that follow a status byte, or None for undefined statuses.
+    const fn data_length(status: u8) -> Option<usize> {
+        match status {
+            0x80..=0xBF | 0xE0..=0xEF | 0xF2 => Some(2),
+            0xC0..=0xDF | 0xF1 | 0xF3 => Some(1),
+            0xF6 => Some(0),
+            _ => None,
+        }
+    }
+}
+
+impl<const SYSEX_CAPACITY: usize> Default for Parser<SYSEX_CAPACITY> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The things a parser found, with SysEx messages copied so that they can be compared.
+    #[derive(Debug, PartialEq, Eq)]
+    enum Owned {
+        Message(u8, u8, u8),
+        Sysex(Vec<u8>),
+        SysexOverflow,
+    }
+
+    fn parse<const N: usize>(parser: &mut Parser<N>, bytes: &[u8]) -> Vec<Owned> {
+        let mut found = Vec::new();
+        parser.parse(bytes, |parsed| {
+            found.push(match parsed {
+                Parsed::Message(message) => Owned::Message(
+                    message.status(),
+                    message.data().0.into(),
+                    message.data().1.into(),
+                ),
+                Parsed::Sysex(data) => Owned::Sysex(data.to_vec()),
+                Parsed::SysexOverflow => Owned::SysexOverflow,
+            })
+        });
+        found
+    }
+
+    fn parse_all(bytes: &[u8]) -> Vec<Owned> {
+        parse(&mut Parser::<16>::new(), bytes)
+    }
+
+    use Owned::Message as M;
+
+    #[test]
+    fn channel_messages_are_parsed() {
+        assert_eq!(
+            parse_all(&[
+                0x80, 60, 0, 0x91, 60, 127, 0xA2, 60, 10, 0xB3, 7, 100, 0xC4, 5, 0xD5, 20, 0xE6,
+                0x00, 0x40
+            ]),
+            [
+                M(0x80, 60, 0),
+                M(0x91, 60, 127),
+                M(0xA2, 60, 10),
+                M(0xB3, 7, 100),
+                M(0xC4, 5, 0),
+                M(0xD5, 20, 0),
+                M(0xE6, 0x00, 0x40),
+            ]
+        );
+    }
+
+    #[test]
+    fn system_common_messages_are_parsed() {
+        assert_eq!(
+            parse_all(&[0xF1, 0x23, 0xF2, 0x10, 0x20, 0xF3, 4, 0xF6]),
+            [
+                M(0xF1, 0x23, 0),
+                M(0xF2, 0x10, 0x20),
+                M(0xF3, 4, 0),
+                M(0xF6, 0, 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn running_status_is_supported() {
+        assert_eq!(
+            parse_all(&[0x90, 60, 127, 62, 100, 64, 0]),
+            [M(0x90, 60, 127), M(0x90, 62, 100), M(0x90, 64, 0)]
+        );
+        assert_eq!(
+            parse_all(&[0xC0, 1, 2, 3]),
+            [M(0xC0, 1, 0), M(0xC0, 2, 0), M(0xC0, 3, 0)]
+        );
+    }
+
+    #[test]
+    fn running_status_is_kept_across_realtime_messages() {
+        assert_eq!(
+            parse_all(&[0x90, 60, 0xF8, 127, 0xFA, 62, 0xFE, 100]),
+            [
+                M(0xF8, 0, 0),
+                M(0x90, 60, 127),
+                M(0xFA, 0, 0),
+                M(0xFE, 0, 0),
+                M(0x90, 62, 100)
+            ]
+        );
+    }
+
+    #[test]
+    fn system_common_messages_cancel_running_status() {
+        assert_eq!(
+            parse_all(&[0x90, 60, 127, 0xF3, 1, 62, 100]),
+            [M(0x90, 60, 127), M(0xF3, 1, 0)]
+        );
+        assert_eq!(
+            parse_all(&[0x90, 60, 127, 0xF6, 62, 100]),
+            [M(0x90, 60, 127), M(0xF6, 0, 0)]
+        );
+        assert_eq!(
+            parse_all(&[0x90, 60, 127, 0xF4, 62, 100]),
+            [M(0x90, 60, 127)]
+        );
+    }
+
+    #[test]
+    fn all_realtime_messages_are_parsed() {
+        assert_eq!(
+            parse_all(&[0xF8, 0xF9, 0xFA, 0xFB, 0xFC, 0xFD, 0xFE, 0xFF]),
+            [
+                M(0xF8, 0, 0),
+                M(0xFA, 0, 0),
+                M(0xFB, 0, 0),
+                M(0xFC, 0, 0),
+                M(0xFE, 0, 0),
+                M(0xFF, 0, 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn stray_data_bytes_are_ignored() {
+        assert_eq!(parse_all(&[60, 127, 0x90, 60, 127]), [M(0x90, 60, 127)]);
+        assert_eq!(
+            parse_all(&[0xF3, 1, 2, 3, 0xF8]),
+            [M(0xF3, 1, 0), M(0xF8, 0, 0)]
+        );
+    }
+
+    #[test]
+    fn undefined_statuses_are_ignored() {
+        assert_eq!(
+            parse_all(&[0xF4, 1, 0xF5, 2, 0xF9, 0xFD, 0x80, 60, 0]),
+            [M(0x80, 60, 0)]
+        );
+    }
+
+    #[test]
+    fn incomplete_messages_are_discarded_by_a_new_status() {
+        assert_eq!(parse_all(&[0x90, 60, 0x80, 60, 0]), [M(0x80, 60, 0)]);
+        assert_eq!(parse_all(&[0xE0, 0xB0, 7, 100]), [M(0xB0, 7, 100)]);
+    }
+
+    #[test]
+    fn sysex_messages_are_parsed() {
+        assert_eq!(
+            parse_all(&[0xF0, 0x00, 0x20, 0x29, 0xF7, 0x90, 60, 127]),
+            [
+                Owned::Sysex(vec![0xF0, 0x00, 0x20, 0x29, 0xF7]),
+                M(0x90, 60, 127)
+            ]
+        );
+        assert_eq!(parse_all(&[0xF0, 0xF7]), [Owned::Sysex(vec![0xF0, 0xF7])]);
+    }
+
+    #[test]
+    fn realtime_messages_can_interrupt_sysex() {
+        assert_eq!(
+            parse_all(&[0xF0, 0x01, 0xF8, 0x02, 0xFE, 0xF7]),
+            [
+                M(0xF8, 0, 0),
+                M(0xFE, 0, 0),
+                Owned::Sysex(vec![0xF0, 0x01, 0x02, 0xF7])
+            ]
+        );
+    }
+
+    #[test]
+    fn sysex_cancels_running_status() {
+        assert_eq!(
+            parse_all(&[0x90, 60, 127, 0xF0, 0x01, 0xF7, 62, 100]),
+            [M(0x90, 60, 127), Owned::Sysex(vec![0xF0, 0x01, 0xF7])]
+        );
+    }
+
+    #[test]
+    fn unterminated_sysex_is_discarded() {
+        assert_eq!(
+            parse_all(&[0xF0, 0x01, 0x02, 0x90, 60, 127]),
+            [M(0x90, 60, 127)]
+        );
+        assert_eq!(
+            parse_all(&[0xF0, 0x01, 0xF0, 0x02, 0xF7]),
+            [Owned::Sysex(vec![0xF0, 0x02, 0xF7])]
+        );
+    }
+
+    #[test]
+    fn end_of_sysex_without_a_start_is_ignored() {
+        assert_eq!(parse_all(&[0xF7, 0x90, 60, 127]), [M(0x90, 60, 127)]);
+        assert_eq!(parse_all(&[0x90, 60, 0xF7, 127]), []);
+    }
+
+    #[test]
+    fn sysex_that_overflows_the_buffer_is_discarded() {
+        let mut parser = Parser::<4>::new();
+        assert_eq!(
+            parse(&mut parser, &[0xF0, 1, 2, 0xF7]),
+            [Owned::Sysex(vec![0xF0, 1, 2, 0xF7])]
+        );
+        assert_eq!(
+            parse(&mut parser, &[0xF0, 1, 2, 3, 0xF7]),
+            [Owned::SysexOverflow]
+        );
+        assert_eq!(
+            parse(&mut parser, &[0xF0, 1, 2, 3, 4, 5, 6, 0xF7, 0xF0, 7, 0xF7]),
+            [Owned::SysexOverflow, Owned::Sysex(vec![0xF0, 7, 0xF7])]
+        );
+    }
+
+    #[test]
+    fn messages_can_be_split_across_calls() {
+        let mut parser = Parser::<16>::new();
+        assert_eq!(parse(&mut parser, &[0x90]), []);
+        assert_eq!(parse(&mut parser, &[60]), []);
+        assert_eq!(parse(&mut parser, &[127, 62]), [M(0x90, 60, 127)]);
+        assert_eq!(parse(&mut parser, &[100, 0xF0, 1]), [M(0x90, 62, 100)]);
+        assert_eq!(
+            parse(&mut parser, &[2, 0xF7]),
+            [Owned::Sysex(vec![0xF0, 1, 2, 0xF7])]
+        );
+    }
+
+    #[test]
+    fn reset_forgets_partial_messages() {
+        let mut parser = Parser::<16>::new();
+        assert_eq!(parse(&mut parser, &[0x90, 60, 127, 62]), [M(0x90, 60, 127)]);
+        parser.reset();
+        assert_eq!(parse(&mut parser, &[100, 64, 0]), []);
+    }
+
+    /// Encode messages using running status where possible, with realtime messages inserted after
+    /// the bytes chosen by a pseudo-random sequence, and check that they are parsed back in order.
+    #[test]
+    fn random_streams_are_parsed() {
+        let mut seed = 0x1234_5678_u32;
+        let mut random = move |limit: u32| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (seed >> 16) % limit
+        };
+
+        for _ in 0..200 {
+            let mut bytes = Vec::new();
+            let mut expected = Vec::new();
+            let mut running_status = None;
+            for _ in 0..20 {
+                let status = match random(4) {
+                    0 => 0x80 | random(0x70) as u8,
+                    1 => [0xF1, 0xF2, 0xF3, 0xF6][random(4) as usize],
+                    _ => [0x90, 0xC5, 0xE1][random(3) as usize],
+                };
+                let length = Parser::<0>::data_length(status).unwrap();
+                let data = [random(128) as u8, random(128) as u8];
+
+                if running_status != Some(status) {
+                    bytes.push(status);
+                }
+                running_status = if status < 0xF0 { Some(status) } else { None };
+                for &byte in &data[..length] {
+                    if random(4) == 0 {
+                        bytes.push(0xF8);
+                        expected.push(M(0xF8, 0, 0));
+                    }
+                    bytes.push(byte);
+                }
+                expected.push(M(
+                    status,
+                    if length > 0 { data[0] } else { 0 },
+                    if length > 1 { data[1] } else { 0 },
+                ));
+            }
+            assert_eq!(parse_all(&bytes), expected);
+        }
+    }
+}
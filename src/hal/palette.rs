@@ -0,0 +1,168 @@
+use crate::hal::{midi::U7, Blue, Green, Red, Rgb};
+
+/// The number of colours in a palette, one for each 7-bit MIDI value.
+const SIZE: usize = 128;
+
+/// A table of colours indexed by a 7-bit value, which is how most Launchpad software chooses the
+/// colour of a pad: the velocity of a note sent to the Launchpad picks the colour from the
+/// palette.
+///
+/// # Example
+///
+/// ```
+/// use launchpad_pro_rs::hal::{
+///     midi::{MidiMessage, U7},
+///     surface, Palette, Point, Rgb,
+/// };
+///
+/// // light pads from incoming notes the way the stock firmware does
+/// fn show(message: MidiMessage) {
+///     if let MidiMessage::NoteOn { note, velocity, .. } = message {
+///         surface::set_led(
+///             Point::from_index(note.value()),
+///             Palette::LAUNCHPAD.get(velocity),
+///         );
+///     }
+/// }
+///
+/// assert_eq!(Palette::LAUNCHPAD.get(U7::new(5).unwrap()), Rgb::RED);
+/// assert_eq!(Palette::LAUNCHPAD.nearest(Rgb::RED), U7::new(5).unwrap());
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Palette {
+    colours: [Rgb; SIZE],
+}
+
+impl Palette {
+    /// The velocity colour palette of the stock Launchpad Pro firmware.
+    pub const LAUNCHPAD: Palette = Palette::new(LAUNCHPAD);
+
+    /// Construct a palette from its colours.
+    pub const fn new(colours: [Rgb; SIZE]) -> Self {
+        Self { colours }
+    }
+
+    /// Returns the colour at an index in the palette.
+    pub const fn get(&self, index: U7) -> Rgb {
+        self.colours[index.value() as usize]
+    }
+
+    /// Returns the colours in the palette.
+    pub const fn colours(&self) -> &[Rgb; SIZE] {
+        &self.colours
+    }
+
+    /// Returns the index of the colour in the palette that looks most like a colour. If several
+    /// colours match equally well then the lowest index is returned.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use launchpad_pro_rs::hal::{Palette, Rgb};
+    ///
+    /// let orange = Rgb::from_6_bit(63, 24, 0);
+    /// assert_eq!(Palette::LAUNCHPAD.nearest(orange).value(), 9);
+    /// ```
+    pub fn nearest(&self, rgb: Rgb) -> U7 {
+        let mut nearest = (0, u32::MAX);
+        for (index, &colour) in self.colours.iter().enumerate() {
+            let distance = distance(rgb, colour);
+            if distance < nearest.1 {
+                nearest = (index, distance);
+            }
+        }
+        U7::saturating(nearest.0 as u8)
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::LAUNCHPAD
+    }
+}
+
+/// Returns how different two colours look, weighting green most and red least as the eye does.
+fn distance(a: Rgb, b: Rgb) -> u32 {
+    let (Rgb(Red(r0), Green(g0), Blue(b0)), Rgb(Red(r1), Green(g1), Blue(b1))) = (a, b);
+    let square = |a: u8, b: u8| (a as i32 - b as i32).pow(2) as u32;
+    2 * square(r0, r1) + 4 * square(g0, g1) + 3 * square(b0, b1)
+}
+
+/// Construct a colour from a 24-bit hex value.
+const fn rgb(hex: u32) -> Rgb {
+    Rgb::new(
+        Red::new((hex >> 16) as u8),
+        Green::new((hex >> 8) as u8),
+        Blue::new(hex as u8),
+    )
+}
+
+#[rustfmt::skip]
+const LAUNCHPAD: [Rgb; SIZE] = [
+    rgb(0x000000), rgb(0x1C1C1C), rgb(0x7C7C7C), rgb(0xFCFCFC), // 0
+    rgb(0xFF4C4C), rgb(0xFF0000), rgb(0x590000), rgb(0x190000), // 4
+    rgb(0xFFBD6C), rgb(0xFF5400), rgb(0x591D00), rgb(0x271B00), // 8
+    rgb(0xFFFF4C), rgb(0xFFFF00), rgb(0x595900), rgb(0x191900), // 12
+    rgb(0x88FF4C), rgb(0x54FF00), rgb(0x1D5900), rgb(0x142B00), // 16
+    rgb(0x4CFF4C), rgb(0x00FF00), rgb(0x005900), rgb(0x001900), // 20
+    rgb(0x4CFF5E), rgb(0x00FF19), rgb(0x00590D), rgb(0x001902), // 24
+    rgb(0x4CFF88), rgb(0x00FF55), rgb(0x00591D), rgb(0x001F12), // 28
+    rgb(0x4CFFB7), rgb(0x00FF99), rgb(0x005935), rgb(0x001912), // 32
+    rgb(0x4CC3FF), rgb(0x00A9FF), rgb(0x004152), rgb(0x001019), // 36
+    rgb(0x4C88FF), rgb(0x0055FF), rgb(0x001D59), rgb(0x000819), // 40
+    rgb(0x4C4CFF), rgb(0x0000FF), rgb(0x000059), rgb(0x000019), // 44
+    rgb(0x874CFF), rgb(0x5400FF), rgb(0x190064), rgb(0x0F0030), // 48
+    rgb(0xFF4CFF), rgb(0xFF00FF), rgb(0x590059), rgb(0x190019), // 52
+    rgb(0xFF4C87), rgb(0xFF0054), rgb(0x59001D), rgb(0x220013), // 56
+    rgb(0xFF1500), rgb(0x993500), rgb(0x795100), rgb(0x436400), // 60
+    rgb(0x033900), rgb(0x005735), rgb(0x00547F), rgb(0x0000FF), // 64
+    rgb(0x00454F), rgb(0x2500CC), rgb(0x7F7F7F), rgb(0x202020), // 68
+    rgb(0xFF0000), rgb(0xBDFF2D), rgb(0xAFED06), rgb(0x64FF09), // 72
+    rgb(0x108B00), rgb(0x00FF87), rgb(0x00A9FF), rgb(0x002AFF), // 76
+    rgb(0x3F00FF), rgb(0x7A00FF), rgb(0xB21A7D), rgb(0x402100), // 80
+    rgb(0xFF4A00), rgb(0x88E106), rgb(0x72FF15), rgb(0x00FF00), // 84
+    rgb(0x3BFF26), rgb(0x59FF71), rgb(0x38FFCC), rgb(0x5B8AFF), // 88
+    rgb(0x3151C6), rgb(0x877FE9), rgb(0xD31DFF), rgb(0xFF005D), // 92
+    rgb(0xFF7F00), rgb(0xB9B000), rgb(0x90FF00), rgb(0x835D07), // 96
+    rgb(0x392B00), rgb(0x144C10), rgb(0x0D5038), rgb(0x15152A), // 100
+    rgb(0x16205A), rgb(0x693C1C), rgb(0xA8000A), rgb(0xDE513D), // 104
+    rgb(0xD86A1C), rgb(0xFFE126), rgb(0x9EE12F), rgb(0x67B50F), // 108
+    rgb(0x1E1E30), rgb(0xDCFF6B), rgb(0x80FFBD), rgb(0x9A99FF), // 112
+    rgb(0x8E66FF), rgb(0x404040), rgb(0x757575), rgb(0xE0FFFF), // 116
+    rgb(0xA00000), rgb(0x350000), rgb(0x1AD000), rgb(0x074200), // 120
+    rgb(0xB9B000), rgb(0x3F3100), rgb(0xB35F00), rgb(0x4B1502), // 124
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index(value: u8) -> U7 {
+        U7::new(value).unwrap()
+    }
+
+    #[test]
+    fn the_launchpad_palette_has_the_standard_colours() {
+        let palette = Palette::default();
+        assert_eq!(palette.get(index(0)), Rgb::BLACK);
+        assert_eq!(palette.get(index(3)), Rgb::from_6_bit(62, 62, 62));
+        assert_eq!(palette.get(index(13)), Rgb::YELLOW);
+        assert_eq!(palette.get(index(21)), Rgb::GREEN);
+        assert_eq!(palette.get(index(45)), Rgb::BLUE);
+        assert_eq!(palette.get(index(53)), Rgb::MAGENTA);
+        assert_eq!(palette.get(U7::MAX), Rgb::from_6_bit(18, 5, 0));
+    }
+
+    #[test]
+    fn every_colour_in_the_palette_is_its_own_nearest_match() {
+        let palette = Palette::LAUNCHPAD;
+        for (value, &colour) in palette.colours().iter().enumerate() {
+            let nearest = palette.nearest(colour);
+            assert!(nearest.value() <= value as u8);
+            assert_eq!(palette.get(nearest), colour, "{}", value);
+        }
+        assert_eq!(palette.nearest(Rgb::WHITE), index(3));
+        assert_eq!(palette.nearest(Rgb::from_6_bit(1, 0, 1)), index(0));
+        assert_eq!(palette.nearest(Rgb::from_6_bit(0, 60, 2)), index(21));
+    }
+}
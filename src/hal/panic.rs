@@ -0,0 +1,365 @@
+use {
+    crate::hal::{
+        flash,
+        midi::{self, Port},
+        surface::{self, Led},
+        Blue, Green, Grid, Red, Rgb,
+    },
+    core::fmt,
+};
+
+/// The maximum length in bytes of a panic report. Longer reports are truncated.
+pub const CAPACITY: usize = 120;
+
+/// The size in bytes of the area at the end of the user area that is reserved for storing a panic
+/// report, which [`settings`](crate::hal::settings) does not use.
+pub const STORE_SIZE: usize = 128;
+
+/// The offset of the reserved area from the beginning of the user area.
+const STORE_OFFSET: usize = flash::SIZE - STORE_SIZE;
+
+/// Identifies a panic report stored in the reserved area.
+const MAGIC: [u8; 4] = *b"LPPN";
+
+/// The size of the header that precedes a stored report: the magic bytes and its length.
+const HEADER_SIZE: usize = 6;
+
+/// The bytes that start a panic report sent as SysEx.
+const SYSEX_HEADER: [u8; 4] = [0xF0, midi::NON_COMMERCIAL_ID, b'L', b'P'];
+
+/// The maximum length in bytes of a panic report sent as SysEx: the header, the 7-bit packed
+/// report, and the end byte.
+pub const SYSEX_SIZE: usize = SYSEX_HEADER.len() + CAPACITY + CAPACITY.div_ceil(7) + 1;
+
+/// The colour of the pads around the cross in the error pattern.
+const DIM_RED: Rgb = Rgb::new(Red::new(64), Green::new(0), Blue::new(0));
+
+/// What to do once a panic has been reported.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    /// Stop running, leaving the error pattern on the grid.
+    Halt,
+    /// Reset the device, which starts the app again.
+    Reset,
+}
+
+/// How panics are reported.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// Whether to store the report in flash so that it can be read with [`stored`] after the
+    /// device restarts.
+    pub store: bool,
+    /// What to do once the panic has been reported.
+    pub action: Action,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            store: false,
+            action: Action::Halt,
+        }
+    }
+}
+
+/// A description of a panic, with its location and message.
+///
+/// # Example
+///
+/// ```
+/// use {
+///     core::fmt::Write,
+///     launchpad_pro_rs::hal::panic::{self, Report},
+/// };
+///
+/// let mut report = Report::new();
+/// write!(report, "panicked at src/main.rs:10:5:\nout of range").unwrap();
+///
+/// let mut sysex = [0; panic::SYSEX_SIZE];
+/// let length = report.to_sysex(&mut sysex);
+/// assert_eq!(Report::from_sysex(&sysex[..length]), Some(report));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Report {
+    text: [u8; CAPACITY],
+    length: usize,
+}
+
+impl Report {
+    /// Construct an empty report.
+    pub const fn new() -> Self {
+        Self {
+            text: [0; CAPACITY],
+            length: 0,
+        }
+    }
+
+    /// Returns the text of the report.
+    pub fn as_str(&self) -> &str {
+        // the text can only be cut part way through a character when it is truncated
+        let text = &self.text[..self.length];
+        match core::str::from_utf8(text) {
+            Ok(text) => text,
+            Err(error) => core::str::from_utf8(&text[..error.valid_up_to()]).unwrap_or_default(),
+        }
+    }
+
+    /// Encode the report as a SysEx message, returning its length. The buffer must be able to
+    /// hold at least [`SYSEX_SIZE`] bytes.
+    pub fn to_sysex(&self, buffer: &mut [u8]) -> usize {
+        buffer[..SYSEX_HEADER.len()].copy_from_slice(&SYSEX_HEADER);
+        let packed = midi::pack_7bit(self.text(), &mut buffer[SYSEX_HEADER.len()..]).unwrap_or(0);
+        let end = SYSEX_HEADER.len() + packed;
+        buffer[end] = 0xF7;
+        end + 1
+    }
+
+    /// Decode a report sent as a SysEx message, returning None if the message is not a report.
+    pub fn from_sysex(data: &[u8]) -> Option<Self> {
+        let packed = data.strip_prefix(&SYSEX_HEADER)?.strip_suffix(&[0xF7])?;
+        let mut report = Self::new();
+        report.length = midi::unpack_7bit(packed, &mut report.text)?;
+        Some(report)
+    }
+
+    fn text(&self) -> &[u8] {
+        &self.text[..self.length]
+    }
+}
+
+impl Default for Report {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Write for Report {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let count = s.len().min(CAPACITY - self.length);
+        self.text[self.length..self.length + count].copy_from_slice(&s.as_bytes()[..count]);
+        self.length += count;
+        Ok(())
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(target_arch = "arm")]
+static CONFIG: crate::hal::Mutex<Config> = crate::hal::Mutex::new(Config {
+    store: false,
+    action: Action::Halt,
+});
+
+#[cfg(target_arch = "arm")]
+fn with_config<R>(f: impl FnOnce(&mut Config) -> R) -> R {
+    f(&mut CONFIG.lock())
+}
+
+#[cfg(target_arch = "arm")]
+fn try_with_config<R>(f: impl FnOnce(&mut Config) -> R) -> Option<R> {
+    CONFIG.try_lock().map(|mut config| f(&mut config))
+}
+
+#[cfg(not(target_arch = "arm"))]
+std::thread_local! {
+    static CONFIG: core::cell::RefCell<Config> = const {
+        core::cell::RefCell::new(Config { store: false, action: Action::Halt })
+    };
+}
+
+#[cfg(not(target_arch = "arm"))]
+fn with_config<R>(f: impl FnOnce(&mut Config) -> R) -> R {
+    CONFIG.with(|config| f(&mut config.borrow_mut()))
+}
+
+#[cfg(not(target_arch = "arm"))]
+fn try_with_config<R>(f: impl FnOnce(&mut Config) -> R) -> Option<R> {
+    CONFIG.with(|config| Some(f(&mut *config.try_borrow_mut().ok()?)))
+}
+
+/// Returns how panics are reported.
+pub fn config() -> Config {
+    with_config(|config| *config)
+}
+
+/// Change how panics are reported. By default the report is not stored, and the device halts.
+///
+/// # Example
+///
+/// ```
+/// use launchpad_pro_rs::hal::panic::{self, Action, Config};
+///
+/// panic::configure(Config {
+///     store: true,
+///     action: Action::Reset,
+/// });
+/// ```
+pub fn configure(config: Config) {
+    with_config(|current| *current = config);
+}
+
+/// Returns the panic report stored in flash, if there is one.
+pub fn stored() -> Option<Report> {
+    let mut header = [0; HEADER_SIZE];
+    flash::read(STORE_OFFSET, &mut header).ok()?;
+    if header[..4] != MAGIC {
+        return None;
+    }
+
+    let mut report = Report::new();
+    report.length = u16::from_le_bytes([header[4], header[5]]) as usize;
+    if report.length > CAPACITY {
+        return None;
+    }
+    flash::read(
+        STORE_OFFSET + HEADER_SIZE,
+        &mut report.text[..report.length],
+    )
+    .ok()?;
+    Some(report)
+}
+
+/// Remove the panic report stored in flash, so that it is not reported again.
+pub fn clear_stored() {
+    let _ = flash::write(STORE_OFFSET, &[0; 4]);
+}
+
+fn store(report: &Report) {
+    let mut data = [0; HEADER_SIZE + CAPACITY];
+    data[..4].copy_from_slice(&MAGIC);
+    data[4..HEADER_SIZE].copy_from_slice(&(report.length as u16).to_le_bytes());
+    data[HEADER_SIZE..HEADER_SIZE + report.length].copy_from_slice(report.text());
+    let _ = flash::write(STORE_OFFSET, &data[..HEADER_SIZE + report.length]);
+}
+
+/// Draw the error pattern: a bright red cross on a dim red grid.
+fn draw() {
+    for point in Grid::points() {
+        let (x, y) = (point.x(), point.y());
+        let inner = (1..=8).contains(&x) && (1..=8).contains(&y);
+        let colour = if inner && (x == y || x + y == 9) {
+            Rgb::RED
+        } else {
+            DIM_RED
+        };
+        surface::set_led(point, colour);
+    }
+    surface::set_led(Led::Setup, Rgb::RED);
+}
+
+/// Show the report on the device, send it over USB and store it if configured to, returning what
+/// to do next. The panic may have happened while the configuration was being changed, so rather
+/// than wait for it the default configuration is used.
+#[doc(hidden)]
+pub fn report(report: &Report) -> Action {
+    let config = try_with_config(|config| *config).unwrap_or_default();
+
+    draw();
+
+    let mut sysex = [0; SYSEX_SIZE];
+    let length = report.to_sysex(&mut sysex);
+    midi::send_sysex(Port::USB, &sysex[..length]);
+
+    if config.store {
+        store(report);
+    }
+
+    config.action
+}
+
+/// Report a panic, then halt or reset the device. Called by the panic handler generated by the
+/// `launchpad_app!` macro.
+#[doc(hidden)]
+#[cfg(target_arch = "arm")]
+pub fn handle(info: &core::panic::PanicInfo) -> ! {
+    use core::fmt::Write;
+
+    let mut panic = Report::new();
+    let _ = write!(panic, "{}", info);
+    if report(&panic) == Action::Reset {
+        // request a system reset through the Cortex-M application interrupt and reset control
+        // register
+        const AIRCR: *mut u32 = 0xE000_ED0C as *mut u32;
+        unsafe { core::ptr::write_volatile(AIRCR, 0x05FA_0004) };
+    }
+    loop {
+        core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::hal::{sim, Point},
+        core::fmt::Write,
+        std::{string::ToString, vec::Vec},
+    };
+
+    fn report_of(text: &str) -> Report {
+        let mut report = Report::new();
+        report.write_str(text).unwrap();
+        report
+    }
+
+    #[test]
+    fn reports_are_truncated() {
+        let report = report_of(&"x".repeat(200));
+        assert_eq!(report.as_str().len(), CAPACITY);
+
+        // a character cut in two by truncation is dropped
+        let report = report_of(&("x".repeat(CAPACITY - 1) + "é"));
+        assert_eq!(report.as_str(), "x".repeat(CAPACITY - 1));
+    }
+
+    #[test]
+    fn the_largest_report_fits_in_a_sysex_message() {
+        let report = report_of(&"\u{FF}".repeat(CAPACITY));
+        let mut sysex = [0; SYSEX_SIZE];
+        let length = report.to_sysex(&mut sysex);
+        assert_eq!(length, SYSEX_SIZE);
+        assert!(sysex[1..length - 1].iter().all(|&byte| byte < 0x80));
+        assert_eq!(Report::from_sysex(&sysex[..length]), Some(report));
+        assert_eq!(Report::from_sysex(&[0xF0, 0x7D, b'L', b'R', 0xF7]), None);
+    }
+
+    #[test]
+    fn panics_are_reported() {
+        sim::reset();
+        let panic = report_of("panicked at src/main.rs:1:1:\noops");
+        assert_eq!(report(&panic), Action::Halt);
+
+        let frame = sim::frame();
+        assert_eq!(frame.led(Point::new(1, 1)), Rgb::RED);
+        assert_eq!(frame.led(Point::new(8, 1)), Rgb::RED);
+        assert_eq!(frame.led(Point::new(2, 1)), DIM_RED);
+        assert_eq!(frame.led(Led::Setup), Rgb::RED);
+
+        let sent: Vec<_> = sim::take_sysex()
+            .into_iter()
+            .filter_map(|(port, data)| Some((port, Report::from_sysex(&data)?.to_string())))
+            .collect();
+        assert_eq!(sent, [(Port::USB, panic.to_string())]);
+
+        // the report is only stored when configured to
+        clear_stored();
+        assert_eq!(stored(), None);
+        configure(Config {
+            store: true,
+            action: Action::Reset,
+        });
+        assert_eq!(report(&panic), Action::Reset);
+        assert_eq!(stored(), Some(panic));
+        clear_stored();
+        assert_eq!(stored(), None);
+
+        // a panic while the configuration is in use falls back to the default
+        assert_eq!(with_config(|_| report(&panic)), Action::Halt);
+        assert_eq!(stored(), None);
+        configure(Config::default());
+    }
+}
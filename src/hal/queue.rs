@@ -0,0 +1,497 @@
+use {
+    crate::hal::{self, midi::DEFAULT_SYSEX_CAPACITY, LaunchpadApp, Mutex},
+    core::{
+        cell::UnsafeCell,
+        mem::MaybeUninit,
+        sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering},
+    },
+};
+
+/// The number of events that can be waiting to be delivered to the app.
+pub const CAPACITY: usize = 64;
+
+/// The number of SysEx messages that can be waiting to be delivered to the app.
+pub const SYSEX_CAPACITY: usize = 2;
+
+struct Slot<T> {
+    /// The lap of the queue the slot is on, less its index. This tells producers and consumers
+    /// whether the slot is waiting to be written or read.
+    sequence: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+impl<T> Slot<T> {
+    const fn new() -> Self {
+        Self {
+            sequence: AtomicUsize::new(0),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+}
+
+/// A fixed capacity first in, first out queue that can be pushed to and popped from without
+/// locking, so that it is safe to use from interrupts. The capacity must be a power of two.
+///
+/// # Example
+///
+/// ```
+/// use launchpad_pro_rs::hal::queue::Queue;
+///
+/// let queue: Queue<u8, 2> = Queue::new();
+/// assert_eq!(queue.push(1), Ok(()));
+/// assert_eq!(queue.push(2), Ok(()));
+/// assert_eq!(queue.push(3), Err(3));
+/// assert_eq!(queue.pop(), Some(1));
+/// ```
+pub struct Queue<T: Copy, const N: usize> {
+    slots: [Slot<T>; N],
+    head: AtomicUsize,
+    tail: AtomicUsize,
+}
+
+// The sequence numbers hand each slot to one producer or consumer at a time.
+unsafe impl<T: Copy + Send, const N: usize> Sync for Queue<T, N> {}
+
+impl<T: Copy, const N: usize> Queue<T, N> {
+    /// Construct an empty queue.
+    pub const fn new() -> Self {
+        assert!(N.is_power_of_two(), "queue capacity must be a power of two");
+        Self {
+            slots: [const { Slot::new() }; N],
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+
+    /// Add a value to the back of the queue, or return it if the queue is full.
+    pub fn push(&self, value: T) -> Result<(), T> {
+        let mut position = self.tail.load(Ordering::Relaxed);
+        loop {
+            let (slot, lap) = self.slot(position);
+            let ready = slot.sequence.load(Ordering::Acquire).wrapping_sub(lap) as isize;
+            if ready == 0 {
+                match self.tail.compare_exchange_weak(
+                    position,
+                    position.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        unsafe { (*slot.value.get()).write(value) };
+                        slot.sequence.store(lap.wrapping_add(1), Ordering::Release);
+                        return Ok(());
+                    }
+                    Err(current) => position = current,
+                }
+            } else if ready < 0 {
+                return Err(value);
+            } else {
+                position = self.tail.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Remove the value at the front of the queue. A value that is still being pushed is not
+    /// returned until the push completes.
+    pub fn pop(&self) -> Option<T> {
+        let mut position = self.head.load(Ordering::Relaxed);
+        loop {
+            let (slot, lap) = self.slot(position);
+            let ready = slot
+                .sequence
+                .load(Ordering::Acquire)
+                .wrapping_sub(lap.wrapping_add(1)) as isize;
+            if ready == 0 {
+                match self.head.compare_exchange_weak(
+                    position,
+                    position.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        let value = unsafe { (*slot.value.get()).assume_init() };
+                        slot.sequence.store(lap.wrapping_add(N), Ordering::Release);
+                        return Some(value);
+                    }
+                    Err(current) => position = current,
+                }
+            } else if ready < 0 {
+                return None;
+            } else {
+                position = self.head.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Returns whether there is a value ready to be popped.
+    pub fn is_ready(&self) -> bool {
+        let (slot, lap) = self.slot(self.head.load(Ordering::Relaxed));
+        slot.sequence.load(Ordering::Acquire) == lap.wrapping_add(1)
+    }
+
+    /// Returns the slot for a position, and the position of the start of its lap.
+    fn slot(&self, position: usize) -> (&Slot<T>, usize) {
+        let index = position % N;
+        (&self.slots[index], position.wrapping_sub(index))
+    }
+}
+
+impl<T: Copy, const N: usize> Default for Queue<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The number of events that have been dropped because the app was too busy to receive them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Overflows {
+    /// Surface, MIDI, aftertouch and cable events, along with SysEx messages that arrived when
+    /// the event queue was full.
+    pub events: u32,
+    /// SysEx messages that arrived when there was no room to copy them.
+    pub sysex: u32,
+}
+
+/// An event from the low level firmware, with its raw values.
+#[derive(Clone, Copy, Debug)]
+enum Input {
+    Surface {
+        event: u8,
+        index: u8,
+        value: u8,
+    },
+    Midi {
+        port: u8,
+        status: u8,
+        data: (u8, u8),
+        time: u32,
+    },
+    Sysex {
+        port: u8,
+        buffer: usize,
+    },
+    Aftertouch {
+        index: u8,
+        value: u8,
+    },
+    Cable {
+        cable_type: u8,
+        value: u8,
+    },
+}
+
+/// Holds a copy of a SysEx message until it is delivered, as the firmware's buffer is only valid
+/// for the duration of its call.
+struct SysexBuffer {
+    in_use: AtomicBool,
+    length: AtomicUsize,
+    data: UnsafeCell<[u8; DEFAULT_SYSEX_CAPACITY]>,
+}
+
+impl SysexBuffer {
+    const fn new() -> Self {
+        Self {
+            in_use: AtomicBool::new(false),
+            length: AtomicUsize::new(0),
+            data: UnsafeCell::new([0; DEFAULT_SYSEX_CAPACITY]),
+        }
+    }
+}
+
+/// The events waiting to be delivered to the app.
+struct Pending {
+    inputs: Queue<Input, CAPACITY>,
+    sysex: [SysexBuffer; SYSEX_CAPACITY],
+    timer_events: AtomicU32,
+    /// Whether any of the app's software timers have fired since they were last delivered.
+    software_timers: AtomicBool,
+    dropped_events: AtomicU32,
+    dropped_sysex: AtomicU32,
+}
+
+// A SysEx buffer is only accessed by the producer that claimed it until it is queued, and then by
+// the consumer that popped it until it is released.
+unsafe impl Sync for Pending {}
+
+impl Pending {
+    const fn new() -> Self {
+        Self {
+            inputs: Queue::new(),
+            sysex: [const { SysexBuffer::new() }; SYSEX_CAPACITY],
+            timer_events: AtomicU32::new(0),
+            software_timers: AtomicBool::new(false),
+            dropped_events: AtomicU32::new(0),
+            dropped_sysex: AtomicU32::new(0),
+        }
+    }
+
+    fn push(&self, input: Input) -> bool {
+        let queued = self.inputs.push(input).is_ok();
+        if !queued {
+            self.dropped_events.fetch_add(1, Ordering::Relaxed);
+        }
+        queued
+    }
+
+    fn push_sysex(&self, port: u8, data: &[u8]) {
+        let buffer = self.sysex.iter().position(|buffer| {
+            data.len() <= DEFAULT_SYSEX_CAPACITY
+                && buffer
+                    .in_use
+                    .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+        });
+        let buffer = match buffer {
+            Some(buffer) => buffer,
+            None => {
+                self.dropped_sysex.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+        };
+
+        let sysex = &self.sysex[buffer];
+        unsafe { (&mut *sysex.data.get())[..data.len()].copy_from_slice(data) };
+        sysex.length.store(data.len(), Ordering::Relaxed);
+        if !self.push(Input::Sysex { port, buffer }) {
+            sysex.in_use.store(false, Ordering::Release);
+        }
+    }
+
+    fn is_ready(&self) -> bool {
+        self.inputs.is_ready()
+            || self.software_timers.load(Ordering::Relaxed)
+            || self.timer_events.load(Ordering::Relaxed) > 0
+    }
+
+    fn deliver(&self, app: &mut dyn LaunchpadApp) {
+        while let Some(input) = self.inputs.pop() {
+            match input {
+                Input::Surface {
+                    event,
+                    index,
+                    value,
+                } => hal::app_surface_event(app, event, index, value),
+                Input::Midi {
+                    port,
+                    status,
+                    data: (data1, data2),
+                    time,
+                } => hal::app_midi_event(app, port, status, data1, data2, time),
+                Input::Sysex { port, buffer } => {
+                    let sysex = &self.sysex[buffer];
+                    let length = sysex.length.load(Ordering::Relaxed);
+                    let data = unsafe { &(&*sysex.data.get())[..length] };
+                    hal::app_sysex_event(app, port, data);
+                    sysex.in_use.store(false, Ordering::Release);
+                }
+                Input::Aftertouch { index, value } => hal::app_aftertouch_event(app, index, value),
+                Input::Cable { cable_type, value } => hal::app_cable_event(app, cable_type, value),
+            }
+        }
+
+        if self.software_timers.swap(false, Ordering::Relaxed) {
+            hal::app_software_timer_events(app);
+        }
+
+        let count = self.timer_events.swap(0, Ordering::Relaxed);
+        if count > 0 {
+            hal::app_timer_events(app, count);
+        }
+    }
+}
+
+#[cfg(target_arch = "arm")]
+static PENDING: Pending = Pending::new();
+
+#[cfg(target_arch = "arm")]
+fn with_pending<R>(f: impl FnOnce(&Pending) -> R) -> R {
+    f(&PENDING)
+}
+
+#[cfg(not(target_arch = "arm"))]
+std::thread_local! {
+    static PENDING: Pending = const { Pending::new() };
+}
+
+#[cfg(not(target_arch = "arm"))]
+fn with_pending<R>(f: impl FnOnce(&Pending) -> R) -> R {
+    PENDING.with(|pending| f(pending))
+}
+
+/// Returns the number of events that have been dropped because the app was too busy to receive
+/// them. Timer events are never dropped; when several are missed they are delivered together.
+pub fn overflows() -> Overflows {
+    with_pending(|pending| Overflows {
+        events: pending.dropped_events.load(Ordering::Relaxed),
+        sysex: pending.dropped_sysex.load(Ordering::Relaxed),
+    })
+}
+
+#[doc(hidden)]
+pub fn push_surface_event(event: u8, index: u8, value: u8) {
+    with_pending(|pending| {
+        pending.push(Input::Surface {
+            event,
+            index,
+            value,
+        })
+    });
+}
+
+#[doc(hidden)]
+pub fn push_midi_event(port: u8, status: u8, data1: u8, data2: u8) {
+    with_pending(|pending| {
+        pending.push(Input::Midi {
+            port,
+            status,
+            data: (data1, data2),
+            time: hal::time::now(),
+        })
+    });
+}
+
+#[doc(hidden)]
+pub fn push_sysex_event(port: u8, data: &[u8]) {
+    with_pending(|pending| pending.push_sysex(port, data));
+}
+
+#[doc(hidden)]
+pub fn push_aftertouch_event(index: u8, value: u8) {
+    with_pending(|pending| pending.push(Input::Aftertouch { index, value }));
+}
+
+#[doc(hidden)]
+pub fn push_cable_event(cable_type: u8, value: u8) {
+    with_pending(|pending| pending.push(Input::Cable { cable_type, value }));
+}
+
+#[doc(hidden)]
+pub fn push_timer_event() {
+    with_pending(|pending| {
+        let count = &pending.timer_events;
+        let _ = count.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| {
+            count.checked_add(1)
+        });
+    });
+}
+
+/// Note that some of the app's software timers have fired, so that they are delivered by the next
+/// dispatch.
+pub(crate) fn push_software_timer_event() {
+    with_pending(|pending| pending.software_timers.store(true, Ordering::Relaxed));
+}
+
+/// Deliver the waiting events to the app, unless it is already handling an event, in which case
+/// they are delivered by whoever holds the lock once it is done. The events are delivered in the
+/// order they arrived, followed by any software timers that have fired, and then the app's timer
+/// with the number of times it has fired.
+#[doc(hidden)]
+pub fn dispatch<A: LaunchpadApp>(app: &Mutex<Option<A>>) {
+    // An event can be pushed just after the holder of the lock has delivered the last one, so
+    // check again once the lock is released.
+    while with_pending(Pending::is_ready) {
+        let mut app = match app.try_lock() {
+            Some(app) => app,
+            None => return,
+        };
+        match app.as_mut() {
+            Some(app) => with_pending(|pending| pending.deliver(app)),
+            None => return,
+        }
+    }
+}
+
+/// Discard the waiting events and clear the overflow counters.
+#[cfg(not(target_arch = "arm"))]
+pub(crate) fn reset() {
+    with_pending(|pending| {
+        while let Some(input) = pending.inputs.pop() {
+            if let Input::Sysex { buffer, .. } = input {
+                pending.sysex[buffer].in_use.store(false, Ordering::Relaxed);
+            }
+        }
+        pending.timer_events.store(0, Ordering::Relaxed);
+        pending.software_timers.store(false, Ordering::Relaxed);
+        pending.dropped_events.store(0, Ordering::Relaxed);
+        pending.dropped_sysex.store(0, Ordering::Relaxed);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        std::{sync::Arc, thread, vec::Vec},
+    };
+
+    #[test]
+    fn values_are_popped_in_the_order_they_were_pushed() {
+        let queue: Queue<u32, 4> = Queue::new();
+        assert!(!queue.is_ready());
+        assert_eq!(queue.pop(), None);
+
+        // go round the queue several times
+        for lap in 0..10 {
+            for value in 0..3 {
+                queue.push(lap * 3 + value).unwrap();
+            }
+            assert!(queue.is_ready());
+            for value in 0..3 {
+                assert_eq!(queue.pop(), Some(lap * 3 + value));
+            }
+            assert_eq!(queue.pop(), None);
+        }
+    }
+
+    #[test]
+    fn a_full_queue_rejects_values() {
+        let queue: Queue<u8, 4> = Queue::new();
+        for value in 0..4 {
+            assert_eq!(queue.push(value), Ok(()));
+        }
+        assert_eq!(queue.push(4), Err(4));
+        assert_eq!(queue.pop(), Some(0));
+        assert_eq!(queue.push(4), Ok(()));
+        let values: Vec<_> = core::iter::from_fn(|| queue.pop()).collect();
+        assert_eq!(values, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn values_pushed_concurrently_are_not_lost() {
+        const PRODUCERS: u32 = 4;
+        const VALUES: u32 = 10_000;
+
+        let queue: Arc<Queue<u32, 16>> = Arc::new(Queue::new());
+        let producers: Vec<_> = (0..PRODUCERS)
+            .map(|producer| {
+                let queue = Arc::clone(&queue);
+                thread::spawn(move || {
+                    for value in 0..VALUES {
+                        while queue.push(producer * VALUES + value).is_err() {
+                            thread::yield_now();
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        // each producer's values arrive in order, and none are duplicated or lost
+        let mut next = [0; PRODUCERS as usize];
+        let mut received = 0;
+        while received < PRODUCERS * VALUES {
+            match queue.pop() {
+                Some(value) => {
+                    let producer = (value / VALUES) as usize;
+                    assert_eq!(value % VALUES, next[producer]);
+                    next[producer] += 1;
+                    received += 1;
+                }
+                None => thread::yield_now(),
+            }
+        }
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        assert_eq!(queue.pop(), None);
+    }
+}
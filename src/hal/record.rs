@@ -0,0 +1,601 @@
+use {
+    crate::hal::{
+        midi::{self, Port},
+        time,
+    },
+    core::fmt,
+};
+
+/// Identifies the start of a recording.
+const MAGIC: [u8; 4] = *b"LPRC";
+
+/// The version of the recording format.
+const VERSION: u8 = 1;
+
+/// The bytes that start each SysEx frame of a recording.
+const FRAME_HEADER: [u8; 4] = [0xF0, midi::NON_COMMERCIAL_ID, b'L', b'R'];
+
+/// The number of bytes of the recording carried by each SysEx frame. This is a multiple of seven
+/// so that the packed frames stay within the 320 byte SysEx limit.
+const FRAME_CAPACITY: usize = 252;
+
+/// The tags that identify each type of event in a recording.
+const SURFACE: u8 = 0;
+const MIDI: u8 = 1;
+const SYSEX: u8 = 2;
+const AFTERTOUCH: u8 = 3;
+const CABLE: u8 = 4;
+const TIMER: u8 = 5;
+const SOFTWARE_TIMER: u8 = 6;
+const TIMERS: u8 = 7;
+
+/// An event received by the app, with the raw values passed to it by the low level firmware.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event<'a> {
+    /// A button was pressed or released.
+    Surface { event: u8, index: u8, value: u8 },
+    /// A MIDI message was received.
+    Midi {
+        port: u8,
+        status: u8,
+        data: (u8, u8),
+    },
+    /// A SysEx message was received.
+    Sysex { port: u8, data: &'a [u8] },
+    /// An aftertouch (pad pressure) event was reported.
+    Aftertouch { index: u8, value: u8 },
+    /// A MIDI DIN cable was connected or disconnected.
+    Cable { cable_type: u8, value: u8 },
+    /// The app's timer fired.
+    Timer,
+    /// One of the app's software timers fired.
+    SoftwareTimer { id: u8 },
+    /// The app's timer fired several times while the app was busy, and was delivered once with
+    /// the count.
+    Timers { count: u32 },
+}
+
+/// An event, along with the time it occurred in milliseconds since the recording started.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Entry<'a> {
+    pub time: u32,
+    pub event: Event<'a>,
+}
+
+/// The errors that can occur when decoding a recording.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The data does not start with a recording header.
+    NotARecording,
+    /// The recording was made with an unsupported version of the format.
+    UnsupportedVersion(u8),
+    /// The recording ended part way through an event.
+    Truncated,
+    /// The recording contains an event of an unknown type.
+    UnknownEvent(u8),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::NotARecording => write!(f, "not a recording"),
+            Error::UnsupportedVersion(version) => {
+                write!(f, "unsupported recording version {}", version)
+            }
+            Error::Truncated => write!(f, "recording ended unexpectedly"),
+            Error::UnknownEvent(tag) => write!(f, "unknown event type {}", tag),
+        }
+    }
+}
+
+/// A destination for an encoded recording.
+pub trait Sink {
+    /// Append bytes to the recording.
+    fn write(&mut self, bytes: &[u8]);
+}
+
+#[cfg(not(target_arch = "arm"))]
+impl Sink for std::vec::Vec<u8> {
+    fn write(&mut self, bytes: &[u8]) {
+        self.extend_from_slice(bytes);
+    }
+}
+
+/// Encodes events into the compact recording format. Each event is stored as the time since the
+/// previous event, followed by a tag identifying its type and its raw values.
+pub struct Encoder {
+    time: u32,
+}
+
+impl Encoder {
+    /// Start a new recording, writing its header to the sink.
+    pub fn new(sink: &mut impl Sink) -> Self {
+        sink.write(&MAGIC);
+        sink.write(&[VERSION]);
+        Self { time: 0 }
+    }
+
+    /// Encode an entry. Entries must be encoded in the order they occurred.
+    pub fn encode(&mut self, entry: &Entry, sink: &mut impl Sink) {
+        write_varint(entry.time.wrapping_sub(self.time), sink);
+        self.time = entry.time;
+
+        match entry.event {
+            Event::Surface {
+                event,
+                index,
+                value,
+            } => sink.write(&[SURFACE, event, index, value]),
+            Event::Midi {
+                port,
+                status,
+                data: (data1, data2),
+            } => sink.write(&[MIDI, port, status, data1, data2]),
+            Event::Sysex { port, data } => {
+                sink.write(&[SYSEX, port]);
+                write_varint(data.len() as u32, sink);
+                sink.write(data);
+            }
+            Event::Aftertouch { index, value } => sink.write(&[AFTERTOUCH, index, value]),
+            Event::Cable { cable_type, value } => sink.write(&[CABLE, cable_type, value]),
+            Event::Timer => sink.write(&[TIMER]),
+            Event::SoftwareTimer { id } => sink.write(&[SOFTWARE_TIMER, id]),
+            Event::Timers { count } => {
+                sink.write(&[TIMERS]);
+                write_varint(count, sink);
+            }
+        }
+    }
+}
+
+fn write_varint(mut value: u32, sink: &mut impl Sink) {
+    while value >= 0x80 {
+        sink.write(&[(value as u8) | 0x80]);
+        value >>= 7;
+    }
+    sink.write(&[value as u8]);
+}
+
+/// Decodes the entries of a recording.
+///
+/// # Example
+///
+/// ```
+/// use launchpad_pro_rs::hal::record::{Decoder, Encoder, Entry, Event};
+///
+/// let mut recording = Vec::new();
+/// let mut encoder = Encoder::new(&mut recording);
+/// encoder.encode(&Entry { time: 250, event: Event::Timer }, &mut recording);
+///
+/// let mut decoder = Decoder::new(&recording).unwrap();
+/// assert_eq!(decoder.next(), Some(Ok(Entry { time: 250, event: Event::Timer })));
+/// assert_eq!(decoder.next(), None);
+/// ```
+pub struct Decoder<'a> {
+    data: &'a [u8],
+    time: u32,
+}
+
+impl<'a> Decoder<'a> {
+    /// Start decoding a recording, checking its header.
+    pub fn new(data: &'a [u8]) -> Result<Self, Error> {
+        match data {
+            [m0, m1, m2, m3, version, data @ ..] if [*m0, *m1, *m2, *m3] == MAGIC => {
+                if *version == VERSION {
+                    Ok(Self { data, time: 0 })
+                } else {
+                    Err(Error::UnsupportedVersion(*version))
+                }
+            }
+            _ => Err(Error::NotARecording),
+        }
+    }
+
+    fn read_bytes(&mut self, length: usize) -> Result<&'a [u8], Error> {
+        if length > self.data.len() {
+            return Err(Error::Truncated);
+        }
+        let (bytes, data) = self.data.split_at(length);
+        self.data = data;
+        Ok(bytes)
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.read_bytes(N)?);
+        Ok(array)
+    }
+
+    fn read_varint(&mut self) -> Result<u32, Error> {
+        let mut value = 0;
+        for shift in (0..32).step_by(7) {
+            let [byte] = self.read_array()?;
+            value |= ((byte & 0x7F) as u32) << shift;
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        Ok(value)
+    }
+
+    fn read_entry(&mut self) -> Result<Entry<'a>, Error> {
+        self.time = self.time.wrapping_add(self.read_varint()?);
+
+        let [tag] = self.read_array()?;
+        let event = match tag {
+            SURFACE => {
+                let [event, index, value] = self.read_array()?;
+                Event::Surface {
+                    event,
+                    index,
+                    value,
+                }
+            }
+            MIDI => {
+                let [port, status, data1, data2] = self.read_array()?;
+                Event::Midi {
+                    port,
+                    status,
+                    data: (data1, data2),
+                }
+            }
+            SYSEX => {
+                let [port] = self.read_array()?;
+                let length = self.read_varint()? as usize;
+                Event::Sysex {
+                    port,
+                    data: self.read_bytes(length)?,
+                }
+            }
+            AFTERTOUCH => {
+                let [index, value] = self.read_array()?;
+                Event::Aftertouch { index, value }
+            }
+            CABLE => {
+                let [cable_type, value] = self.read_array()?;
+                Event::Cable { cable_type, value }
+            }
+            TIMER => Event::Timer,
+            SOFTWARE_TIMER => {
+                let [id] = self.read_array()?;
+                Event::SoftwareTimer { id }
+            }
+            TIMERS => Event::Timers {
+                count: self.read_varint()?,
+            },
+            tag => return Err(Error::UnknownEvent(tag)),
+        };
+
+        Ok(Entry {
+            time: self.time,
+            event,
+        })
+    }
+}
+
+impl<'a> Iterator for Decoder<'a> {
+    type Item = Result<Entry<'a>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+
+        let entry = self.read_entry();
+        if entry.is_err() {
+            self.data = &[];
+        }
+        Some(entry)
+    }
+}
+
+/// Sends a recording as a stream of SysEx frames.
+struct FrameWriter {
+    port: Port,
+    buffer: [u8; FRAME_CAPACITY],
+    length: usize,
+}
+
+impl FrameWriter {
+    fn flush(&mut self) {
+        if self.length == 0 {
+            return;
+        }
+
+        const PACKED_CAPACITY: usize = FRAME_CAPACITY + FRAME_CAPACITY / 7;
+        let mut frame = [0; FRAME_HEADER.len() + PACKED_CAPACITY + 1];
+        frame[..FRAME_HEADER.len()].copy_from_slice(&FRAME_HEADER);
+        let packed = midi::pack_7bit(
+            &self.buffer[..self.length],
+            &mut frame[FRAME_HEADER.len()..],
+        )
+        .unwrap_or(0);
+        let end = FRAME_HEADER.len() + packed;
+        frame[end] = 0xF7;
+
+        midi::send_sysex(self.port, &frame[..=end]);
+        self.length = 0;
+    }
+}
+
+impl Sink for FrameWriter {
+    fn write(&mut self, mut bytes: &[u8]) {
+        while !bytes.is_empty() {
+            let count = bytes.len().min(FRAME_CAPACITY - self.length);
+            self.buffer[self.length..self.length + count].copy_from_slice(&bytes[..count]);
+            self.length += count;
+            bytes = &bytes[count..];
+
+            if self.length == FRAME_CAPACITY {
+                self.flush();
+            }
+        }
+    }
+}
+
+/// The state of an active recording.
+struct Recorder {
+    writer: FrameWriter,
+    encoder: Encoder,
+    start: u32,
+}
+
+#[cfg(target_arch = "arm")]
+static RECORDER: crate::hal::Mutex<Option<Recorder>> = crate::hal::Mutex::new(None);
+
+#[cfg(target_arch = "arm")]
+fn with_recorder<R>(f: impl FnOnce(&mut Option<Recorder>) -> R) -> R {
+    f(&mut RECORDER.lock())
+}
+
+#[cfg(not(target_arch = "arm"))]
+std::thread_local! {
+    static RECORDER: core::cell::RefCell<Option<Recorder>> = const { core::cell::RefCell::new(None) };
+}
+
+#[cfg(not(target_arch = "arm"))]
+fn with_recorder<R>(f: impl FnOnce(&mut Option<Recorder>) -> R) -> R {
+    RECORDER.with(|recorder| f(&mut recorder.borrow_mut()))
+}
+
+/// Start recording every event the app receives, streaming the recording as SysEx messages over
+/// a port. Any recording that is already in progress is stopped first.
+///
+/// The SysEx messages can be captured on the host with any MIDI monitor, and converted back into
+/// a recording using `replay::Recording::from_sysex`.
+///
+/// # Example
+///
+/// ```
+/// use launchpad_pro_rs::hal::{midi::Port, record};
+///
+/// record::start(Port::USB);
+/// assert!(record::is_recording());
+///
+/// record::stop();
+/// assert!(!record::is_recording());
+/// ```
+pub fn start(port: Port) {
+    stop();
+    with_recorder(|recorder| {
+        let mut writer = FrameWriter {
+            port,
+            buffer: [0; FRAME_CAPACITY],
+            length: 0,
+        };
+        let encoder = Encoder::new(&mut writer);
+        writer.flush();
+
+        *recorder = Some(Recorder {
+            writer,
+            encoder,
+            start: time::now(),
+        });
+    });
+}
+
+/// Stop recording.
+pub fn stop() {
+    with_recorder(|recorder| {
+        if let Some(mut recorder) = recorder.take() {
+            recorder.writer.flush();
+        }
+    });
+}
+
+/// Returns whether a recording is in progress.
+pub fn is_recording() -> bool {
+    with_recorder(|recorder| recorder.is_some())
+}
+
+/// Returns whether a SysEx message is a frame of a recording, as sent by [`start`].
+pub fn is_frame(data: &[u8]) -> bool {
+    data.starts_with(&FRAME_HEADER) && data.last() == Some(&0xF7)
+}
+
+/// Extract the part of a recording carried by a SysEx frame, appending it to the sink. Returns
+/// false if the message is not a frame of a recording.
+pub fn read_frame(data: &[u8], sink: &mut impl Sink) -> bool {
+    if !is_frame(data) {
+        return false;
+    }
+
+    let mut buffer = [0; FRAME_CAPACITY];
+    match midi::unpack_7bit(&data[FRAME_HEADER.len()..data.len() - 1], &mut buffer) {
+        Some(length) => {
+            sink.write(&buffer[..length]);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Record an event if a recording is in progress.
+pub(crate) fn capture(event: Event) {
+    with_recorder(|recorder| {
+        if let Some(recorder) = recorder {
+            let entry = Entry {
+                time: time::now().wrapping_sub(recorder.start),
+                event,
+            };
+            recorder.encoder.encode(&entry, &mut recorder.writer);
+            recorder.writer.flush();
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::hal::sim, std::vec::Vec};
+
+    fn encode(entries: &[Entry]) -> Vec<u8> {
+        let mut recording = Vec::new();
+        let mut encoder = Encoder::new(&mut recording);
+        for entry in entries {
+            encoder.encode(entry, &mut recording);
+        }
+        recording
+    }
+
+    #[test]
+    fn events_survive_a_round_trip() {
+        let sysex = [0xF0, 0x01, 0x02, 0x03, 0xF7];
+        let entries = [
+            Entry {
+                time: 0,
+                event: Event::Surface {
+                    event: 0,
+                    index: 44,
+                    value: 127,
+                },
+            },
+            Entry {
+                time: 1,
+                event: Event::Midi {
+                    port: 2,
+                    status: 0x90,
+                    data: (60, 100),
+                },
+            },
+            Entry {
+                time: 300,
+                event: Event::Sysex {
+                    port: 1,
+                    data: &sysex,
+                },
+            },
+            Entry {
+                time: 300,
+                event: Event::Aftertouch {
+                    index: 44,
+                    value: 12,
+                },
+            },
+            Entry {
+                time: 100_000,
+                event: Event::Cable {
+                    cable_type: 1,
+                    value: 0,
+                },
+            },
+            Entry {
+                time: 100_100,
+                event: Event::Timer,
+            },
+            Entry {
+                time: 100_150,
+                event: Event::SoftwareTimer { id: 3 },
+            },
+            Entry {
+                time: 100_160,
+                event: Event::Timers { count: 300 },
+            },
+        ];
+
+        let recording = encode(&entries);
+        let decoded: Result<Vec<_>, _> = Decoder::new(&recording).unwrap().collect();
+        assert_eq!(decoded.unwrap(), entries);
+    }
+
+    #[test]
+    fn recordings_are_compact() {
+        let timer = |time| Entry {
+            time,
+            event: Event::Timer,
+        };
+        let recording = encode(&[timer(100), timer(200), timer(300)]);
+        assert_eq!(recording.len(), 5 + 3 * 2);
+    }
+
+    #[test]
+    fn invalid_recordings_are_rejected() {
+        assert_eq!(Decoder::new(b"LPR").err(), Some(Error::NotARecording));
+        assert_eq!(
+            Decoder::new(b"LPRC\x02").err(),
+            Some(Error::UnsupportedVersion(2))
+        );
+
+        let mut decoder = Decoder::new(b"LPRC\x01\x00\x09").unwrap();
+        assert_eq!(decoder.next(), Some(Err(Error::UnknownEvent(9))));
+        assert_eq!(decoder.next(), None);
+
+        let mut decoder = Decoder::new(b"LPRC\x01\x00\x00\x01").unwrap();
+        assert_eq!(decoder.next(), Some(Err(Error::Truncated)));
+    }
+
+    #[test]
+    fn recordings_are_streamed_as_sysex_frames() {
+        sim::reset();
+        start(Port::USB);
+
+        let long_sysex = [0x42; 300];
+        for _ in 0..5 {
+            crate::hal::app_tick();
+        }
+        capture(Event::Aftertouch {
+            index: 11,
+            value: 64,
+        });
+        capture(Event::Sysex {
+            port: 2,
+            data: &long_sysex,
+        });
+        stop();
+
+        let mut recording = Vec::new();
+        for (port, frame) in sim::take_sysex() {
+            assert_eq!(port, Port::USB);
+            assert!(frame.len() <= 320);
+            assert!(frame[1..frame.len() - 1].iter().all(|&byte| byte < 0x80));
+            assert!(read_frame(&frame, &mut recording));
+        }
+
+        let decoded: Result<Vec<_>, _> = Decoder::new(&recording).unwrap().collect();
+        assert_eq!(
+            decoded.unwrap(),
+            [
+                Entry {
+                    time: 5,
+                    event: Event::Aftertouch {
+                        index: 11,
+                        value: 64
+                    }
+                },
+                Entry {
+                    time: 5,
+                    event: Event::Sysex {
+                        port: 2,
+                        data: &long_sysex
+                    }
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn nothing_is_captured_unless_recording() {
+        sim::reset();
+        capture(Event::Timer);
+        assert!(sim::take_sysex().is_empty());
+    }
+}
@@ -0,0 +1,468 @@
+use {
+    crate::hal::{
+        self,
+        midi::Port,
+        record::{self, Decoder, Entry, Event},
+        sim::{self, Frame, Simulator},
+        snapshot,
+        surface::{Button, Led},
+        timer, Blue, Green, Grid, LaunchpadApp, Point, Red, Rgb,
+    },
+    std::{fmt, fs, io, path::Path},
+};
+
+/// A recording of the events an app received, made using [`record::start`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Recording {
+    data: Vec<u8>,
+}
+
+impl Recording {
+    /// Construct a recording from its encoded form, checking its header.
+    pub fn new(data: Vec<u8>) -> Result<Self, record::Error> {
+        Decoder::new(&data)?;
+        Ok(Self { data })
+    }
+
+    /// Reassemble a recording from the SysEx frames it was streamed as. Any messages that are not
+    /// frames of a recording are ignored.
+    pub fn from_sysex<'a>(
+        messages: impl IntoIterator<Item = &'a [u8]>,
+    ) -> Result<Self, record::Error> {
+        let mut data = Vec::new();
+        for message in messages {
+            record::read_frame(message, &mut data);
+        }
+        Self::new(data)
+    }
+
+    /// Reassemble a recording from a SysEx dump, such as a `.syx` file saved by a MIDI monitor,
+    /// which contains a sequence of SysEx messages.
+    pub fn from_sysex_dump(dump: &[u8]) -> Result<Self, record::Error> {
+        Self::from_sysex(
+            dump.split_inclusive(|&byte| byte == 0xF7)
+                .filter_map(|message| {
+                    message
+                        .iter()
+                        .position(|&byte| byte == 0xF0)
+                        .map(|start| &message[start..])
+                }),
+        )
+    }
+
+    /// Load a recording from a file, which may either contain the encoded recording or a SysEx
+    /// dump of its frames.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let data = fs::read(path)?;
+        let recording = if data.first() == Some(&0xF0) {
+            Self::from_sysex_dump(&data)
+        } else {
+            Self::new(data)
+        };
+        recording.map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error.to_string()))
+    }
+
+    /// Save the encoded recording to a file.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, &self.data)
+    }
+
+    /// Returns the encoded recording.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns an iterator over the entries of the recording.
+    pub fn entries(&self) -> Decoder<'_> {
+        Decoder::new(&self.data).expect("recording header was checked on construction")
+    }
+
+    /// Replay the recording into an app running on the simulated Launchpad Pro, starting from
+    /// the moment the app is initialised. The app's timer is driven by the recorded timer events
+    /// rather than by simulated time, so replays are deterministic.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use launchpad_pro_rs::hal::{
+    ///     midi::Port,
+    ///     record,
+    ///     replay::Recording,
+    ///     sim::{self, Simulator},
+    ///     surface::{set_led, Button, ButtonEvent},
+    ///     LaunchpadApp, Point, Rgb,
+    /// };
+    ///
+    /// #[derive(Default)]
+    /// struct App;
+    ///
+    /// impl LaunchpadApp for App {
+    ///     fn button_event(&mut self, event: ButtonEvent) {
+    ///         if let Button::Pad(point) = event.button {
+    ///             set_led(point, Rgb::GREEN);
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// let mut simulator = Simulator::new(App, 100);
+    /// record::start(Port::USB);
+    /// simulator.advance(20);
+    /// simulator.press(Button::Pad(Point::new(2, 3)), 100);
+    /// record::stop();
+    ///
+    /// let frames = sim::take_sysex();
+    /// let recording = Recording::from_sysex(frames.iter().map(|(_, frame)| frame.as_slice()));
+    /// let transcript = recording.unwrap().replay(App).unwrap();
+    ///
+    /// assert_eq!(
+    ///     transcript.to_string(),
+    ///     "0 > init\n20 > press 2,3 100\n20 < led 2,3 0 63 0\n"
+    /// );
+    /// ```
+    pub fn replay<A: LaunchpadApp>(&self, app: A) -> Result<Transcript, record::Error> {
+        let mut transcript = Transcript::default();
+
+        let mut frame = Frame::default();
+        // the timers that fired are part of the recording, so they are not simulated
+        let mut simulator = Simulator::new(app, 0);
+        simulator.timers = false;
+        transcript.input(0, "init".to_string());
+        transcript.output(0, &mut frame);
+
+        for entry in self.entries() {
+            let Entry { time, event } = entry?;
+            if let Some(delay) = time.checked_sub(simulator.elapsed() as u32) {
+                simulator.advance(delay);
+            }
+
+            transcript.input(time, describe(&event));
+            let app = simulator.app_mut();
+            match event {
+                Event::Surface {
+                    event,
+                    index,
+                    value,
+                } => hal::app_surface_event(app, event, index, value),
+                Event::Midi {
+                    port,
+                    status,
+                    data: (data1, data2),
+                } => hal::app_midi_event(app, port, status, data1, data2, hal::time::now()),
+                Event::Sysex { port, data } => hal::app_sysex_event(app, port, data),
+                Event::Aftertouch { index, value } => hal::app_aftertouch_event(app, index, value),
+                Event::Cable { cable_type, value } => hal::app_cable_event(app, cable_type, value),
+                Event::Timer => hal::app_timer_event(app),
+                Event::SoftwareTimer { id } => {
+                    hal::app_software_timer_event(app, timer::TimerId(id))
+                }
+                Event::Timers { count } => hal::app_timer_events(app, count),
+            }
+            transcript.output(time, &mut frame);
+        }
+
+        Ok(transcript)
+    }
+}
+
+/// Describe a recorded event for the transcript.
+fn describe(event: &Event) -> String {
+    match *event {
+        Event::Surface {
+            event,
+            index,
+            value,
+        } => {
+            let button = match event {
+                1 => Button::Setup,
+                _ => Button::Pad(Point::from_index(index)),
+            };
+            match value {
+                0 => format!("release {}", describe_led(button.into())),
+                velocity => format!("press {} {}", describe_led(button.into()), velocity),
+            }
+        }
+        Event::Midi {
+            port,
+            status,
+            data: (data1, data2),
+        } => format!(
+            "midi {} {:02X} {:02X} {:02X}",
+            describe_port(port),
+            status,
+            data1,
+            data2
+        ),
+        Event::Sysex { port, data } => format!("sysex {} {}", describe_port(port), hex(data)),
+        Event::Aftertouch { index, value } => format!(
+            "aftertouch {} {}",
+            describe_led(Point::from_index(index).into()),
+            value
+        ),
+        Event::Cable { cable_type, value } => format!("cable {} {}", cable_type, value),
+        Event::Timer => "timer".to_string(),
+        Event::SoftwareTimer { id } => format!("software timer {}", id),
+        Event::Timers { count } => format!("timer x{}", count),
+    }
+}
+
+fn describe_port(port: u8) -> String {
+    match Port::from_u8(port) {
+        Some(port) => format!("{:?}", port),
+        None => format!("port-{}", port),
+    }
+}
+
+fn describe_led(led: Led) -> String {
+    match led {
+        Led::Pad(point) => format!("{},{}", point.x(), point.y()),
+        Led::Setup => "setup".to_string(),
+    }
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// A line-by-line account of the events replayed into an app, and how the app responded: the
+/// LEDs that changed colour and the MIDI and SysEx messages it sent. Each line starts with the
+/// time of the event, followed by `>` for events the app received or `<` for its responses.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Transcript {
+    lines: Vec<String>,
+}
+
+impl Transcript {
+    fn input(&mut self, time: u32, description: String) {
+        self.lines.push(format!("{} > {}", time, description));
+    }
+
+    /// Record the app's responses: the LEDs that changed since the last frame and the messages
+    /// it sent.
+    fn output(&mut self, time: u32, last_frame: &mut Frame) {
+        let frame = sim::frame();
+        let leds = Grid::points()
+            .map(Led::Pad)
+            .chain(core::iter::once(Led::Setup));
+        for led in leds {
+            if frame.led(led) != last_frame.led(led) {
+                let Rgb(Red(red), Green(green), Blue(blue)) = frame.led(led);
+                self.lines.push(format!(
+                    "{} < led {} {} {} {}",
+                    time,
+                    describe_led(led),
+                    red,
+                    green,
+                    blue
+                ));
+            }
+        }
+        *last_frame = frame;
+
+        for (port, message) in sim::take_midi() {
+            let (data1, data2) = message.data();
+            self.lines.push(format!(
+                "{} < midi {:?} {:02X} {:02X} {:02X}",
+                time,
+                port,
+                message.status(),
+                data1,
+                data2
+            ));
+        }
+        for (port, data) in sim::take_sysex() {
+            if !record::is_frame(&data) {
+                self.lines
+                    .push(format!("{} < sysex {:?} {}", time, port, hex(&data)));
+            }
+        }
+    }
+
+    /// Returns the lines of the transcript.
+    pub fn lines(&self) -> impl Iterator<Item = &str> {
+        self.lines.iter().map(String::as_str)
+    }
+
+    /// Compare the transcript with the expected transcript, returning a description of the first
+    /// difference if they do not match.
+    pub fn compare(&self, expected: &str) -> Result<(), String> {
+        let mut expected_lines = expected.lines();
+        let mut actual_lines = self.lines();
+        for line in 1.. {
+            match (expected_lines.next(), actual_lines.next()) {
+                (None, None) => return Ok(()),
+                (expected, actual) if expected == actual => continue,
+                (expected, actual) => {
+                    return Err(format!(
+                        "transcripts differ at line {}\n  expected: {}\n  actual:   {}",
+                        line,
+                        expected.unwrap_or("<end of transcript>"),
+                        actual.unwrap_or("<end of transcript>")
+                    ))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Assert that the transcript matches the golden transcript stored in a file. If the
+    /// `LAUNCHPAD_BLESS` environment variable is set to `1` the file is written instead.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the file cannot be read, or if the transcripts differ.
+    pub fn assert_matches(&self, path: impl AsRef<Path>) {
+        let path = path.as_ref();
+        if snapshot::blessing() {
+            snapshot::bless(path, &self.to_string());
+            return;
+        }
+
+        let expected = snapshot::read_golden(path);
+        if let Err(difference) = self.compare(&expected) {
+            panic!(
+                "{} does not match: {}\nset {}=1 to update it",
+                path.display(),
+                difference,
+                snapshot::BLESS_VARIABLE
+            );
+        }
+    }
+
+    /// Save the transcript to a file, for use as a golden transcript.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+}
+
+impl fmt::Display for Transcript {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for line in &self.lines {
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::hal::{
+            midi::{send_message, Message},
+            surface::{set_led, ButtonEvent, Event as SurfaceEvent},
+        },
+    };
+
+    /// Counts timer events on the top row, and echoes notes for pad presses.
+    #[derive(Default)]
+    struct CounterApp {
+        count: i8,
+    }
+
+    impl LaunchpadApp for CounterApp {
+        fn init_event(&mut self, _pads: crate::hal::surface::Pads) {
+            set_led(Led::Setup, Rgb::BLUE);
+        }
+
+        fn timer_event(&mut self) {
+            set_led(Point::new(self.count, 9), Rgb::WHITE);
+            self.count += 1;
+        }
+
+        fn button_event(&mut self, event: ButtonEvent) {
+            if let (Button::Pad(point), SurfaceEvent::Press(velocity)) = (event.button, event.event)
+            {
+                if let Some(message) = Message::new(0x90, (point.to_index(), velocity)) {
+                    send_message(Port::DIN, message);
+                }
+            }
+        }
+    }
+
+    fn record_session() -> Vec<u8> {
+        let mut simulator = Simulator::new(CounterApp::default(), 10);
+        record::start(Port::USB);
+        simulator.advance(25);
+        simulator.press(Button::Pad(Point::new(1, 1)), 64);
+        simulator.release(Button::Pad(Point::new(1, 1)));
+        simulator.advance(5);
+        record::stop();
+
+        sim::take_sysex()
+            .into_iter()
+            .flat_map(|(_, frame)| frame)
+            .collect()
+    }
+
+    const GOLDEN: &str = "\
+0 > init
+0 < led setup 0 0 63
+10 > timer
+10 < led 0,9 63 63 63
+20 > timer
+20 < led 1,9 63 63 63
+25 > press 1,1 64
+25 < midi DIN 90 0B 40
+25 > release 1,1
+30 > timer
+30 < led 2,9 63 63 63
+";
+
+    #[test]
+    fn replaying_a_recording_reproduces_the_session() {
+        let recording = Recording::from_sysex_dump(&record_session()).unwrap();
+        let transcript = recording.replay(CounterApp::default()).unwrap();
+        assert_eq!(transcript.compare(GOLDEN), Ok(()));
+    }
+
+    #[test]
+    fn recordings_can_be_saved_and_loaded() {
+        let directory = std::env::temp_dir();
+        let dump_path = directory.join(format!("lp-recording-{}.syx", std::process::id()));
+        let path = directory.join(format!("lp-recording-{}.lprc", std::process::id()));
+
+        fs::write(&dump_path, record_session()).unwrap();
+        let recording = Recording::load(&dump_path).unwrap();
+        recording.save(&path).unwrap();
+        assert_eq!(Recording::load(&path).unwrap(), recording);
+
+        fs::remove_file(dump_path).unwrap();
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn differences_from_the_golden_transcript_are_reported() {
+        let recording = Recording::from_sysex_dump(&record_session()).unwrap();
+        let transcript = recording.replay(CounterApp::default()).unwrap();
+
+        let expected = GOLDEN.replace("25 < midi DIN 90 0B 40", "25 < midi DIN 90 0B 7F");
+        assert_eq!(
+            transcript.compare(&expected),
+            Err("transcripts differ at line 8\n  \
+                 expected: 25 < midi DIN 90 0B 7F\n  \
+                 actual:   25 < midi DIN 90 0B 40"
+                .to_string())
+        );
+
+        let truncated = GOLDEN.lines().take(3).collect::<Vec<_>>().join("\n");
+        assert!(transcript
+            .compare(&truncated)
+            .unwrap_err()
+            .contains("expected: <end of transcript>"));
+    }
+
+    #[test]
+    fn invalid_recordings_are_rejected() {
+        assert_eq!(
+            Recording::new(vec![1, 2, 3]),
+            Err(record::Error::NotARecording)
+        );
+        assert_eq!(
+            Recording::from_sysex_dump(&[0xF0, 0x01, 0xF7]),
+            Err(record::Error::NotARecording)
+        );
+    }
+}
@@ -0,0 +1,562 @@
+use {
+    crate::hal::{flash, panic},
+    core::fmt,
+};
+
+/// Identifies a settings image in the user area.
+const MAGIC: [u8; 4] = *b"LPRS";
+
+/// The size of the header that precedes the serialized settings: the magic bytes, followed by the
+/// schema version, payload length and payload CRC.
+const HEADER_SIZE: usize = 12;
+
+/// The maximum size of the serialized settings in bytes. The end of the user area is reserved for
+/// storing panic reports.
+pub const MAX_SIZE: usize = flash::SIZE - panic::STORE_SIZE - HEADER_SIZE;
+
+/// The errors that can occur when loading or saving settings.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The flash memory could not be accessed.
+    Flash(flash::Error),
+    /// There are no settings stored in the user area.
+    Missing,
+    /// The stored settings failed their integrity check.
+    Corrupt,
+    /// The stored settings have a schema version that cannot be loaded.
+    UnsupportedVersion(u16),
+    /// The serialized settings ended before the value being read.
+    Truncated,
+    /// The serialized settings do not fit in the user area.
+    Overflow,
+    /// The serialized settings contain a value that is not valid.
+    Invalid,
+}
+
+impl From<flash::Error> for Error {
+    fn from(error: flash::Error) -> Self {
+        Error::Flash(error)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Flash(error) => write!(f, "flash error: {}", error),
+            Error::Missing => write!(f, "no settings stored"),
+            Error::Corrupt => write!(f, "stored settings are corrupt"),
+            Error::UnsupportedVersion(version) => {
+                write!(f, "unsupported settings version {}", version)
+            }
+            Error::Truncated => write!(f, "settings ended unexpectedly"),
+            Error::Overflow => write!(f, "settings exceed {} bytes", MAX_SIZE),
+            Error::Invalid => write!(f, "settings contain an invalid value"),
+        }
+    }
+}
+
+/// A type that can be stored in the user area of the flash memory.
+///
+/// # Example
+///
+/// ```
+/// use launchpad_pro_rs::hal::settings::{self, Error, Reader, Settings, Writer};
+///
+/// #[derive(Debug, Default, PartialEq)]
+/// struct AppSettings {
+///     brightness: u8,
+///     channel: u8,
+/// }
+///
+/// impl Settings for AppSettings {
+///     const VERSION: u16 = 2;
+///
+///     fn serialize(&self, writer: &mut Writer) -> Result<(), Error> {
+///         writer.write_u8(self.brightness)?;
+///         writer.write_u8(self.channel)
+///     }
+///
+///     fn deserialize(reader: &mut Reader) -> Result<Self, Error> {
+///         Ok(Self {
+///             brightness: reader.read_u8()?,
+///             channel: reader.read_u8()?,
+///         })
+///     }
+///
+///     fn migrate(version: u16, reader: &mut Reader) -> Result<Self, Error> {
+///         match version {
+///             // version 1 did not store a channel
+///             1 => Ok(Self {
+///                 brightness: reader.read_u8()?,
+///                 ..Self::default()
+///             }),
+///             _ => Err(Error::UnsupportedVersion(version)),
+///         }
+///     }
+/// }
+///
+/// let app_settings = AppSettings { brightness: 63, channel: 9 };
+/// settings::save(&app_settings).unwrap();
+/// assert_eq!(settings::load::<AppSettings>(), app_settings);
+/// ```
+pub trait Settings: Default {
+    /// The schema version. This should be incremented whenever the serialized form changes.
+    const VERSION: u16;
+
+    /// Serialize the settings.
+    fn serialize(&self, writer: &mut Writer) -> Result<(), Error>;
+
+    /// Deserialize settings that were stored with the current schema version.
+    fn deserialize(reader: &mut Reader) -> Result<Self, Error>;
+
+    /// Deserialize settings that were stored with an older schema version. By default older
+    /// settings are not supported, so the default settings will be loaded instead.
+    fn migrate(version: u16, _reader: &mut Reader) -> Result<Self, Error> {
+        Err(Error::UnsupportedVersion(version))
+    }
+}
+
+/// Apps that implement this trait have their settings restored from flash on startup, when they
+/// are registered using `launchpad_app!(App => 100 ms, with settings)`.
+pub trait Persistent {
+    /// The type of settings that are stored for the app.
+    type Settings: Settings;
+
+    /// Called on startup, before `init_event`, with the settings restored from flash. If there
+    /// were no valid settings stored then the default settings are provided.
+    fn restore(&mut self, settings: Self::Settings);
+}
+
+/// Writes serialized settings into memory, so that nothing is written to the user area unless
+/// the settings are serialized successfully.
+pub struct Writer {
+    data: [u8; HEADER_SIZE + MAX_SIZE],
+    length: usize,
+}
+
+impl Writer {
+    const fn new() -> Self {
+        Self {
+            data: [0; HEADER_SIZE + MAX_SIZE],
+            length: 0,
+        }
+    }
+
+    /// Write raw bytes.
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        if bytes.len() > MAX_SIZE - self.length {
+            return Err(Error::Overflow);
+        }
+        let start = HEADER_SIZE + self.length;
+        self.data[start..start + bytes.len()].copy_from_slice(bytes);
+        self.length += bytes.len();
+        Ok(())
+    }
+
+    /// Write a `u8`.
+    pub fn write_u8(&mut self, value: u8) -> Result<(), Error> {
+        self.write_bytes(&[value])
+    }
+
+    /// Write a `u16`.
+    pub fn write_u16(&mut self, value: u16) -> Result<(), Error> {
+        self.write_bytes(&value.to_le_bytes())
+    }
+
+    /// Write a `u32`.
+    pub fn write_u32(&mut self, value: u32) -> Result<(), Error> {
+        self.write_bytes(&value.to_le_bytes())
+    }
+
+    /// Write an `i8`.
+    pub fn write_i8(&mut self, value: i8) -> Result<(), Error> {
+        self.write_bytes(&value.to_le_bytes())
+    }
+
+    /// Write an `i16`.
+    pub fn write_i16(&mut self, value: i16) -> Result<(), Error> {
+        self.write_bytes(&value.to_le_bytes())
+    }
+
+    /// Write an `i32`.
+    pub fn write_i32(&mut self, value: i32) -> Result<(), Error> {
+        self.write_bytes(&value.to_le_bytes())
+    }
+
+    /// Write a `bool`.
+    pub fn write_bool(&mut self, value: bool) -> Result<(), Error> {
+        self.write_u8(value as u8)
+    }
+}
+
+/// Reads serialized settings from the user area.
+pub struct Reader {
+    position: usize,
+    length: usize,
+}
+
+impl Reader {
+    /// Returns the number of bytes left to read.
+    pub const fn remaining(&self) -> usize {
+        self.length - self.position
+    }
+
+    /// Read raw bytes, filling the buffer.
+    pub fn read_bytes(&mut self, buffer: &mut [u8]) -> Result<(), Error> {
+        if buffer.len() > self.remaining() {
+            return Err(Error::Truncated);
+        }
+        flash::read(HEADER_SIZE + self.position, buffer)?;
+        self.position += buffer.len();
+        Ok(())
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        let mut bytes = [0; N];
+        self.read_bytes(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// Read a `u8`.
+    pub fn read_u8(&mut self) -> Result<u8, Error> {
+        self.read_array().map(u8::from_le_bytes)
+    }
+
+    /// Read a `u16`.
+    pub fn read_u16(&mut self) -> Result<u16, Error> {
+        self.read_array().map(u16::from_le_bytes)
+    }
+
+    /// Read a `u32`.
+    pub fn read_u32(&mut self) -> Result<u32, Error> {
+        self.read_array().map(u32::from_le_bytes)
+    }
+
+    /// Read an `i8`.
+    pub fn read_i8(&mut self) -> Result<i8, Error> {
+        self.read_array().map(i8::from_le_bytes)
+    }
+
+    /// Read an `i16`.
+    pub fn read_i16(&mut self) -> Result<i16, Error> {
+        self.read_array().map(i16::from_le_bytes)
+    }
+
+    /// Read an `i32`.
+    pub fn read_i32(&mut self) -> Result<i32, Error> {
+        self.read_array().map(i32::from_le_bytes)
+    }
+
+    /// Read a `bool`.
+    pub fn read_bool(&mut self) -> Result<bool, Error> {
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(Error::Invalid),
+        }
+    }
+}
+
+/// Save the settings to the user area. If the settings cannot be serialized then the settings
+/// already stored are left as they were.
+pub fn save<S: Settings>(settings: &S) -> Result<(), Error> {
+    let mut writer = Writer::new();
+    settings.serialize(&mut writer)?;
+
+    let end = HEADER_SIZE + writer.length;
+    let mut crc = Crc32::new();
+    crc.update(&writer.data[HEADER_SIZE..end]);
+
+    let header = &mut writer.data[..HEADER_SIZE];
+    header[0..4].copy_from_slice(&MAGIC);
+    header[4..6].copy_from_slice(&S::VERSION.to_le_bytes());
+    header[6..8].copy_from_slice(&(writer.length as u16).to_le_bytes());
+    header[8..12].copy_from_slice(&crc.finish().to_le_bytes());
+    flash::write(0, &writer.data[..end])?;
+    Ok(())
+}
+
+/// Load the settings from the user area. If the stored settings are missing, corrupt, or cannot
+/// be migrated from an older version then the default settings are returned.
+pub fn load<S: Settings>() -> S {
+    try_load().unwrap_or_default()
+}
+
+/// Load the settings from the user area, reporting why they could not be loaded.
+pub fn try_load<S: Settings>() -> Result<S, Error> {
+    let mut header = [0; HEADER_SIZE];
+    flash::read(0, &mut header)?;
+
+    if header[0..4] != MAGIC {
+        return Err(Error::Missing);
+    }
+    let version = u16::from_le_bytes([header[4], header[5]]);
+    let length = u16::from_le_bytes([header[6], header[7]]) as usize;
+    let crc = u32::from_le_bytes([header[8], header[9], header[10], header[11]]);
+
+    if length > MAX_SIZE || crc != payload_crc(length)? {
+        return Err(Error::Corrupt);
+    }
+
+    let mut reader = Reader {
+        position: 0,
+        length,
+    };
+    if version == S::VERSION {
+        S::deserialize(&mut reader)
+    } else if version < S::VERSION {
+        S::migrate(version, &mut reader)
+    } else {
+        Err(Error::UnsupportedVersion(version))
+    }
+}
+
+/// Invalidate any settings stored in the user area, so that the defaults are loaded next time.
+pub fn clear() -> Result<(), Error> {
+    flash::write(0, &[0; 4])?;
+    Ok(())
+}
+
+/// Calculate the CRC of the stored payload without reading it all into memory at once.
+fn payload_crc(length: usize) -> Result<u32, Error> {
+    let mut crc = Crc32::new();
+    let mut chunk = [0; 32];
+    let mut position = 0;
+    while position < length {
+        let chunk = &mut chunk[..(length - position).min(32)];
+        flash::read(HEADER_SIZE + position, chunk)?;
+        crc.update(chunk);
+        position += chunk.len();
+    }
+    Ok(crc.finish())
+}
+
+/// A CRC-32 (IEEE 802.3) checksum.
+struct Crc32(u32);
+
+impl Crc32 {
+    const fn new() -> Self {
+        Self(0xFFFF_FFFF)
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u32;
+            for _ in 0..8 {
+                self.0 = if self.0 & 1 != 0 {
+                    (self.0 >> 1) ^ 0xEDB8_8320
+                } else {
+                    self.0 >> 1
+                };
+            }
+        }
+    }
+
+    const fn finish(&self) -> u32 {
+        !self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct TestSettings {
+        level: u16,
+        enabled: bool,
+    }
+
+    impl Default for TestSettings {
+        fn default() -> Self {
+            Self {
+                level: 50,
+                enabled: true,
+            }
+        }
+    }
+
+    impl Settings for TestSettings {
+        const VERSION: u16 = 3;
+
+        fn serialize(&self, writer: &mut Writer) -> Result<(), Error> {
+            writer.write_u16(self.level)?;
+            writer.write_bool(self.enabled)
+        }
+
+        fn deserialize(reader: &mut Reader) -> Result<Self, Error> {
+            Ok(Self {
+                level: reader.read_u16()?,
+                enabled: reader.read_bool()?,
+            })
+        }
+
+        fn migrate(version: u16, reader: &mut Reader) -> Result<Self, Error> {
+            match version {
+                2 => Ok(Self {
+                    level: reader.read_u8()? as u16,
+                    enabled: true,
+                }),
+                _ => Err(Error::UnsupportedVersion(version)),
+            }
+        }
+    }
+
+    /// Settings that were stored by an older version of the app.
+    #[derive(Default)]
+    struct OldSettings<const VERSION: u16>(u8);
+
+    impl<const VERSION: u16> Settings for OldSettings<VERSION> {
+        const VERSION: u16 = VERSION;
+
+        fn serialize(&self, writer: &mut Writer) -> Result<(), Error> {
+            writer.write_u8(self.0)
+        }
+
+        fn deserialize(reader: &mut Reader) -> Result<Self, Error> {
+            Ok(Self(reader.read_u8()?))
+        }
+    }
+
+    #[test]
+    fn crc_matches_the_standard_check_value() {
+        let mut crc = Crc32::new();
+        crc.update(b"123456789");
+        assert_eq!(crc.finish(), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn can_save_and_load_settings() {
+        let settings = TestSettings {
+            level: 1234,
+            enabled: false,
+        };
+        save(&settings).unwrap();
+        assert_eq!(try_load::<TestSettings>(), Ok(settings));
+    }
+
+    #[test]
+    fn missing_settings_fall_back_to_default() {
+        clear().unwrap();
+        assert_eq!(try_load::<TestSettings>(), Err(Error::Missing));
+        assert_eq!(load::<TestSettings>(), TestSettings::default());
+    }
+
+    #[test]
+    fn corrupt_settings_fall_back_to_default() {
+        save(&TestSettings {
+            level: 1234,
+            enabled: false,
+        })
+        .unwrap();
+        flash::write(HEADER_SIZE, &[0xFF]).unwrap();
+
+        assert_eq!(try_load::<TestSettings>(), Err(Error::Corrupt));
+        assert_eq!(load::<TestSettings>(), TestSettings::default());
+    }
+
+    #[test]
+    fn older_settings_are_migrated() {
+        save(&OldSettings::<2>(99)).unwrap();
+        assert_eq!(
+            try_load::<TestSettings>(),
+            Ok(TestSettings {
+                level: 99,
+                enabled: true
+            })
+        );
+    }
+
+    #[test]
+    fn unsupported_versions_fall_back_to_default() {
+        save(&OldSettings::<1>(99)).unwrap();
+        assert_eq!(
+            try_load::<TestSettings>(),
+            Err(Error::UnsupportedVersion(1))
+        );
+
+        save(&OldSettings::<4>(99)).unwrap();
+        assert_eq!(
+            try_load::<TestSettings>(),
+            Err(Error::UnsupportedVersion(4))
+        );
+        assert_eq!(load::<TestSettings>(), TestSettings::default());
+    }
+
+    #[test]
+    fn reading_beyond_the_payload_is_an_error() {
+        save(&OldSettings::<3>(99)).unwrap();
+        assert_eq!(try_load::<TestSettings>(), Err(Error::Truncated));
+    }
+
+    #[test]
+    fn settings_are_restored_before_the_app_is_initialised() {
+        #[derive(Default)]
+        struct App {
+            settings: Option<TestSettings>,
+            initialised_with_settings: bool,
+        }
+
+        impl crate::hal::LaunchpadApp for App {
+            fn init_event(&mut self, _pads: crate::hal::surface::Pads) {
+                self.initialised_with_settings = self.settings.is_some();
+            }
+        }
+
+        impl Persistent for App {
+            type Settings = TestSettings;
+
+            fn restore(&mut self, settings: Self::Settings) {
+                self.settings = Some(settings);
+            }
+        }
+
+        let settings = TestSettings {
+            level: 7,
+            enabled: false,
+        };
+        save(&settings).unwrap();
+
+        let mut app = App::default();
+        crate::hal::app_init_event_with_settings(&mut app, core::ptr::null());
+        assert_eq!(app.settings, Some(settings));
+        assert!(app.initialised_with_settings);
+    }
+
+    #[test]
+    fn oversized_settings_are_rejected() {
+        let mut writer = Writer::new();
+        assert_eq!(writer.write_bytes(&[0; MAX_SIZE]), Ok(()));
+        assert_eq!(writer.write_u8(0), Err(Error::Overflow));
+    }
+
+    #[test]
+    fn failing_to_save_keeps_the_stored_settings() {
+        #[derive(Default)]
+        struct Failing;
+
+        impl Settings for Failing {
+            const VERSION: u16 = 3;
+
+            fn serialize(&self, writer: &mut Writer) -> Result<(), Error> {
+                writer.write_u16(999)?;
+                Err(Error::Invalid)
+            }
+
+            fn deserialize(_reader: &mut Reader) -> Result<Self, Error> {
+                Ok(Self)
+            }
+        }
+
+        let settings = TestSettings {
+            level: 1234,
+            enabled: false,
+        };
+        save(&settings).unwrap();
+        let stored = crate::hal::sim::flash();
+
+        assert_eq!(save(&Failing), Err(Error::Invalid));
+        assert_eq!(crate::hal::sim::flash()[..], stored[..]);
+        assert_eq!(try_load::<TestSettings>(), Ok(settings));
+    }
+}
@@ -0,0 +1,680 @@
+use {
+    crate::hal::{
+        self, flash,
+        gfx::FrameBuffer,
+        midi::{CableEvent, Message, Port},
+        surface::{Button, Led, Pads},
+        Blue, Green, Grid, LaunchpadApp, Point, Red, Rgb,
+    },
+    std::{cell::RefCell, fs, io, path::Path},
+};
+
+/// The number of 16-bit values the ADC pointer provided to `init_event` refers to.
+const ADC_SIZE: usize = 64;
+
+/// The simulated ADC values that the app reads pad pressure from. The app keeps the pointer it
+/// is given in `init_event`, so every write goes through that same pointer rather than through a
+/// reference that would invalidate it.
+pub(crate) struct Adc(*mut u16);
+
+impl Adc {
+    pub(crate) fn new() -> Self {
+        Self(Box::into_raw(Box::new([0u16; ADC_SIZE])).cast())
+    }
+
+    /// Returns the pointer provided to the app's `init_event`.
+    pub(crate) fn as_ptr(&self) -> *const u16 {
+        self.0
+    }
+
+    /// Set the raw 12-bit value at an offset, which is ignored if it is beyond the buffer.
+    pub(crate) fn set(&mut self, offset: usize, value: u16) {
+        if offset < ADC_SIZE {
+            unsafe { *self.0.add(offset) = value & 0x0FFF };
+        }
+    }
+}
+
+impl Drop for Adc {
+    fn drop(&mut self) {
+        drop(unsafe { Box::from_raw(self.0.cast::<[u16; ADC_SIZE]>()) });
+    }
+}
+
+/// The state of the simulated Launchpad Pro.
+struct Device {
+    pads: [Rgb; Grid::size() as usize],
+    setup: Rgb,
+    midi: Vec<(Port, Message)>,
+    sysex: Vec<(Port, Vec<u8>)>,
+    flash: [u8; flash::SIZE],
+    device_id: u8,
+    layout_text: bool,
+    clock: u32,
+}
+
+impl Device {
+    const fn new() -> Self {
+        Self {
+            pads: [Rgb::BLACK; Grid::size() as usize],
+            setup: Rgb::BLACK,
+            midi: Vec::new(),
+            sysex: Vec::new(),
+            flash: [0xFF; flash::SIZE],
+            device_id: 0,
+            layout_text: true,
+            clock: 0,
+        }
+    }
+
+    fn led(&mut self, t: u8, index: u8) -> Option<&mut Rgb> {
+        match t {
+            0 => self.pads.get_mut(index as usize),
+            1 => Some(&mut self.setup),
+            _ => None,
+        }
+    }
+}
+
+std::thread_local! {
+    static DEVICE: RefCell<Device> = const { RefCell::new(Device::new()) };
+}
+
+fn with_device<R>(f: impl FnOnce(&mut Device) -> R) -> R {
+    DEVICE.with(|device| f(&mut device.borrow_mut()))
+}
+
+pub(super) unsafe fn hal_plot_led(t: u8, index: u8, red: u8, green: u8, blue: u8) {
+    with_device(|device| {
+        if let Some(led) = device.led(t, index) {
+            *led = Rgb(Red(red & 0x3F), Green(green & 0x3F), Blue(blue & 0x3F));
+        }
+    });
+}
+
+pub(super) unsafe fn hal_read_led(t: u8, index: u8, red: *mut u8, green: *mut u8, blue: *mut u8) {
+    if let Some(Rgb(Red(r), Green(g), Blue(b))) =
+        with_device(|device| device.led(t, index).copied())
+    {
+        *red = r;
+        *green = g;
+        *blue = b;
+    }
+}
+
+pub(super) unsafe fn hal_send_midi(port: u8, status: u8, data1: u8, data2: u8) {
+    if let Some(port) = Port::from_u8(port) {
+        if let Some(message) = Message::new(status, (data1, data2)) {
+            with_device(|device| device.midi.push((port, message)));
+        }
+    }
+}
+
+pub(super) unsafe fn hal_send_sysex(port: u8, data: *const u8, length: u16) {
+    if let Some(port) = Port::from_u8(port) {
+        let data = core::slice::from_raw_parts(data, length as usize).to_vec();
+        with_device(|device| device.sysex.push((port, data)));
+    }
+}
+
+pub(super) unsafe fn hal_read_flash(offset: u32, data: *mut u8, length: u32) {
+    let (offset, length) = (offset as usize, length as usize);
+    if offset + length <= flash::SIZE {
+        let data = core::slice::from_raw_parts_mut(data, length);
+        with_device(|device| data.copy_from_slice(&device.flash[offset..offset + length]));
+    }
+}
+
+pub(super) unsafe fn hal_write_flash(offset: u32, data: *const u8, length: u32) {
+    let (offset, length) = (offset as usize, length as usize);
+    if offset + length <= flash::SIZE {
+        let data = core::slice::from_raw_parts(data, length);
+        with_device(|device| device.flash[offset..offset + length].copy_from_slice(data));
+    }
+}
+
+pub(super) unsafe fn hal_read_device_id() -> u8 {
+    with_device(|device| device.device_id)
+}
+
+pub(super) unsafe fn hal_read_layout_text() -> u8 {
+    with_device(|device| device.layout_text as u8)
+}
+
+pub(super) fn read_clock() -> u32 {
+    with_device(|device| device.clock)
+}
+
+pub(super) fn advance_clock() {
+    with_device(|device| device.clock = device.clock.wrapping_add(1));
+}
+
+pub(super) fn set_device_id(id: u8) {
+    with_device(|device| device.device_id = id);
+}
+
+pub(super) fn set_layout_text(enabled: bool) {
+    with_device(|device| device.layout_text = enabled);
+}
+
+/// The colours of all the LEDs on the surface at a moment in time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pads: [Rgb; Grid::size() as usize],
+    setup: Rgb,
+}
+
+impl Default for Frame {
+    /// A frame with all the LEDs turned off.
+    fn default() -> Self {
+        Self {
+            pads: [Rgb::BLACK; Grid::size() as usize],
+            setup: Rgb::BLACK,
+        }
+    }
+}
+
+impl Frame {
+    /// Returns the colour of an LED in the frame.
+    pub fn led(&self, led: impl Into<Led>) -> Rgb {
+        match led.into() {
+            Led::Pad(point) => self.pads[point.to_index() as usize],
+            Led::Setup => self.setup,
+        }
+    }
+
+    /// Set the colour of an LED in the frame.
+    pub fn set(&mut self, led: impl Into<Led>, rgb: Rgb) {
+        match led.into() {
+            Led::Pad(point) => self.pads[point.to_index() as usize] = rgb,
+            Led::Setup => self.setup = rgb,
+        }
+    }
+
+    /// Render the pads as rows of characters from the top of the grid, so that drawing can be
+    /// checked at a glance. Pads with a colour in the legend are shown by its character, other
+    /// pads that are off by `.` and the rest by `#`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use launchpad_pro_rs::hal::{sim::Frame, Point, Rgb};
+    ///
+    /// let mut frame = Frame::default();
+    /// frame.set(Point::new(0, 9), Rgb::RED);
+    /// frame.set(Point::new(1, 9), Rgb::WHITE);
+    /// assert_eq!(frame.render(&[(Rgb::RED, 'R')])[0], "R#........");
+    /// ```
+    pub fn render(&self, legend: &[(Rgb, char)]) -> Vec<String> {
+        (0..Grid::height() as i8)
+            .rev()
+            .map(|y| {
+                (0..Grid::width() as i8)
+                    .map(|x| {
+                        let rgb = self.led(Point::new(x, y));
+                        match legend.iter().find(|(colour, _)| *colour == rgb) {
+                            Some(&(_, c)) => c,
+                            None if rgb == Rgb::BLACK => '.',
+                            None => '#',
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+impl From<&FrameBuffer> for Frame {
+    /// The frame being drawn in a frame buffer, whether or not it has been flushed to the LEDs.
+    fn from(frame_buffer: &FrameBuffer) -> Self {
+        let mut frame = Self::default();
+        for point in Grid::points() {
+            frame.set(point, frame_buffer.get(point));
+        }
+        frame.set(Led::Setup, frame_buffer.get(Led::Setup));
+        frame
+    }
+}
+
+/// Simulate turning the Launchpad Pro off and on again. All the LEDs are turned off, the clock is
+/// reset and any captured MIDI and SysEx messages are discarded, but the contents of the flash
+/// memory and the device configuration are retained.
+pub fn reset() {
+    with_device(|device| {
+        device.pads = [Rgb::BLACK; Grid::size() as usize];
+        device.setup = Rgb::BLACK;
+        device.midi.clear();
+        device.sysex.clear();
+        device.clock = 0;
+    });
+    hal::record::stop();
+    hal::midi::clock::reset();
+    hal::midi::clock::master::reset();
+    hal::timer::reset();
+    hal::queue::reset();
+    hal::log::reset();
+}
+
+/// Returns the current colours of all the LEDs on the surface.
+pub fn frame() -> Frame {
+    with_device(|device| Frame {
+        pads: device.pads,
+        setup: device.setup,
+    })
+}
+
+/// Returns the MIDI messages that have been sent since the last call, in the order they were sent.
+pub fn take_midi() -> Vec<(Port, Message)> {
+    with_device(|device| core::mem::take(&mut device.midi))
+}
+
+/// Returns the SysEx messages that have been sent since the last call, in the order they were
+/// sent.
+pub fn take_sysex() -> Vec<(Port, Vec<u8>)> {
+    with_device(|device| core::mem::take(&mut device.sysex))
+}
+
+/// Returns the contents of the simulated user area of the flash memory.
+pub fn flash() -> [u8; flash::SIZE] {
+    with_device(|device| device.flash)
+}
+
+/// Replace the contents of the simulated user area of the flash memory.
+pub fn set_flash(contents: &[u8; flash::SIZE]) {
+    with_device(|device| device.flash = *contents);
+}
+
+/// Load the simulated user area of the flash memory from a file. Files shorter than the user area
+/// are padded with erased (`0xFF`) bytes.
+pub fn load_flash(path: impl AsRef<Path>) -> io::Result<()> {
+    let data = fs::read(path)?;
+    if data.len() > flash::SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "file is larger than the flash user area",
+        ));
+    }
+
+    let mut contents = [0xFF; flash::SIZE];
+    contents[..data.len()].copy_from_slice(&data);
+    set_flash(&contents);
+    Ok(())
+}
+
+/// Save the simulated user area of the flash memory to a file.
+pub fn save_flash(path: impl AsRef<Path>) -> io::Result<()> {
+    fs::write(path, flash())
+}
+
+/// Runs a [`LaunchpadApp`] against the simulated Launchpad Pro, so that its behaviour can be
+/// tested on the host.
+///
+/// The simulated device belongs to the current thread, so each test can use its own simulator.
+///
+/// # Example
+///
+/// ```
+/// use launchpad_pro_rs::hal::{
+///     sim::{self, Simulator},
+///     surface::{read_led, set_led, Button, ButtonEvent, Event},
+///     LaunchpadApp, Point, Rgb,
+/// };
+///
+/// #[derive(Default)]
+/// struct App;
+///
+/// impl LaunchpadApp for App {
+///     fn button_event(&mut self, event: ButtonEvent) {
+///         if let (Button::Pad(point), Event::Press(_)) = (event.button, event.event) {
+///             set_led(point, Rgb::RED);
+///         }
+///     }
+/// }
+///
+/// let mut simulator = Simulator::new(App, 100);
+/// simulator.press(Button::Pad(Point::new(4, 4)), 127);
+///
+/// assert_eq!(read_led(Point::new(4, 4)), Some(Rgb::RED));
+/// assert_eq!(sim::frame().led(Point::new(4, 5)), Rgb::BLACK);
+/// ```
+pub struct Simulator<A: LaunchpadApp> {
+    app: A,
+    adc: Adc,
+    elapsed: u64,
+    /// Whether the app's timers are simulated, rather than driven by recorded events.
+    pub(crate) timers: bool,
+}
+
+impl<A: LaunchpadApp> Simulator<A> {
+    /// Reset the simulated device and initialise the app on it. The app's `timer_event` will be
+    /// called every `timer_interval` milliseconds of simulated time, or never if the interval is
+    /// zero. The app can change the interval using [`hal::timer::set_interval`].
+    pub fn new(mut app: A, timer_interval: u32) -> Self {
+        reset();
+        hal::timer::set_interval(timer_interval);
+
+        let adc = Adc::new();
+        hal::app_init_event(&mut app, adc.as_ptr());
+
+        Self {
+            app,
+            adc,
+            elapsed: 0,
+            timers: true,
+        }
+    }
+
+    /// Returns a reference to the app.
+    pub fn app(&self) -> &A {
+        &self.app
+    }
+
+    /// Returns a mutable reference to the app.
+    pub fn app_mut(&mut self) -> &mut A {
+        &mut self.app
+    }
+
+    /// Returns the simulated time that has elapsed since the app was initialised, in milliseconds.
+    pub fn elapsed(&self) -> u64 {
+        self.elapsed
+    }
+
+    /// Press a button with the given velocity.
+    pub fn press(&mut self, button: Button, velocity: u8) {
+        self.surface_event(button, velocity.max(1));
+    }
+
+    /// Release a button.
+    pub fn release(&mut self, button: Button) {
+        self.surface_event(button, 0);
+    }
+
+    fn surface_event(&mut self, button: Button, value: u8) {
+        let (event, index) = match button {
+            Button::Pad(point) => (0, point.to_index()),
+            Button::Setup => (1, 0),
+        };
+        hal::app_surface_event(&mut self.app, event, index, value);
+    }
+
+    /// Report an aftertouch (pad pressure) event for the pad at the given point.
+    pub fn aftertouch(&mut self, point: Point, value: u8) {
+        hal::app_aftertouch_event(&mut self.app, point.to_index(), value);
+    }
+
+    /// Set the raw 12-bit ADC value that [`Pads::read`] returns for the pad at the given point.
+    pub fn set_pad_pressure(&mut self, point: Point, value: u16) {
+        if let Some(offset) = Pads::point_to_offset(point) {
+            self.adc.set(offset, value);
+        }
+    }
+
+    /// Receive a MIDI message on a port.
+    pub fn midi(&mut self, port: Port, message: Message) {
+        hal::app_midi_event(
+            &mut self.app,
+            port as u8,
+            message.status(),
+            message.data().0.into(),
+            message.data().1.into(),
+            hal::time::now(),
+        );
+    }
+
+    /// Receive a SysEx message on a port.
+    pub fn sysex(&mut self, port: Port, data: &[u8]) {
+        hal::app_sysex_event(&mut self.app, port as u8, data);
+    }
+
+    /// Connect or disconnect a MIDI DIN cable.
+    pub fn cable(&mut self, event: CableEvent) {
+        let (cable, value) = match event {
+            CableEvent::Connect(cable) => (cable, 1),
+            CableEvent::Disconnect(cable) => (cable, 0),
+        };
+        hal::app_cable_event(&mut self.app, cable as u8, value);
+    }
+
+    /// Advance simulated time by one millisecond, calling the app's `timer_event` if its interval
+    /// has elapsed.
+    pub fn tick(&mut self) {
+        hal::app_tick();
+        self.elapsed += 1;
+        if self.timers {
+            hal::app_software_timer_events(&mut self.app);
+            if hal::timer::interval_elapsed() {
+                self.timer_event();
+            }
+        }
+    }
+
+    /// Call the app's `timer_event` immediately.
+    pub fn timer_event(&mut self) {
+        hal::app_timer_event(&mut self.app);
+    }
+
+    /// Advance simulated time by a number of milliseconds.
+    pub fn advance(&mut self, milliseconds: u32) {
+        for _ in 0..milliseconds {
+            self.tick();
+        }
+    }
+}
+
+/// An app that ignores every event, for tests that only need the simulated device and time.
+#[cfg(test)]
+pub(crate) struct IdleApp;
+
+#[cfg(test)]
+impl LaunchpadApp for IdleApp {}
+
+#[cfg(test)]
+impl Simulator<IdleApp> {
+    /// Simulate the device running an app that ignores every event.
+    pub(crate) fn idle() -> Self {
+        Self::new(IdleApp, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::hal::{
+            midi::{send_message, send_sysex, Cable},
+            surface::{read_led, set_led, AftertouchEvent, ButtonEvent, Event},
+        },
+    };
+
+    #[derive(Default)]
+    struct EchoApp {
+        pads: Option<Pads>,
+        timer_events: u32,
+        cable_events: Vec<CableEvent>,
+    }
+
+    impl LaunchpadApp for EchoApp {
+        fn init_event(&mut self, pads: Pads) {
+            self.pads = Some(pads);
+            set_led(Led::Setup, Rgb::WHITE);
+        }
+
+        fn timer_event(&mut self) {
+            self.timer_events += 1;
+        }
+
+        fn midi_event(&mut self, port: Port, message: Message) {
+            send_message(port, message);
+        }
+
+        fn sysex_event(&mut self, port: Port, data: &[u8]) {
+            send_sysex(port, data);
+        }
+
+        fn cable_event(&mut self, cable_event: CableEvent) {
+            self.cable_events.push(cable_event);
+        }
+
+        fn button_event(&mut self, button_event: ButtonEvent) {
+            match button_event {
+                ButtonEvent {
+                    button: Button::Pad(point),
+                    event: Event::Press(velocity),
+                } => set_led(
+                    point,
+                    Rgb::new(Red::new(velocity), Green::new(0), Blue::new(0)),
+                ),
+                ButtonEvent {
+                    button: Button::Pad(point),
+                    event: Event::Release,
+                } => set_led(point, Rgb::BLACK),
+                ButtonEvent {
+                    button: Button::Setup,
+                    ..
+                } => set_led(Led::Setup, Rgb::GREEN),
+            }
+        }
+
+        fn aftertouch_event(&mut self, AftertouchEvent { point, value }: AftertouchEvent) {
+            set_led(
+                point,
+                Rgb::new(Red::new(0), Green::new(0), Blue::new(value)),
+            );
+        }
+    }
+
+    #[test]
+    fn leds_can_be_read_back() {
+        let mut simulator = Simulator::new(EchoApp::default(), 100);
+        assert_eq!(read_led(Led::Setup), Some(Rgb::WHITE));
+        assert_eq!(read_led(Point::new(3, 3)), Some(Rgb::BLACK));
+
+        simulator.press(Button::Pad(Point::new(3, 3)), 255);
+        assert_eq!(read_led(Point::new(3, 3)), Some(Rgb::RED));
+        assert_eq!(frame().led(Point::new(3, 3)), Rgb::RED);
+
+        simulator.aftertouch(Point::new(3, 3), 255);
+        assert_eq!(frame().led(Point::new(3, 3)), Rgb::BLUE);
+
+        simulator.release(Button::Pad(Point::new(3, 3)));
+        assert_eq!(frame().led(Point::new(3, 3)), Rgb::BLACK);
+
+        simulator.press(Button::Setup, 127);
+        assert_eq!(frame().led(Led::Setup), Rgb::GREEN);
+    }
+
+    #[test]
+    fn reset_turns_off_all_leds() {
+        let _simulator = Simulator::new(EchoApp::default(), 100);
+        set_led(Point::new(1, 2), Rgb::CYAN);
+
+        reset();
+        assert_eq!(frame().led(Point::new(1, 2)), Rgb::BLACK);
+        assert_eq!(frame().led(Led::Setup), Rgb::BLACK);
+    }
+
+    #[test]
+    fn outgoing_messages_are_captured() {
+        let mut simulator = Simulator::new(EchoApp::default(), 100);
+
+        simulator.midi(Port::DIN, Message::new(0x90, (60, 127)).unwrap());
+        simulator.midi(Port::USB, Message::new(0x80, (60, 0)).unwrap());
+        simulator.sysex(Port::USB, &[0xF0, 0x01, 0x02, 0xF7]);
+
+        assert_eq!(
+            take_midi(),
+            vec![
+                (Port::DIN, Message::new(0x90, (60, 127)).unwrap()),
+                (Port::USB, Message::new(0x80, (60, 0)).unwrap())
+            ]
+        );
+        assert_eq!(take_midi(), vec![]);
+        assert_eq!(
+            take_sysex(),
+            vec![(Port::USB, vec![0xF0, 0x01, 0x02, 0xF7])]
+        );
+    }
+
+    #[test]
+    fn cable_events_are_delivered() {
+        let mut simulator = Simulator::new(EchoApp::default(), 100);
+
+        simulator.cable(CableEvent::Connect(Cable::MidiIn));
+        simulator.cable(CableEvent::Disconnect(Cable::MidiOut));
+        assert_eq!(
+            simulator.app().cable_events,
+            vec![
+                CableEvent::Connect(Cable::MidiIn),
+                CableEvent::Disconnect(Cable::MidiOut)
+            ]
+        );
+    }
+
+    #[test]
+    fn timer_events_follow_simulated_time() {
+        let mut simulator = Simulator::new(EchoApp::default(), 10);
+
+        simulator.advance(9);
+        assert_eq!(simulator.app().timer_events, 0);
+
+        simulator.tick();
+        assert_eq!(simulator.app().timer_events, 1);
+
+        simulator.advance(95);
+        assert_eq!(simulator.app().timer_events, 10);
+        assert_eq!(simulator.elapsed(), 105);
+        assert_eq!(crate::hal::time::now(), 105);
+
+        let mut simulator = Simulator::new(EchoApp::default(), 0);
+        simulator.advance(1000);
+        assert_eq!(simulator.app().timer_events, 0);
+        assert_eq!(crate::hal::time::now(), 1000);
+    }
+
+    #[test]
+    fn the_timer_interval_can_be_changed_at_runtime() {
+        use crate::hal::timer;
+
+        let mut simulator = Simulator::new(EchoApp::default(), 10);
+        assert_eq!(timer::interval(), 10);
+
+        simulator.advance(15);
+        timer::set_interval(2);
+        simulator.advance(10);
+        assert_eq!(simulator.app().timer_events, 6);
+
+        timer::pause();
+        assert!(timer::is_paused());
+        simulator.advance(100);
+        assert_eq!(simulator.app().timer_events, 6);
+
+        timer::resume();
+        simulator.advance(2);
+        assert_eq!(simulator.app().timer_events, 7);
+    }
+
+    #[test]
+    fn pad_pressure_can_be_injected() {
+        let mut simulator = Simulator::new(EchoApp::default(), 100);
+
+        simulator.set_pad_pressure(Point::new(1, 2), 1234);
+        let pads = simulator.app().pads.as_ref().unwrap();
+        assert_eq!(pads.read(Point::new(1, 2)), Some(1234));
+        assert_eq!(pads.read(Point::new(2, 2)), Some(0));
+    }
+
+    #[test]
+    fn flash_can_be_saved_to_and_loaded_from_a_file() {
+        let path = std::env::temp_dir().join(format!("lp-flash-{}.bin", std::process::id()));
+
+        set_flash(&[0xFF; flash::SIZE]);
+        flash::write(0, &[1, 2, 3]).unwrap();
+        save_flash(&path).unwrap();
+
+        set_flash(&[0; flash::SIZE]);
+        load_flash(&path).unwrap();
+        assert_eq!(&flash()[..4], &[1, 2, 3, 0xFF]);
+
+        fs::remove_file(path).unwrap();
+    }
+}
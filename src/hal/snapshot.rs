@@ -0,0 +1,301 @@
+use {
+    crate::hal::{sim::Frame, surface::Led, Blue, Green, Grid, Point, Red, Rgb},
+    std::{env, fmt::Write, fs, path::Path},
+};
+
+/// Setting this environment variable to `1` causes golden files to be written rather than
+/// compared against.
+pub const BLESS_VARIABLE: &str = "LAUNCHPAD_BLESS";
+
+/// The symbol used for LEDs that are turned off.
+const OFF: char = '.';
+
+/// The symbols used for the named colours, so that they are easy to recognise.
+const NAMED: [(char, Rgb); 7] = [
+    ('R', Rgb::RED),
+    ('G', Rgb::GREEN),
+    ('B', Rgb::BLUE),
+    ('Y', Rgb::YELLOW),
+    ('C', Rgb::CYAN),
+    ('M', Rgb::MAGENTA),
+    ('W', Rgb::WHITE),
+];
+
+/// The symbols used for any other colours, in the order they are assigned. There are enough for
+/// every LED to be a different colour.
+const SYMBOLS: &str = "abcdefghijklmnopqrstuvwxyzADEFHIJKLNOPQSTUVXZ0123456789\
+                       αβγδεζηθικλμνξπρστυφχψωΓΔΘΛΞΠΣΦΨΩ!#$%&*+-=<>?@^~";
+
+/// Render a frame as a text grid. Each LED is drawn as a symbol, with the top row of the grid
+/// first, followed by a legend giving the colour of each symbol as 6-bit red, green and blue
+/// values.
+///
+/// # Example
+///
+/// ```
+/// use launchpad_pro_rs::hal::{sim::Frame, snapshot, surface::Led, Point, Rgb};
+///
+/// let mut frame = Frame::default();
+/// frame.set(Point::new(1, 9), Rgb::GREEN);
+/// frame.set(Led::Setup, Rgb::RED);
+///
+/// assert!(snapshot::render(&frame).starts_with("setup R\n9 . G . . . . . . . .\n"));
+/// ```
+pub fn render(frame: &Frame) -> String {
+    let mut legend: Vec<(char, Rgb)> = vec![(OFF, Rgb::BLACK)];
+    let mut unused = SYMBOLS.chars();
+    let mut symbol_for = |rgb: Rgb| match legend.iter().find(|(_, color)| *color == rgb) {
+        Some((symbol, _)) => *symbol,
+        None => {
+            let symbol = match NAMED.iter().find(|(_, color)| *color == rgb) {
+                Some((symbol, _)) => *symbol,
+                None => unused.next().expect("there is a symbol for every LED"),
+            };
+            legend.push((symbol, rgb));
+            symbol
+        }
+    };
+
+    let mut text = String::new();
+    let _ = writeln!(text, "setup {}", symbol_for(frame.led(Led::Setup)));
+    for y in (0..Grid::height() as i8).rev() {
+        let _ = write!(text, "{}", y);
+        for x in 0..Grid::width() as i8 {
+            let _ = write!(text, " {}", symbol_for(frame.led(Point::new(x, y))));
+        }
+        text.push('\n');
+    }
+    text.push_str("  0 1 2 3 4 5 6 7 8 9\n\n");
+
+    legend.sort_by_key(|(symbol, _)| *symbol);
+    for (symbol, Rgb(Red(red), Green(green), Blue(blue))) in legend {
+        let _ = writeln!(text, "{} {} {} {}", symbol, red, green, blue);
+    }
+    text
+}
+
+/// Parse a frame from a text grid produced by [`render`].
+pub fn parse(text: &str) -> Result<Frame, String> {
+    let mut lines = text.lines();
+    let setup = lines
+        .next()
+        .and_then(|line| line.strip_prefix("setup "))
+        .ok_or("missing setup line")?;
+    let rows: Vec<&str> = lines.by_ref().take(Grid::height() as usize).collect();
+
+    let legend: Vec<(&str, Rgb)> = lines
+        .skip_while(|line| !line.is_empty())
+        .filter(|line| !line.is_empty())
+        .map(parse_legend_entry)
+        .collect::<Result<_, _>>()?;
+    let color = |symbol: &str| {
+        legend
+            .iter()
+            .find(|(candidate, _)| *candidate == symbol)
+            .map(|(_, rgb)| *rgb)
+            .ok_or_else(|| format!("symbol {} is not in the legend", symbol))
+    };
+
+    let mut frame = Frame::default();
+    frame.set(Led::Setup, color(setup.trim())?);
+    for (row, line) in rows.iter().enumerate() {
+        let y = Grid::height() as i8 - 1 - row as i8;
+        let symbols: Vec<&str> = line.split_whitespace().skip(1).collect();
+        if symbols.len() != Grid::width() as usize {
+            return Err(format!("row {} does not have {} LEDs", y, Grid::width()));
+        }
+        for (x, symbol) in symbols.into_iter().enumerate() {
+            frame.set(Point::new(x as i8, y), color(symbol)?);
+        }
+    }
+    Ok(frame)
+}
+
+fn parse_legend_entry(line: &str) -> Result<(&str, Rgb), String> {
+    let invalid = || format!("invalid legend entry: {}", line);
+    let mut parts = line.split_whitespace();
+    let symbol = parts.next().ok_or_else(invalid)?;
+    let mut component = || -> Result<u8, String> {
+        match parts.next().and_then(|value| value.parse().ok()) {
+            Some(value) if value < 64 => Ok(value),
+            _ => Err(invalid()),
+        }
+    };
+    Ok((
+        symbol,
+        Rgb(Red(component()?), Green(component()?), Blue(component()?)),
+    ))
+}
+
+/// Describe the LEDs that differ between two frames, or return None if they are identical.
+pub fn diff(expected: &Frame, actual: &Frame) -> Option<String> {
+    let leds = Grid::points()
+        .map(Led::Pad)
+        .chain(core::iter::once(Led::Setup));
+    let differences: Vec<Led> = leds
+        .filter(|&led| expected.led(led) != actual.led(led))
+        .collect();
+    if differences.is_empty() {
+        return None;
+    }
+
+    let mut text = match differences.len() {
+        1 => "1 LED differs\n\nexpected:\n".to_string(),
+        count => format!("{} LEDs differ\n\nexpected:\n", count),
+    };
+    text.push_str(&render(expected));
+    text.push_str("\nactual:\n");
+    text.push_str(&render(actual));
+    text.push_str("\ndifferences:\n");
+    for led in differences {
+        let describe =
+            |Rgb(Red(red), Green(green), Blue(blue))| format!("{} {} {}", red, green, blue);
+        let name = match led {
+            Led::Pad(point) => format!("{},{}", point.x(), point.y()),
+            Led::Setup => "setup".to_string(),
+        };
+        let _ = writeln!(
+            text,
+            "  {}: expected {}, actual {}",
+            name,
+            describe(expected.led(led)),
+            describe(actual.led(led))
+        );
+    }
+    Some(text)
+}
+
+/// Returns whether golden files should be written rather than compared against.
+pub fn blessing() -> bool {
+    env::var(BLESS_VARIABLE).is_ok_and(|value| value == "1")
+}
+
+/// Assert that a frame matches the golden frame stored in a file, as a text grid. If the
+/// `LAUNCHPAD_BLESS` environment variable is set to `1` the file is written instead.
+///
+/// # Panics
+///
+/// Panics if the golden file cannot be read or parsed, or if the frames differ.
+///
+/// # Example
+///
+/// ```no_run
+/// use launchpad_pro_rs::hal::{sim, snapshot};
+///
+/// snapshot::assert_matches(&sim::frame(), "snapshots/start.txt");
+/// ```
+pub fn assert_matches(frame: &Frame, path: impl AsRef<Path>) {
+    let path = path.as_ref();
+    if blessing() {
+        bless(path, &render(frame));
+        return;
+    }
+
+    let expected = read_golden(path);
+    let expected = parse(&expected)
+        .unwrap_or_else(|error| panic!("failed to parse snapshot {}: {}", path.display(), error));
+    if let Some(difference) = diff(&expected, frame) {
+        panic!(
+            "snapshot {} does not match: {}\nset {}=1 to update it",
+            path.display(),
+            difference,
+            BLESS_VARIABLE
+        );
+    }
+}
+
+/// Write a golden file, creating its directory if necessary.
+pub(crate) fn bless(path: &Path, contents: &str) {
+    if let Some(directory) = path.parent() {
+        let _ = fs::create_dir_all(directory);
+    }
+    fs::write(path, contents)
+        .unwrap_or_else(|error| panic!("failed to write {}: {}", path.display(), error));
+}
+
+/// Read a golden file, explaining how to create it if it does not exist.
+pub(crate) fn read_golden(path: &Path) -> String {
+    fs::read_to_string(path).unwrap_or_else(|error| {
+        panic!(
+            "failed to read {}: {}\nset {}=1 to create it",
+            path.display(),
+            error,
+            BLESS_VARIABLE
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example_frame() -> Frame {
+        let mut frame = Frame::default();
+        frame.set(Point::new(0, 0), Rgb::WHITE);
+        frame.set(Point::new(4, 5), Rgb::GREEN);
+        frame.set(Point::new(9, 9), Rgb(Red(1), Green(2), Blue(3)));
+        frame.set(Led::Setup, Rgb(Red(1), Green(2), Blue(3)));
+        frame
+    }
+
+    #[test]
+    fn frames_are_rendered_as_a_grid_with_a_legend() {
+        assert_eq!(
+            render(&example_frame()),
+            "\
+setup a
+9 . . . . . . . . . a
+8 . . . . . . . . . .
+7 . . . . . . . . . .
+6 . . . . . . . . . .
+5 . . . . G . . . . .
+4 . . . . . . . . . .
+3 . . . . . . . . . .
+2 . . . . . . . . . .
+1 . . . . . . . . . .
+0 W . . . . . . . . .
+  0 1 2 3 4 5 6 7 8 9
+
+. 0 0 0
+G 0 63 0
+W 63 63 63
+a 1 2 3
+"
+        );
+    }
+
+    #[test]
+    fn rendered_frames_can_be_parsed() {
+        let frame = example_frame();
+        assert_eq!(parse(&render(&frame)), Ok(frame));
+
+        let mut colourful = Frame::default();
+        for point in Grid::points() {
+            let index = point.to_index();
+            colourful.set(point, Rgb(Red(index % 64), Green(index / 2), Blue(63)));
+        }
+        assert_eq!(parse(&render(&colourful)), Ok(colourful));
+    }
+
+    #[test]
+    fn invalid_snapshots_are_rejected() {
+        assert!(parse("").is_err());
+        assert!(parse(&render(&example_frame()).replace("G 0 63 0", "G 0 64 0")).is_err());
+        assert!(parse(&render(&example_frame()).replace("W . .", "W .")).is_err());
+    }
+
+    #[test]
+    fn differences_are_listed() {
+        let expected = example_frame();
+        let mut actual = example_frame();
+        assert_eq!(diff(&expected, &actual), None);
+
+        actual.set(Point::new(4, 5), Rgb::BLACK);
+        actual.set(Led::Setup, Rgb::BLUE);
+        let difference = diff(&expected, &actual).unwrap();
+        assert!(difference.starts_with("2 LEDs differ\n"));
+        assert!(difference.ends_with(
+            "differences:\n  4,5: expected 0 63 0, actual 0 0 0\n  setup: expected 1 2 3, actual 0 0 63\n"
+        ));
+    }
+}
@@ -0,0 +1,563 @@
+use {
+    crate::hal::queue,
+    core::{
+        fmt,
+        sync::atomic::{AtomicBool, AtomicU32, Ordering},
+    },
+};
+
+/// The number of timers that can be scheduled at once.
+pub const CAPACITY: usize = 16;
+
+/// Identifies a timer. The IDs are chosen by the app, so that it can tell its timers apart when
+/// they fire and cancel or reschedule them later.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TimerId(pub u8);
+
+/// The reasons a timer cannot be scheduled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// All of the timers are in use.
+    Full,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Full => write!(f, "all {} timers are in use", CAPACITY),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Timer {
+    id: TimerId,
+    /// The number of milliseconds until the timer next fires, or zero if it has finished.
+    remaining: u32,
+    /// The period of a periodic timer, or zero for a one-shot timer.
+    period: u32,
+    /// The number of times the timer has fired without being delivered to the app.
+    expired: u16,
+}
+
+/// A set of one-shot and periodic timers, counted in milliseconds.
+///
+/// # Example
+///
+/// ```
+/// use launchpad_pro_rs::hal::timer::{TimerId, Timers};
+///
+/// const BLINK: TimerId = TimerId(0);
+///
+/// let mut timers = Timers::new();
+/// timers.every(BLINK, 2).unwrap();
+///
+/// timers.tick();
+/// assert_eq!(timers.take_expired(), None);
+/// timers.tick();
+/// assert_eq!(timers.take_expired(), Some(BLINK));
+/// ```
+#[derive(Clone, Debug)]
+pub struct Timers {
+    timers: [Option<Timer>; CAPACITY],
+}
+
+impl Timers {
+    /// Construct a set of timers with none scheduled.
+    pub const fn new() -> Self {
+        Self {
+            timers: [None; CAPACITY],
+        }
+    }
+
+    /// Schedule a timer to fire once after a delay in milliseconds. A timer that is already
+    /// scheduled with the same ID is replaced. A delay of zero fires on the next millisecond.
+    pub fn once(&mut self, id: TimerId, delay: u32) -> Result<(), Error> {
+        self.schedule(id, delay, 0)
+    }
+
+    /// Schedule a timer to fire repeatedly with a period in milliseconds. A timer that is already
+    /// scheduled with the same ID is replaced. A period of zero fires every millisecond.
+    pub fn every(&mut self, id: TimerId, period: u32) -> Result<(), Error> {
+        self.schedule(id, period, period.max(1))
+    }
+
+    /// Change the time until a scheduled timer next fires, keeping its period. Returns false if
+    /// no timer is scheduled with the ID.
+    pub fn reschedule(&mut self, id: TimerId, delay: u32) -> bool {
+        match self.find(id) {
+            Some(timer) => {
+                timer.remaining = delay.max(1);
+                timer.expired = 0;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Cancel a timer, including any times it has fired that have not been taken. Returns false
+    /// if no timer is scheduled with the ID.
+    pub fn cancel(&mut self, id: TimerId) -> bool {
+        match self.timers.iter_mut().find(|slot| Self::matches(slot, id)) {
+            Some(slot) => {
+                *slot = None;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the number of milliseconds until a timer next fires, or None if it is not
+    /// scheduled.
+    pub fn remaining(&self, id: TimerId) -> Option<u32> {
+        self.timers
+            .iter()
+            .flatten()
+            .find(|timer| timer.id == id && timer.remaining > 0)
+            .map(|timer| timer.remaining)
+    }
+
+    /// Advance the timers by a millisecond.
+    pub fn tick(&mut self) {
+        for timer in self.timers.iter_mut().flatten() {
+            if timer.remaining == 0 {
+                continue;
+            }
+            timer.remaining -= 1;
+            if timer.remaining == 0 {
+                timer.expired = timer.expired.saturating_add(1);
+                timer.remaining = timer.period;
+            }
+        }
+    }
+
+    /// Returns whether any timers have fired that have not been taken.
+    pub fn has_expired(&self) -> bool {
+        self.timers.iter().flatten().any(|timer| timer.expired > 0)
+    }
+
+    /// Take the next timer that has fired, in the order the timers were scheduled. A timer that
+    /// fired several times before being taken is returned once for each time.
+    pub fn take_expired(&mut self) -> Option<TimerId> {
+        let slot = self
+            .timers
+            .iter_mut()
+            .find(|slot| matches!(slot, Some(timer) if timer.expired > 0))?;
+        let timer = slot.as_mut()?;
+        let id = timer.id;
+        timer.expired -= 1;
+        if timer.expired == 0 && timer.remaining == 0 {
+            *slot = None;
+        }
+        Some(id)
+    }
+
+    fn schedule(&mut self, id: TimerId, delay: u32, period: u32) -> Result<(), Error> {
+        let timer = Timer {
+            id,
+            remaining: delay.max(1),
+            period,
+            expired: 0,
+        };
+        let slot = match self.timers.iter().position(|slot| Self::matches(slot, id)) {
+            Some(index) => &mut self.timers[index],
+            None => self
+                .timers
+                .iter_mut()
+                .find(|slot| slot.is_none())
+                .ok_or(Error::Full)?,
+        };
+        *slot = Some(timer);
+        Ok(())
+    }
+
+    fn find(&mut self, id: TimerId) -> Option<&mut Timer> {
+        self.timers
+            .iter_mut()
+            .flatten()
+            .find(|timer| timer.id == id)
+    }
+
+    fn matches(slot: &Option<Timer>, id: TimerId) -> bool {
+        matches!(slot, Some(timer) if timer.id == id)
+    }
+}
+
+impl Default for Timers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Counts down the interval between calls to the app's `timer_event`. It is counted from the 1 ms
+/// tick and changed by the app, so it is kept in atomics rather than behind a lock.
+#[derive(Debug)]
+struct Interval {
+    /// The interval in milliseconds, or zero if `timer_event` is never called.
+    interval: AtomicU32,
+    /// The number of milliseconds since `timer_event` was last called.
+    elapsed: AtomicU32,
+    paused: AtomicBool,
+}
+
+impl Interval {
+    const fn new(interval: u32) -> Self {
+        Self {
+            interval: AtomicU32::new(interval),
+            elapsed: AtomicU32::new(0),
+            paused: AtomicBool::new(false),
+        }
+    }
+
+    fn get(&self) -> u32 {
+        self.interval.load(Ordering::Relaxed)
+    }
+
+    fn set(&self, interval: u32) {
+        self.interval.store(interval, Ordering::Relaxed);
+        self.elapsed.store(0, Ordering::Relaxed);
+    }
+
+    fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Relaxed);
+    }
+
+    /// Advance by a millisecond, returning whether `timer_event` is due.
+    fn tick(&self) -> bool {
+        let interval = self.get();
+        if self.is_paused() || interval == 0 {
+            return false;
+        }
+        let elapsed = self.elapsed.load(Ordering::Relaxed) + 1;
+        let due = elapsed >= interval;
+        self.elapsed
+            .store(if due { 0 } else { elapsed }, Ordering::Relaxed);
+        due
+    }
+}
+
+#[cfg(target_arch = "arm")]
+static TIMERS: crate::hal::Mutex<Timers> = crate::hal::Mutex::new(Timers::new());
+
+#[cfg(target_arch = "arm")]
+fn with_timers<R>(f: impl FnOnce(&mut Timers) -> R) -> R {
+    f(&mut TIMERS.lock())
+}
+
+#[cfg(target_arch = "arm")]
+fn try_with_timers<R>(f: impl FnOnce(&mut Timers) -> R) -> Option<R> {
+    TIMERS.try_lock().map(|mut timers| f(&mut timers))
+}
+
+/// The milliseconds that the tick could not count because the timers were in use.
+#[cfg(target_arch = "arm")]
+static MISSED_TICKS: AtomicU32 = AtomicU32::new(0);
+
+#[cfg(target_arch = "arm")]
+fn with_missed_ticks<R>(f: impl FnOnce(&AtomicU32) -> R) -> R {
+    f(&MISSED_TICKS)
+}
+
+#[cfg(target_arch = "arm")]
+static INTERVAL: Interval = Interval::new(0);
+
+#[cfg(target_arch = "arm")]
+fn with_interval<R>(f: impl FnOnce(&Interval) -> R) -> R {
+    f(&INTERVAL)
+}
+
+#[cfg(not(target_arch = "arm"))]
+std::thread_local! {
+    static TIMERS: core::cell::RefCell<Timers> = const { core::cell::RefCell::new(Timers::new()) };
+}
+
+#[cfg(not(target_arch = "arm"))]
+fn with_timers<R>(f: impl FnOnce(&mut Timers) -> R) -> R {
+    TIMERS.with(|timers| f(&mut timers.borrow_mut()))
+}
+
+#[cfg(not(target_arch = "arm"))]
+fn try_with_timers<R>(f: impl FnOnce(&mut Timers) -> R) -> Option<R> {
+    TIMERS.with(|timers| Some(f(&mut *timers.try_borrow_mut().ok()?)))
+}
+
+#[cfg(not(target_arch = "arm"))]
+std::thread_local! {
+    static MISSED_TICKS: AtomicU32 = const { AtomicU32::new(0) };
+}
+
+#[cfg(not(target_arch = "arm"))]
+fn with_missed_ticks<R>(f: impl FnOnce(&AtomicU32) -> R) -> R {
+    MISSED_TICKS.with(|missed| f(missed))
+}
+
+#[cfg(not(target_arch = "arm"))]
+std::thread_local! {
+    static INTERVAL: Interval = const { Interval::new(0) };
+}
+
+#[cfg(not(target_arch = "arm"))]
+fn with_interval<R>(f: impl FnOnce(&Interval) -> R) -> R {
+    INTERVAL.with(|interval| f(interval))
+}
+
+/// Returns the interval in milliseconds between calls to the app's `timer_event`, or zero if it
+/// is never called. The interval starts as the one given to `launchpad_app!`.
+pub fn interval() -> u32 {
+    with_interval(Interval::get)
+}
+
+/// Change the interval in milliseconds between calls to the app's `timer_event`, or stop it
+/// being called with an interval of zero. The next call comes a full interval after this one.
+///
+/// # Example
+///
+/// ```
+/// use launchpad_pro_rs::hal::timer;
+///
+/// // speed up an animation
+/// timer::set_interval(timer::interval() / 2);
+/// ```
+pub fn set_interval(interval: u32) {
+    with_interval(|state| state.set(interval));
+}
+
+/// Stop calling the app's `timer_event` until [`resume`] is called.
+pub fn pause() {
+    with_interval(|interval| interval.set_paused(true));
+}
+
+/// Resume calling the app's `timer_event` after [`pause`]. The time that had elapsed towards the
+/// next call before pausing is kept.
+pub fn resume() {
+    with_interval(|interval| interval.set_paused(false));
+}
+
+/// Returns whether calls to the app's `timer_event` are paused.
+pub fn is_paused() -> bool {
+    with_interval(Interval::is_paused)
+}
+
+/// Advance the interval by a millisecond, returning whether the app's `timer_event` is due.
+#[doc(hidden)]
+pub fn interval_elapsed() -> bool {
+    with_interval(Interval::tick)
+}
+
+/// Schedule a timer to fire once after a delay in milliseconds. When it fires the app's
+/// `software_timer_event` is called with its ID. A timer that is already scheduled with the same
+/// ID is replaced.
+///
+/// # Example
+///
+/// ```
+/// use launchpad_pro_rs::hal::timer::{self, TimerId};
+///
+/// const FLASH_OFF: TimerId = TimerId(1);
+///
+/// timer::once(FLASH_OFF, 250).unwrap();
+/// ```
+pub fn once(id: TimerId, delay: u32) -> Result<(), Error> {
+    with_timers(|timers| timers.once(id, delay))
+}
+
+/// Schedule a timer to fire repeatedly with a period in milliseconds. Each time it fires the
+/// app's `software_timer_event` is called with its ID. A timer that is already scheduled with the
+/// same ID is replaced.
+pub fn every(id: TimerId, period: u32) -> Result<(), Error> {
+    with_timers(|timers| timers.every(id, period))
+}
+
+/// Change the time until a scheduled timer next fires, keeping its period. Returns false if no
+/// timer is scheduled with the ID.
+pub fn reschedule(id: TimerId, delay: u32) -> bool {
+    with_timers(|timers| timers.reschedule(id, delay))
+}
+
+/// Cancel a timer. Returns false if no timer is scheduled with the ID.
+pub fn cancel(id: TimerId) -> bool {
+    with_timers(|timers| timers.cancel(id))
+}
+
+/// Returns the number of milliseconds until a timer next fires, or None if it is not scheduled.
+pub fn remaining(id: TimerId) -> Option<u32> {
+    with_timers(|timers| timers.remaining(id))
+}
+
+/// Advance the app's timers by a millisecond. This is called from the 1 ms tick, which can
+/// interrupt the app while it is using the timers, so rather than wait for them the millisecond
+/// is counted on the next tick.
+pub(crate) fn tick() {
+    let ticks = with_missed_ticks(|missed| missed.swap(0, Ordering::Relaxed)) + 1;
+    let expired = try_with_timers(|timers| {
+        for _ in 0..ticks {
+            timers.tick();
+        }
+        timers.has_expired()
+    });
+    match expired {
+        Some(true) => queue::push_software_timer_event(),
+        Some(false) => {}
+        None => {
+            with_missed_ticks(|missed| missed.fetch_add(ticks, Ordering::Relaxed));
+        }
+    }
+}
+
+/// Take the next of the app's timers that has fired. If the timers are in use then they are
+/// taken on the next dispatch instead.
+pub(crate) fn take_expired() -> Option<TimerId> {
+    try_with_timers(Timers::take_expired).unwrap_or_else(|| {
+        queue::push_software_timer_event();
+        None
+    })
+}
+
+/// Cancel all of the app's timers, and stop calling its `timer_event`.
+#[cfg(not(target_arch = "arm"))]
+pub(crate) fn reset() {
+    with_timers(|timers| *timers = Timers::new());
+    with_missed_ticks(|missed| missed.store(0, Ordering::Relaxed));
+    with_interval(|interval| {
+        interval.set(0);
+        interval.set_paused(false);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, std::vec::Vec};
+
+    fn run(timers: &mut Timers, milliseconds: u32) -> Vec<(u32, TimerId)> {
+        let mut fired = Vec::new();
+        for time in 1..=milliseconds {
+            timers.tick();
+            while let Some(id) = timers.take_expired() {
+                fired.push((time, id));
+            }
+        }
+        fired
+    }
+
+    #[test]
+    fn one_shot_timers_fire_once() {
+        let mut timers = Timers::new();
+        timers.once(TimerId(7), 3).unwrap();
+        assert_eq!(timers.remaining(TimerId(7)), Some(3));
+        assert_eq!(run(&mut timers, 10), [(3, TimerId(7))]);
+        assert_eq!(timers.remaining(TimerId(7)), None);
+        assert!(!timers.cancel(TimerId(7)));
+    }
+
+    #[test]
+    fn periodic_timers_fire_repeatedly() {
+        let mut timers = Timers::new();
+        timers.every(TimerId(1), 4).unwrap();
+        timers.every(TimerId(2), 3).unwrap();
+        assert_eq!(
+            run(&mut timers, 12),
+            [
+                (3, TimerId(2)),
+                (4, TimerId(1)),
+                (6, TimerId(2)),
+                (8, TimerId(1)),
+                (9, TimerId(2)),
+                (12, TimerId(1)),
+                (12, TimerId(2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn timers_can_be_cancelled() {
+        let mut timers = Timers::new();
+        timers.every(TimerId(1), 2).unwrap();
+        assert_eq!(run(&mut timers, 2), [(2, TimerId(1))]);
+        assert!(timers.cancel(TimerId(1)));
+        assert_eq!(run(&mut timers, 10), []);
+    }
+
+    #[test]
+    fn timers_can_be_rescheduled() {
+        let mut timers = Timers::new();
+        timers.every(TimerId(1), 5).unwrap();
+        run(&mut timers, 3);
+        assert!(timers.reschedule(TimerId(1), 1));
+        assert_eq!(run(&mut timers, 6), [(1, TimerId(1)), (6, TimerId(1))]);
+        assert!(!timers.reschedule(TimerId(2), 1));
+
+        // scheduling a timer with the same ID replaces it
+        timers.once(TimerId(1), 2).unwrap();
+        assert_eq!(run(&mut timers, 10), [(2, TimerId(1))]);
+    }
+
+    #[test]
+    fn timers_that_fire_before_being_taken_are_not_lost() {
+        let mut timers = Timers::new();
+        timers.every(TimerId(1), 1).unwrap();
+        timers.once(TimerId(2), 2).unwrap();
+        for _ in 0..3 {
+            timers.tick();
+        }
+        assert!(timers.has_expired());
+
+        let mut fired = Vec::new();
+        while let Some(id) = timers.take_expired() {
+            fired.push(id);
+        }
+        assert_eq!(fired, [TimerId(1), TimerId(1), TimerId(1), TimerId(2)]);
+        assert!(!timers.has_expired());
+    }
+
+    #[test]
+    fn the_interval_can_be_changed_and_paused() {
+        let interval = Interval::new(3);
+        let due = || (1..=12).filter(|_| interval.tick()).count();
+        assert_eq!(due(), 4);
+
+        let interval = Interval::new(3);
+        interval.tick();
+        interval.set(5);
+        assert_eq!((1..=5).filter(|_| interval.tick()).count(), 1);
+
+        interval.tick();
+        interval.set_paused(true);
+        assert!(!(1..=10).any(|_| interval.tick()));
+        interval.set_paused(false);
+        assert_eq!((1..=4).position(|_| interval.tick()), Some(3));
+
+        interval.set(0);
+        assert!(!(1..=10).any(|_| interval.tick()));
+    }
+
+    #[test]
+    fn ticks_are_not_lost_while_the_timers_are_in_use() {
+        reset();
+        once(TimerId(3), 2).unwrap();
+
+        // the tick interrupts the app while it is scheduling a timer
+        with_timers(|_| tick());
+        assert_eq!(remaining(TimerId(3)), Some(2));
+        tick();
+        assert_eq!(take_expired(), Some(TimerId(3)));
+        assert_eq!(take_expired(), None);
+    }
+
+    #[test]
+    fn the_number_of_timers_is_limited() {
+        let mut timers = Timers::new();
+        for id in 0..CAPACITY as u8 {
+            timers.once(TimerId(id), 1).unwrap();
+        }
+        assert_eq!(timers.once(TimerId(100), 1), Err(Error::Full));
+        assert_eq!(timers.once(TimerId(0), 5), Ok(()));
+
+        // one-shot timers free their slot once they have been taken
+        run(&mut timers, 1);
+        assert_eq!(timers.every(TimerId(100), 1), Ok(()));
+    }
+}